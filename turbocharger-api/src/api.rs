@@ -0,0 +1,361 @@
+//! The functions and types of a `backend_api.rs`, as the server dispatches them.
+
+use std::collections::BTreeMap;
+use syn::{parse::ParseStream, punctuated::Punctuated, Token};
+
+/// The functions and types of a `backend_api.rs`.
+#[derive(Default)]
+pub(crate) struct Api {
+	/// By dispatch name, with one entry for each instantiation of a generic function, e.g. `first_page<i64>`.
+	pub(crate) fns: BTreeMap<String, Function>,
+	/// Structs and enums, by path, e.g. `api::Person`.
+	pub(crate) types: BTreeMap<String, syn::Item>,
+}
+
+pub(crate) struct Function {
+	/// The modules it's in, e.g. `["arithmetic"]`.
+	pub(crate) path: Vec<String>,
+	/// The type of its `impl` block, if it's a method.
+	pub(crate) service: Option<String>,
+	pub(crate) attrs: Vec<syn::Attribute>,
+	pub(crate) sig: syn::Signature,
+	/// The type arguments of an instantiation of a generic function, e.g. `T = i64`.
+	pub(crate) type_args: Vec<(syn::Ident, syn::Type)>,
+}
+
+impl Api {
+	pub(crate) fn parse(source: &str) -> syn::Result<Self> {
+		let mut api = Self::default();
+		api.add_items(&syn::parse_file(source)?.items, &[], None)?;
+		Ok(api)
+	}
+
+	/// `namespace` is the `#[backend(name = "...")]` prefix of the enclosing `mod` or `impl` block, if any.
+	fn add_items(
+		&mut self,
+		items: &[syn::Item],
+		path: &[String],
+		namespace: Option<&str>,
+	) -> syn::Result<()> {
+		for item in items {
+			match item {
+				syn::Item::Fn(f) => self.add_fn(&f.attrs, &f.sig, path, namespace, None)?,
+				syn::Item::Impl(i) => {
+					let ident = match &*i.self_ty {
+						syn::Type::Path(ty) => ty.path.segments.last().unwrap().ident.to_string(),
+						_ => continue,
+					};
+					let namespace = namespace_for(&i.attrs, namespace, &ident)?;
+					for impl_item in &i.items {
+						if let syn::ImplItem::Fn(method) = impl_item {
+							self.add_fn(&method.attrs, &method.sig, path, namespace.as_deref(), Some(&ident))?;
+						}
+					}
+				}
+				syn::Item::Mod(m) => {
+					let ident = m.ident.to_string();
+					let namespace = namespace_for(&m.attrs, namespace, &ident)?;
+					let path = [path, &[ident]].concat();
+					let items = m.content.as_ref().map_or(&[][..], |(_, items)| items);
+					self.add_items(items, &path, namespace.as_deref())?;
+				}
+				syn::Item::Struct(syn::ItemStruct { ident, .. })
+				| syn::Item::Enum(syn::ItemEnum { ident, .. }) => {
+					self.types.insert(join(path, &ident.to_string()), item.clone());
+				}
+				_ => (),
+			}
+		}
+		Ok(())
+	}
+
+	/// Adds a function under its dispatch name, like `#[backend]` assigns them; `service` is its `impl` block's type.
+	fn add_fn(
+		&mut self,
+		attrs: &[syn::Attribute],
+		sig: &syn::Signature,
+		path: &[String],
+		namespace: Option<&str>,
+		service: Option<&str>,
+	) -> syn::Result<()> {
+		let args = ApiArgs::from_attrs(attrs)?;
+		let ident = sig.ident.to_string();
+		let name = match (args.name, namespace, service) {
+			(Some(name), _, _) => name,
+			(None, Some(namespace), _) => format!("{}::{}", namespace, ident),
+			(None, None, Some(service)) => join(path, &format!("{}::{}", service, ident)),
+			(None, None, None) => join(path, &ident),
+		};
+
+		let attrs: Vec<_> = attrs.iter().filter(|attr| attr.path().is_ident("doc")).cloned().collect();
+		if args.instantiate.is_empty() {
+			let function = Function {
+				path: path.to_vec(),
+				service: service.map(String::from),
+				attrs,
+				sig: sig.clone(),
+				type_args: vec![],
+			};
+			return self.insert_fn(name, function, sig);
+		}
+		for assignments in args.instantiate {
+			let tys: Vec<_> = sig
+				.generics
+				.type_params()
+				.map(|param| {
+					let ty = assignments.iter().find(|(ident, _)| *ident == param.ident).map(|(_, ty)| ty);
+					quote::quote!(#ty).to_string().replace(' ', "")
+				})
+				.collect();
+			let function = Function {
+				path: path.to_vec(),
+				service: service.map(String::from),
+				attrs: attrs.clone(),
+				sig: sig.clone(),
+				type_args: assignments,
+			};
+			self.insert_fn(format!("{}<{}>", name, tys.join(",")), function, sig)?;
+		}
+		Ok(())
+	}
+
+	/// Fails if another function has the dispatch name `name`, since clients couldn't tell them apart.
+	fn insert_fn(
+		&mut self,
+		name: String,
+		function: Function,
+		sig: &syn::Signature,
+	) -> syn::Result<()> {
+		if self.fns.contains_key(&name) {
+			return Err(syn::Error::new_spanned(
+				&sig.ident,
+				format!("more than one function has the dispatch name `{}`", name),
+			));
+		}
+		self.fns.insert(name, function);
+		Ok(())
+	}
+
+	/// The key in `types` of the struct or enum at `path`, which may be written without its module path if that's
+	/// unambiguous.
+	pub(crate) fn type_path(&self, path: &syn::Path) -> Option<String> {
+		let path =
+			path.segments.iter().map(|segment| segment.ident.to_string()).collect::<Vec<_>>().join("::");
+		if self.types.contains_key(&path) {
+			return Some(path);
+		}
+		let mut matches = self.types.keys().filter(|key| last_segment(key) == last_segment(&path));
+		match (matches.next(), matches.next()) {
+			(Some(key), None) => Some(key.clone()),
+			_ => None,
+		}
+	}
+}
+
+/// The `name` and `instantiate(...)` arguments of `#[backend]` attributes in `backend_api.rs`.
+#[derive(Default)]
+struct ApiArgs {
+	name: Option<String>,
+	instantiate: Vec<Vec<(syn::Ident, syn::Type)>>,
+}
+
+impl ApiArgs {
+	fn from_attrs(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+		let mut args = Self::default();
+		for attr in attrs.iter().filter(|attr| attr.path().is_ident("backend")) {
+			attr.parse_nested_meta(|meta| {
+				if meta.path.is_ident("name") {
+					args.name = Some(meta.value()?.parse::<syn::LitStr>()?.value());
+				} else if meta.path.is_ident("instantiate") {
+					let content;
+					syn::parenthesized!(content in meta.input);
+					let instantiations = Punctuated::<Instantiation, Token![,]>::parse_terminated(&content)?;
+					args.instantiate.extend(instantiations.into_iter().map(|instantiation| instantiation.0));
+				} else {
+					return Err(meta.error("Unknown #[backend] argument."));
+				}
+				Ok(())
+			})?;
+		}
+		Ok(args)
+	}
+}
+
+/// `T = User`, or `(K = String, V = User)` for functions with several type parameters.
+struct Instantiation(Vec<(syn::Ident, syn::Type)>);
+
+impl syn::parse::Parse for Instantiation {
+	fn parse(input: ParseStream) -> syn::Result<Self> {
+		fn assignment(input: ParseStream) -> syn::Result<(syn::Ident, syn::Type)> {
+			let ident = input.parse()?;
+			let _: Token![=] = input.parse()?;
+			Ok((ident, input.parse()?))
+		}
+
+		if input.peek(syn::token::Paren) {
+			let content;
+			syn::parenthesized!(content in input);
+			Ok(Self(content.parse_terminated(assignment, Token![,])?.into_iter().collect()))
+		} else {
+			Ok(Self(vec![assignment(input)?]))
+		}
+	}
+}
+
+/// The `#[backend(name = "...")]` prefix for the items of a `mod` or `impl` block named `ident`.
+fn namespace_for(
+	attrs: &[syn::Attribute],
+	namespace: Option<&str>,
+	ident: &str,
+) -> syn::Result<Option<String>> {
+	Ok(match (ApiArgs::from_attrs(attrs)?.name, namespace) {
+		(Some(name), _) => Some(name),
+		(None, Some(namespace)) => Some(format!("{}::{}", namespace, ident)),
+		(None, None) => None,
+	})
+}
+
+/// `tokens` as written, e.g. `Result<i32, tracked::StringError>`.
+pub(crate) fn type_string(tokens: &impl quote::ToTokens) -> String {
+	tokens
+		.to_token_stream()
+		.to_string()
+		.replace(" < ", "<")
+		.replace("< ", "<")
+		.replace(" >", ">")
+		.replace(" :: ", "::")
+		.replace(" ,", ",")
+		.replace("& ", "&")
+}
+
+/// `name` in the module at `path`, e.g. `arithmetic::add`.
+fn join(path: &[String], name: &str) -> String {
+	path.iter().map(String::as_str).chain([name]).collect::<Vec<_>>().join("::")
+}
+
+pub(crate) fn last_segment(path: &str) -> &str {
+	path.rsplit("::").next().unwrap()
+}
+
+pub(crate) fn path_type_args(segment: &syn::PathSegment) -> Vec<&syn::Type> {
+	match &segment.arguments {
+		syn::PathArguments::AngleBracketed(args) => args
+			.args
+			.iter()
+			.filter_map(|arg| match arg {
+				syn::GenericArgument::Type(ty) => Some(ty),
+				_ => None,
+			})
+			.collect(),
+		_ => vec![],
+	}
+}
+
+/// `Item` of `impl Stream<Item = ...>`.
+pub(crate) fn stream_item(ty: &syn::Type) -> Option<syn::Type> {
+	let bounds = match ty {
+		syn::Type::ImplTrait(impl_trait) => &impl_trait.bounds,
+		_ => return None,
+	};
+	bounds.iter().find_map(|bound| match bound {
+		syn::TypeParamBound::Trait(bound) => {
+			let segment = bound.path.segments.last()?;
+			match &segment.arguments {
+				syn::PathArguments::AngleBracketed(args) if segment.ident == "Stream" => {
+					args.args.iter().find_map(|arg| match arg {
+						syn::GenericArgument::AssocType(assoc) if assoc.ident == "Item" => Some(assoc.ty.clone()),
+						_ => None,
+					})
+				}
+				_ => None,
+			}
+		}
+		_ => None,
+	})
+}
+
+/// `(T, Some(E))` for `Result<T, E>`, or else `(ty, None)`.
+pub(crate) fn split_result(ty: &syn::Type) -> (syn::Type, Option<syn::Type>) {
+	if let syn::Type::Path(syn::TypePath { qself: None, path }) = ty {
+		let segment = path.segments.last().unwrap();
+		if let ("Result", [t, e]) =
+			(segment.ident.to_string().as_str(), path_type_args(segment).as_slice())
+		{
+			return ((*t).clone(), Some((*e).clone()));
+		}
+	}
+	(ty.clone(), None)
+}
+
+/// `ty` with the type parameters in `type_args` replaced by their arguments.
+pub(crate) fn substitute(ty: &syn::Type, type_args: &[(syn::Ident, syn::Type)]) -> syn::Type {
+	fn replace(
+		tokens: proc_macro2::TokenStream,
+		type_args: &[(syn::Ident, syn::Type)],
+	) -> proc_macro2::TokenStream {
+		tokens
+			.into_iter()
+			.map(|token| match token {
+				proc_macro2::TokenTree::Ident(ident) => {
+					match type_args.iter().find(|(param, _)| *param == ident) {
+						Some((_, ty)) => quote::quote!(#ty),
+						None => quote::quote!(#ident),
+					}
+				}
+				proc_macro2::TokenTree::Group(group) => {
+					let replaced = proc_macro2::Group::new(group.delimiter(), replace(group.stream(), type_args));
+					quote::quote!(#replaced)
+				}
+				token => quote::quote!(#token),
+			})
+			.collect()
+	}
+
+	if type_args.is_empty() {
+		return ty.clone();
+	}
+	syn::parse2(replace(quote::quote!(#ty), type_args)).unwrap()
+}
+
+/// The doc comment in `attrs`.
+pub(crate) fn docs(attrs: &[syn::Attribute]) -> String {
+	let lines: Vec<_> = attrs
+		.iter()
+		.filter_map(|attr| match &attr.meta {
+			syn::Meta::NameValue(syn::MetaNameValue {
+				path,
+				value: syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(doc), .. }),
+				..
+			})
+				if path.is_ident("doc") =>
+			{
+				Some(doc.value())
+			}
+			_ => None,
+		})
+		.collect();
+	lines.iter().map(|line| line.strip_prefix(' ').unwrap_or(line)).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_fn_names() {
+		let api = Api::parse(
+			"async fn run() {} mod app { async fn run() {} impl Tally { async fn add(&mut self) {} } }
+			#[backend(name = \"people\")] mod api { async fn get() {} }
+			#[backend(instantiate(T = i64, T = String))] async fn page<T>(fill: T) {}",
+		)
+		.unwrap();
+		assert_eq!(
+			api.fns.keys().collect::<Vec<_>>(),
+			["app::Tally::add", "app::run", "page<String>", "page<i64>", "people::get", "run"]
+		);
+
+		let e = Api::parse("mod app { async fn run() {} } mod app { async fn run() {} }").err().unwrap();
+		assert_eq!(e.to_string(), "more than one function has the dispatch name `app::run`");
+		assert!(Api::parse("#[backend(name = \"run\")] async fn a() {} async fn run() {}").is_err());
+	}
+}
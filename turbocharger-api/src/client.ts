@@ -0,0 +1,499 @@
+// Connection to a Turbocharger server, and the bincode encoding of requests and responses.
+
+export type Encoder<T> = (w: Writer, v: T) => void;
+export type Decoder<T> = (r: Reader) => T;
+export type Result<T, E> = { Ok: T } | { Err: E };
+
+const textEncoder = new TextEncoder();
+const textDecoder = new TextDecoder("utf-8", { fatal: true });
+
+export class Writer {
+	private bytes = new Uint8Array(256);
+	private view = new DataView(this.bytes.buffer);
+	private length = 0;
+
+	/** Makes room for `n` more bytes, and returns where they start. */
+	private reserve(n: number): number {
+		if (this.length + n > this.bytes.length) {
+			const bytes = new Uint8Array(Math.max(this.bytes.length * 2, this.length + n));
+			bytes.set(this.bytes);
+			this.bytes = bytes;
+			this.view = new DataView(bytes.buffer);
+		}
+		this.length += n;
+		return this.length - n;
+	}
+
+	finish(): Uint8Array {
+		return this.bytes.slice(0, this.length);
+	}
+
+	raw(bytes: Uint8Array) {
+		this.bytes.set(bytes, this.reserve(bytes.length));
+	}
+	u8(v: number) {
+		this.view.setUint8(this.reserve(1), v);
+	}
+	i8(v: number) {
+		this.view.setInt8(this.reserve(1), v);
+	}
+	u16(v: number) {
+		this.view.setUint16(this.reserve(2), v, true);
+	}
+	i16(v: number) {
+		this.view.setInt16(this.reserve(2), v, true);
+	}
+	u32(v: number) {
+		this.view.setUint32(this.reserve(4), v, true);
+	}
+	i32(v: number) {
+		this.view.setInt32(this.reserve(4), v, true);
+	}
+	u64(v: bigint) {
+		this.view.setBigUint64(this.reserve(8), v, true);
+	}
+	i64(v: bigint) {
+		this.view.setBigInt64(this.reserve(8), v, true);
+	}
+	u128(v: bigint) {
+		this.u64(BigInt.asUintN(64, v));
+		this.u64(BigInt.asUintN(64, v >> 64n));
+	}
+	i128(v: bigint) {
+		this.u64(BigInt.asUintN(64, v));
+		this.i64(BigInt.asIntN(64, v >> 64n));
+	}
+	f32(v: number) {
+		this.view.setFloat32(this.reserve(4), v, true);
+	}
+	f64(v: number) {
+		this.view.setFloat64(this.reserve(8), v, true);
+	}
+	bool(v: boolean) {
+		this.u8(v ? 1 : 0);
+	}
+	string(v: string) {
+		const bytes = textEncoder.encode(v);
+		this.u64(BigInt(bytes.length));
+		this.raw(bytes);
+	}
+	/** A `char` is its UTF-8 encoding, without a length. */
+	char(v: string) {
+		this.raw(textEncoder.encode(v));
+	}
+}
+
+export class Reader {
+	private view: DataView;
+	private offset = 0;
+
+	constructor(private bytes: Uint8Array) {
+		this.view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+	}
+
+	/** Skips `n` bytes, and returns where they start. */
+	private advance(n: number): number {
+		if (this.offset + n > this.bytes.length) throw new Error("turbocharger: unexpected end of response");
+		this.offset += n;
+		return this.offset - n;
+	}
+
+	rest(): Uint8Array {
+		return this.bytes.subarray(this.advance(this.bytes.length - this.offset));
+	}
+	u8(): number {
+		return this.view.getUint8(this.advance(1));
+	}
+	i8(): number {
+		return this.view.getInt8(this.advance(1));
+	}
+	u16(): number {
+		return this.view.getUint16(this.advance(2), true);
+	}
+	i16(): number {
+		return this.view.getInt16(this.advance(2), true);
+	}
+	u32(): number {
+		return this.view.getUint32(this.advance(4), true);
+	}
+	i32(): number {
+		return this.view.getInt32(this.advance(4), true);
+	}
+	u64(): bigint {
+		return this.view.getBigUint64(this.advance(8), true);
+	}
+	i64(): bigint {
+		return this.view.getBigInt64(this.advance(8), true);
+	}
+	u128(): bigint {
+		const low = this.u64();
+		return (this.u64() << 64n) | low;
+	}
+	i128(): bigint {
+		const low = this.u64();
+		return (this.i64() << 64n) | low;
+	}
+	f32(): number {
+		return this.view.getFloat32(this.advance(4), true);
+	}
+	f64(): number {
+		return this.view.getFloat64(this.advance(8), true);
+	}
+	bool(): boolean {
+		const v = this.u8();
+		if (v > 1) throw new Error(`turbocharger: invalid bool ${v}`);
+		return v === 1;
+	}
+	string(): string {
+		const n = Number(this.u64());
+		const offset = this.advance(n);
+		return textDecoder.decode(this.bytes.subarray(offset, offset + n));
+	}
+	char(): string {
+		const first = this.bytes[this.offset];
+		const n = first < 0x80 ? 1 : first < 0xe0 ? 2 : first < 0xf0 ? 3 : 4;
+		const offset = this.advance(n);
+		return textDecoder.decode(this.bytes.subarray(offset, offset + n));
+	}
+}
+
+export const encode = {
+	u8: (w: Writer, v: number) => w.u8(v),
+	i8: (w: Writer, v: number) => w.i8(v),
+	u16: (w: Writer, v: number) => w.u16(v),
+	i16: (w: Writer, v: number) => w.i16(v),
+	u32: (w: Writer, v: number) => w.u32(v),
+	i32: (w: Writer, v: number) => w.i32(v),
+	u64: (w: Writer, v: bigint) => w.u64(v),
+	i64: (w: Writer, v: bigint) => w.i64(v),
+	u128: (w: Writer, v: bigint) => w.u128(v),
+	i128: (w: Writer, v: bigint) => w.i128(v),
+	f32: (w: Writer, v: number) => w.f32(v),
+	f64: (w: Writer, v: number) => w.f64(v),
+	bool: (w: Writer, v: boolean) => w.bool(v),
+	string: (w: Writer, v: string) => w.string(v),
+	char: (w: Writer, v: string) => w.char(v),
+	unit: (_w: Writer, _v: null) => {},
+	option:
+		<T>(some: Encoder<T>): Encoder<T | undefined> =>
+		(w, v) => {
+			if (v === undefined) {
+				w.u8(0);
+			} else {
+				w.u8(1);
+				some(w, v);
+			}
+		},
+	seq:
+		<T>(item: Encoder<T>): Encoder<T[]> =>
+		(w, v) => {
+			w.u64(BigInt(v.length));
+			for (const x of v) item(w, x);
+		},
+	/** A fixed-size array, which has no length. */
+	array:
+		<T>(item: Encoder<T>): Encoder<T[]> =>
+		(w, v) => {
+			for (const x of v) item(w, x);
+		},
+	tuple:
+		(...items: Encoder<any>[]): Encoder<any[]> =>
+		(w, v) =>
+			items.forEach((item, i) => item(w, v[i])),
+	map:
+		<K, V>(key: Encoder<K>, value: Encoder<V>): Encoder<Map<K, V>> =>
+		(w, v) => {
+			w.u64(BigInt(v.size));
+			for (const [k, x] of v) {
+				key(w, k);
+				value(w, x);
+			}
+		},
+	result:
+		<T, E>(ok: Encoder<T>, err: Encoder<E>): Encoder<Result<T, E>> =>
+		(w, v) => {
+			if ("Ok" in v) {
+				w.u32(0);
+				ok(w, v.Ok);
+			} else {
+				w.u32(1);
+				err(w, v.Err);
+			}
+		},
+};
+
+export const decode = {
+	u8: (r: Reader): number => r.u8(),
+	i8: (r: Reader): number => r.i8(),
+	u16: (r: Reader): number => r.u16(),
+	i16: (r: Reader): number => r.i16(),
+	u32: (r: Reader): number => r.u32(),
+	i32: (r: Reader): number => r.i32(),
+	u64: (r: Reader): bigint => r.u64(),
+	i64: (r: Reader): bigint => r.i64(),
+	u128: (r: Reader): bigint => r.u128(),
+	i128: (r: Reader): bigint => r.i128(),
+	f32: (r: Reader): number => r.f32(),
+	f64: (r: Reader): number => r.f64(),
+	bool: (r: Reader): boolean => r.bool(),
+	string: (r: Reader): string => r.string(),
+	char: (r: Reader): string => r.char(),
+	unit: (_r: Reader): null => null,
+	option:
+		<T>(some: Decoder<T>): Decoder<T | undefined> =>
+		(r) => {
+			const tag = r.u8();
+			if (tag > 1) throw new Error(`turbocharger: invalid Option tag ${tag}`);
+			return tag === 1 ? some(r) : undefined;
+		},
+	seq:
+		<T>(item: Decoder<T>): Decoder<T[]> =>
+		(r) => {
+			const v: T[] = [];
+			for (let n = r.u64(); n > 0n; n--) v.push(item(r));
+			return v;
+		},
+	array:
+		<T>(item: Decoder<T>, length: number): Decoder<T[]> =>
+		(r) =>
+			Array.from({ length }, () => item(r)),
+	tuple:
+		(...items: Decoder<any>[]): Decoder<any> =>
+		(r) =>
+			items.map((item) => item(r)),
+	map:
+		<K, V>(key: Decoder<K>, value: Decoder<V>): Decoder<Map<K, V>> =>
+		(r) => {
+			const v = new Map<K, V>();
+			for (let n = r.u64(); n > 0n; n--) {
+				const k = key(r);
+				v.set(k, value(r));
+			}
+			return v;
+		},
+	result:
+		<T, E>(ok: Decoder<T>, err: Decoder<E>): Decoder<Result<T, E>> =>
+		(r) => {
+			switch (r.u32()) {
+				case 0:
+					return { Ok: ok(r) };
+				case 1:
+					return { Err: err(r) };
+				default:
+					throw new Error("turbocharger: invalid Result variant");
+			}
+		},
+};
+
+/** The `Err` of a backend function that returned one. */
+export class BackendError<E> extends Error {
+	constructor(readonly value: E) {
+		super(typeof value === "string" ? value : "backend function returned an error");
+	}
+}
+
+/** The `Ok` of `result`; throws a `BackendError` with the `Err`. */
+export function unwrap<T, E>(result: Result<T, E>): T {
+	if ("Ok" in result) return result.Ok;
+	throw new BackendError(result.Err);
+}
+
+/** The server sends the session token with this txid, and a client resuming the session sends it first. */
+const SESSION_TOKEN_TXID = 2;
+/** The server sends this txid, followed by a stream's txid, when the stream ends. */
+const STREAM_END_TXID = 3;
+
+export interface CallOptions {
+	/** Aborting cancels the call on the server and rejects it with the signal's reason, an `AbortError` by default. */
+	signal?: AbortSignal;
+}
+
+/** The reason `signal` was aborted with, or an `AbortError` in browsers that don't have `AbortSignal.reason`. */
+function abortReason(signal: AbortSignal): unknown {
+	return signal.reason ?? new DOMException("signal is aborted without reason", "AbortError");
+}
+
+interface Transaction {
+	message(r: Reader): void;
+	end?(): void;
+	close(error: Error): void;
+}
+
+/** A WebSocket connection to a Turbocharger server, opened on the first call, and reopened on the next call after it closes. */
+export class Connection {
+	/** Defaults to `/turbocharger_socket` on the page's host. */
+	url?: string;
+	/** Assigned by the server if sessions are enabled; persist it to resume the session after a page reload. */
+	sessionToken?: string;
+	private socket?: Promise<WebSocket>;
+	private nextTxid = 256;
+	private transactions = new Map<number, Transaction>();
+
+	/**
+	 * Calls the function with dispatch name `name`, which resolves to its decoded response. Aborting `options.signal`
+	 * cancels the call and rejects with the signal's reason.
+	 */
+	call<T>(name: string, params: (w: Writer) => void, response: Decoder<T>, options?: CallOptions): Promise<T> {
+		const txid = this.nextTxid++;
+		const req = request(name, txid, params);
+		const signal = options?.signal;
+		return new Promise<T>((resolve, reject) => {
+			if (signal?.aborted) return reject(abortReason(signal));
+			const onAbort = () => {
+				this.transactions.delete(txid);
+				// Sending the request again cancels it.
+				this.send(req).catch(() => {});
+				reject(abortReason(signal!));
+			};
+			const settle = () => {
+				this.transactions.delete(txid);
+				signal?.removeEventListener("abort", onAbort);
+			};
+			signal?.addEventListener("abort", onAbort, { once: true });
+			this.transactions.set(txid, {
+				message: (r) => {
+					settle();
+					try {
+						resolve(response(r));
+					} catch (e) {
+						reject(e);
+					}
+				},
+				close: (e) => {
+					settle();
+					reject(e);
+				},
+			});
+			this.send(req).catch((e) => {
+				settle();
+				reject(e);
+			});
+		});
+	}
+
+	/**
+	 * Calls the streaming function with dispatch name `name`. The iteration ends when the stream does; ending it early,
+	 * e.g. with `break`, cancels it.
+	 */
+	stream<T>(name: string, params: (w: Writer) => void, item: Decoder<T>): AsyncIterableIterator<T> {
+		const txid = this.nextTxid++;
+		const req = request(name, txid, params);
+		const items: T[] = [];
+		let error: Error | undefined;
+		let closed = false;
+		let ended = false;
+		let done = false;
+		let wake = () => {};
+
+		const finish = () => {
+			if (done) return;
+			done = true;
+			this.transactions.delete(txid);
+			// Sending the request again cancels it.
+			if (!closed) this.send(req).catch(() => {});
+		};
+
+		this.transactions.set(txid, {
+			message: (r) => {
+				try {
+					items.push(item(r));
+				} catch (e) {
+					error = e as Error;
+				}
+				wake();
+			},
+			end: () => {
+				ended = closed = true;
+				wake();
+			},
+			close: (e) => {
+				error = e;
+				closed = true;
+				wake();
+			},
+		});
+		this.send(req).catch((e) => {
+			error = e;
+			closed = true;
+			wake();
+		});
+
+		const iterator: AsyncIterableIterator<T> = {
+			next: async () => {
+				while (!items.length && !error && !ended && !done) await new Promise<void>((resolve) => (wake = resolve));
+				if (items.length) return { value: items.shift()!, done: false };
+				finish();
+				if (error) {
+					const e = error;
+					error = undefined;
+					throw e;
+				}
+				return { value: undefined, done: true };
+			},
+			return: async () => {
+				finish();
+				wake();
+				return { value: undefined, done: true };
+			},
+			[Symbol.asyncIterator]: () => iterator,
+		};
+		return iterator;
+	}
+
+	private async send(data: Uint8Array) {
+		if (!this.socket) this.socket = this.connect();
+		(await this.socket).send(data);
+	}
+
+	private connect(): Promise<WebSocket> {
+		let url =
+			this.url ?? `${location.protocol === "https:" ? "wss:" : "ws:"}//${location.host}/turbocharger_socket`;
+		url += `${url.includes("?") ? "&" : "?"}stream_end=1`;
+		const socket = new WebSocket(url);
+		socket.binaryType = "arraybuffer";
+
+		socket.onmessage = (event) => {
+			const r = new Reader(new Uint8Array(event.data as ArrayBuffer));
+			const txid = Number(r.i64());
+			if (txid === SESSION_TOKEN_TXID) {
+				this.sessionToken = textDecoder.decode(r.rest());
+			} else if (txid === STREAM_END_TXID) {
+				this.transactions.get(Number(r.i64()))?.end?.();
+			} else {
+				this.transactions.get(txid)?.message(r);
+			}
+		};
+
+		return new Promise((resolve, reject) => {
+			socket.onopen = () => {
+				// resume the session, before any requests, rather than in the URL, which may be logged
+				if (this.sessionToken !== undefined) {
+					const w = new Writer();
+					w.i64(BigInt(SESSION_TOKEN_TXID));
+					w.raw(textEncoder.encode(this.sessionToken));
+					socket.send(w.finish());
+				}
+				resolve(socket);
+			};
+			socket.onclose = () => {
+				this.socket = undefined;
+				reject(new Error(`turbocharger: can't connect to ${url}`));
+				const transactions = [...this.transactions.values()];
+				this.transactions.clear();
+				for (const transaction of transactions) {
+					transaction.close(new Error("turbocharger: connection closed"));
+				}
+			};
+		});
+	}
+}
+
+/** A request: `1`, which marks requests, the dispatch name, the txid, and the parameters. */
+function request(name: string, txid: number, params: (w: Writer) => void): Uint8Array {
+	const w = new Writer();
+	w.i64(1n);
+	w.string(name);
+	w.i64(BigInt(txid));
+	params(w);
+	return w.finish();
+}
@@ -0,0 +1,368 @@
+//! Classifies the changes between two versions of `backend_api.rs` as breaking or compatible for clients that were
+//! built against the old one.
+//!
+//! Requests and responses are encoded with bincode, which has no field names or default values: parameters, fields,
+//! and enum variants are identified by their position. So adding, removing, or reordering them is breaking, but
+//! renaming them isn't.
+
+use crate::api::{last_segment, type_string, Api};
+use std::{collections::HashSet, fmt};
+
+/// Whether clients built against the old API still work with a server built from the new one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+	Compatible,
+	Breaking,
+}
+
+/// A change to a function or type of the API.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change {
+	pub severity: Severity,
+	/// The function, by dispatch name, or type, by path, e.g. `fn arithmetic::add` or `struct Page`.
+	pub item: String,
+	/// What changed, e.g. ``parameter `b` changed from `i32` to `i64` ``.
+	pub description: String,
+}
+
+impl fmt::Display for Change {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let severity = match self.severity {
+			Severity::Compatible => "compatible",
+			Severity::Breaking => "breaking",
+		};
+		write!(f, "{}: {}: {}", severity, self.item, self.description)
+	}
+}
+
+/// The changes from `old` to `new`, two versions of `backend_api.rs`, with breaking changes first.
+pub fn diff(old: &str, new: &str) -> syn::Result<Vec<Change>> {
+	let old = Api::parse(old)?;
+	let new = Api::parse(new)?;
+	let returned = returned_types(&new);
+	let mut changes = vec![];
+
+	for (name, old_fn) in &old.fns {
+		let item = format!("fn {}", name);
+		match new.fns.get(name) {
+			Some(new_fn) => {
+				for (severity, description) in diff_fns(&old_fn.sig, &new_fn.sig) {
+					changes.push(Change { severity, item: item.clone(), description });
+				}
+			}
+			None => {
+				changes.push(Change { severity: Severity::Breaking, item, description: "removed".into() })
+			}
+		}
+	}
+	for name in new.fns.keys().filter(|name| !old.fns.contains_key(*name)) {
+		changes.push(Change {
+			severity: Severity::Compatible,
+			item: format!("fn {}", name),
+			description: "added".into(),
+		});
+	}
+
+	// Removing a type is only breaking through the functions that used it, which changed too.
+	for (path, old_item) in &old.types {
+		let item = format!("{} {}", kind(old_item), path);
+		match new.types.get(path) {
+			Some(new_item) => {
+				let is_returned = returned.contains(last_segment(path));
+				for (severity, description) in diff_types(old_item, new_item, is_returned) {
+					changes.push(Change { severity, item: item.clone(), description });
+				}
+			}
+			None => {
+				changes.push(Change { severity: Severity::Compatible, item, description: "removed".into() })
+			}
+		}
+	}
+	for (path, new_item) in new.types.iter().filter(|(path, _)| !old.types.contains_key(*path)) {
+		changes.push(Change {
+			severity: Severity::Compatible,
+			item: format!("{} {}", kind(new_item), path),
+			description: "added".into(),
+		});
+	}
+
+	changes.sort_by_key(|change| std::cmp::Reverse(change.severity));
+	Ok(changes)
+}
+
+/// Names of the types that responses can contain, which clients built against the old API have to decode.
+fn returned_types(api: &Api) -> HashSet<String> {
+	let mut pending: Vec<_> = api.fns.values().flat_map(|f| idents(&f.sig.output)).collect();
+	let mut returned = HashSet::new();
+	while let Some(name) = pending.pop() {
+		if returned.insert(name.clone()) {
+			for (path, item) in &api.types {
+				if last_segment(path) == name {
+					pending.extend(idents(item));
+				}
+			}
+		}
+	}
+	returned
+}
+
+fn diff_fns(old: &syn::Signature, new: &syn::Signature) -> Vec<(Severity, String)> {
+	let fn_kind = |sig: &syn::Signature| match sig.asyncness {
+		Some(_) => "an async function",
+		None => "a stream",
+	};
+	if fn_kind(old) != fn_kind(new) {
+		return vec![(Severity::Breaking, format!("changed from {} to {}", fn_kind(old), fn_kind(new)))];
+	}
+
+	let params = |sig: &syn::Signature| -> Vec<Field> {
+		sig
+			.inputs
+			.iter()
+			.enumerate()
+			.filter_map(|(i, input)| match input {
+				syn::FnArg::Typed(pat_type) => Some(Field {
+					name: match &*pat_type.pat {
+						syn::Pat::Ident(pat) => pat.ident.to_string(),
+						_ => i.to_string(),
+					},
+					ty: type_string(&pat_type.ty),
+				}),
+				syn::FnArg::Receiver(_) => None,
+			})
+			.collect()
+	};
+	let mut changes = diff_fields("parameter", &params(old), &params(new));
+
+	let output = |sig: &syn::Signature| match &sig.output {
+		syn::ReturnType::Type(_, ty) => type_string(ty),
+		syn::ReturnType::Default => "()".into(),
+	};
+	if output(old) != output(new) {
+		changes
+			.push((Severity::Breaking, format!("returns `{}` instead of `{}`", output(new), output(old))));
+	}
+	changes
+}
+
+/// `is_returned` if the type can be part of a response.
+fn diff_types(old: &syn::Item, new: &syn::Item, is_returned: bool) -> Vec<(Severity, String)> {
+	let generics = |item: &syn::Item| match item {
+		syn::Item::Struct(s) => type_string(&s.generics),
+		syn::Item::Enum(e) => type_string(&e.generics),
+		_ => unreachable!(),
+	};
+	if generics(old) != generics(new) {
+		return vec![(
+			Severity::Breaking,
+			format!("type parameters changed from `{}` to `{}`", generics(old), generics(new)),
+		)];
+	}
+
+	match (old, new) {
+		(syn::Item::Struct(old), syn::Item::Struct(new)) => {
+			diff_fields("field", &fields(&old.fields), &fields(&new.fields))
+		}
+		(syn::Item::Enum(old), syn::Item::Enum(new)) => {
+			let names =
+				|e: &syn::ItemEnum| e.variants.iter().map(|v| v.ident.to_string()).collect::<Vec<_>>();
+			let (old_names, new_names) = (names(old), names(new));
+			let mut changes = vec![];
+
+			for (old_variant, name) in old.variants.iter().zip(&old_names) {
+				match new.variants.iter().find(|v| v.ident == old_variant.ident) {
+					Some(new_variant) => changes.extend(diff_fields(
+						&format!("`{}` field", name),
+						&fields(&old_variant.fields),
+						&fields(&new_variant.fields),
+					)),
+					None => changes.push((Severity::Breaking, format!("variant `{}` removed", name))),
+				}
+			}
+			changes.extend(diff_order("variant", &old_names, &new_names));
+
+			// Variants are encoded by index, so new ones only fit after the existing ones, and only clients that
+			// send them, not those that receive them, can cope.
+			for (i, name) in new_names.iter().enumerate().filter(|(_, name)| !old_names.contains(name)) {
+				changes.push(match (i < old_names.len(), is_returned) {
+					(true, _) => {
+						(Severity::Breaking, format!("variant `{}` added before existing variants", name))
+					}
+					(false, true) => {
+						(Severity::Breaking, format!("variant `{}` added, and can be returned to clients", name))
+					}
+					(false, false) => (Severity::Compatible, format!("variant `{}` added", name)),
+				});
+			}
+			changes
+		}
+		_ => vec![(Severity::Breaking, format!("changed from {} to {}", kind(old), kind(new)))],
+	}
+}
+
+/// A parameter, field, or tuple field, which is named by its index.
+struct Field {
+	name: String,
+	ty: String,
+}
+
+fn fields(fields: &syn::Fields) -> Vec<Field> {
+	fields
+		.iter()
+		.enumerate()
+		.map(|(i, field)| Field {
+			name: field.ident.as_ref().map_or_else(|| i.to_string(), ToString::to_string),
+			ty: type_string(&field.ty),
+		})
+		.collect()
+}
+
+/// Changes to a list of positional fields; `what` is e.g. `parameter`.
+fn diff_fields(what: &str, old: &[Field], new: &[Field]) -> Vec<(Severity, String)> {
+	let position = |fields: &[Field], name: &str| fields.iter().position(|field| field.name == name);
+	let mut changes = vec![];
+	let mut renamed = HashSet::new();
+
+	for (i, old_field) in old.iter().enumerate() {
+		match position(new, &old_field.name) {
+			Some(j) if new[j].ty != old_field.ty => changes.push((
+				Severity::Breaking,
+				format!("{} `{}` changed from `{}` to `{}`", what, old_field.name, old_field.ty, new[j].ty),
+			)),
+			Some(_) => (),
+			// A new name at the same position, with the same type, is the same field.
+			None => match new.get(i) {
+				Some(new_field) if position(old, &new_field.name).is_none() && new_field.ty == old_field.ty => {
+					renamed.insert(i);
+					changes.push((
+						Severity::Compatible,
+						format!("{} `{}` renamed to `{}`", what, old_field.name, new_field.name),
+					));
+				}
+				_ => changes.push((Severity::Breaking, format!("{} `{}` removed", what, old_field.name))),
+			},
+		}
+	}
+	for (j, new_field) in new.iter().enumerate() {
+		if position(old, &new_field.name).is_none() && !renamed.contains(&j) {
+			changes.push((Severity::Breaking, format!("{} `{}` added", what, new_field.name)));
+		}
+	}
+
+	let names = |fields: &[Field]| fields.iter().map(|field| field.name.clone()).collect::<Vec<_>>();
+	changes.extend(diff_order(what, &names(old), &names(new)));
+	changes
+}
+
+/// A breaking change if the names in both `old` and `new` are in a different order.
+fn diff_order(what: &str, old: &[String], new: &[String]) -> Option<(Severity, String)> {
+	let old_order: Vec<_> = old.iter().filter(|name| new.contains(name)).collect();
+	let new_order: Vec<_> = new.iter().filter(|name| old.contains(name)).collect();
+	let list =
+		|names: &[&String]| names.iter().map(|name| format!("`{}`", name)).collect::<Vec<_>>().join(", ");
+	(old_order != new_order).then(|| {
+		(
+			Severity::Breaking,
+			format!("{}s reordered from {} to {}", what, list(&old_order), list(&new_order)),
+		)
+	})
+}
+
+fn kind(item: &syn::Item) -> &'static str {
+	match item {
+		syn::Item::Struct(_) => "struct",
+		syn::Item::Enum(_) => "enum",
+		_ => "item",
+	}
+}
+
+fn idents(tokens: &impl quote::ToTokens) -> Vec<String> {
+	fn collect(tokens: proc_macro2::TokenStream, idents: &mut Vec<String>) {
+		for token in tokens {
+			match token {
+				proc_macro2::TokenTree::Ident(ident) => idents.push(ident.to_string()),
+				proc_macro2::TokenTree::Group(group) => collect(group.stream(), idents),
+				_ => (),
+			}
+		}
+	}
+
+	let mut idents = vec![];
+	collect(tokens.to_token_stream(), &mut idents);
+	idents
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn changes(old: &str, new: &str) -> Vec<String> {
+		diff(old, new).unwrap().iter().map(ToString::to_string).collect()
+	}
+
+	#[test]
+	fn test_fns() {
+		let old = r#"
+			async fn get_user(id: i64) -> User {}
+			async fn delete_user(id: i64) {}
+			mod arithmetic {
+				async fn add(a: i32, b: i32) -> i32 {}
+			}
+			#[backend(name = "tests.double")]
+			async fn double(n: i32) -> i32 {}
+			#[backend(instantiate(T = i64, T = String))]
+			async fn first_page<T>(fill: T) -> Page<T> {}
+		"#;
+		let new = r#"
+			async fn get_user(user_id: i64) -> User {}
+			mod arithmetic {
+				async fn add(a: i32, b: i64) -> i32 {}
+			}
+			#[backend(name = "tests.double")]
+			async fn twice(n: i32) -> i32 {}
+			#[backend(instantiate(T = String, T = bool))]
+			async fn first_page<T>(fill: T) -> Page<T> {}
+			fn count_up(to: i32) -> impl Stream<Item = i32> {}
+		"#;
+		assert_eq!(
+			changes(old, new),
+			[
+				"breaking: fn arithmetic::add: parameter `b` changed from `i32` to `i64`",
+				"breaking: fn delete_user: removed",
+				"breaking: fn first_page<i64>: removed",
+				"compatible: fn get_user: parameter `id` renamed to `user_id`",
+				"compatible: fn count_up: added",
+				"compatible: fn first_page<bool>: added",
+			]
+		);
+	}
+
+	#[test]
+	fn test_types() {
+		let old = r#"
+			async fn get_shape() -> Shape {}
+			async fn set_color(color: Color) {}
+			struct Person { name: String, age: u8 }
+			enum Shape { Empty, Circle { radius: f64 } }
+			enum Color { Red, Green }
+		"#;
+		let new = r#"
+			async fn get_shape() -> Shape {}
+			async fn set_color(color: Color) {}
+			struct Person { age: u8, name: String, email: String }
+			enum Shape { Empty, Circle { radius: f32 }, Square(f64) }
+			enum Color { Red, Green, Blue }
+		"#;
+		assert_eq!(
+			changes(old, new),
+			[
+				"breaking: struct Person: field `email` added",
+				"breaking: struct Person: fields reordered from `name`, `age` to `age`, `name`",
+				"breaking: enum Shape: `Circle` field `radius` changed from `f64` to `f32`",
+				"breaking: enum Shape: variant `Square` added, and can be returned to clients",
+				"compatible: enum Color: variant `Blue` added",
+			]
+		);
+		assert_eq!(changes(new, new), Vec::<String>::new());
+	}
+}
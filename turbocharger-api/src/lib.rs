@@ -0,0 +1,437 @@
+//! Assembles the API manifests recorded by Turbocharger's `#[backend]` macro into `backend_api.rs`.
+//!
+//! While compiling, `#[backend]` writes the items each crate (or binary) exposes to
+//! `{target dir}/turbocharger/{package}/{crate name}/api.rs`. After building, run `turbocharger-api assemble` to merge
+//! them into `backend_api.rs` in the workspace root.
+//!
+//! [`diff`] compares two versions of `backend_api.rs`, and finds the changes that break deployed clients. [`schema`]
+//! describes the API in `backend_api.rs` as JSON, for use outside of Rust, and [`typescript`] generates a TypeScript
+//! client for it that doesn't need the WASM module.
+
+mod api;
+mod compat;
+mod schema;
+mod typescript;
+
+pub use compat::{diff, Change, Severity};
+pub use schema::schema;
+pub use typescript::typescript;
+
+use std::{
+	collections::HashMap,
+	fs, io,
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+const HEADER: &str = "// This file is auto-generated by Turbocharger.\n// Check it into version control to track API changes over time.\n// To regenerate: build, then run \"turbocharger-api assemble\"\n";
+
+/// The API manifest of one compiled crate.
+pub struct Manifest {
+	/// Package name, e.g. `my-app`.
+	pub package: String,
+	/// Crate name, prefixed by `bin-` for binaries, e.g. `bin-server`.
+	pub unit: String,
+	pub items: Vec<syn::Item>,
+	dir: PathBuf,
+}
+
+impl Manifest {
+	/// Deletes the manifest, e.g. because its crate is no longer part of the build.
+	pub fn remove(self) -> io::Result<()> {
+		fs::remove_dir_all(self.dir)
+	}
+
+	/// Moves the items recorded from each source file, which `#[backend]` lists in a `#[path = "..."] mod`, into a
+	/// `mod` for each segment of the file's module path in the crate whose root source file is `root`, e.g.
+	/// `src/main.rs`. Items of files outside the crate's source directory stay at the top level.
+	pub fn place_in_modules(&mut self, root: &Path) {
+		let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+		for item in std::mem::take(&mut self.items) {
+			let file = match &item {
+				syn::Item::Mod(module) => source_file(module),
+				_ => None,
+			};
+			match (item, file) {
+				(syn::Item::Mod(syn::ItemMod { content: Some((_, contents)), .. }), Some(file)) => {
+					let path = file_module_path(Path::new(&file), &root)
+						.filter(|path| path.iter().all(|segment| syn::parse_str::<syn::Ident>(segment).is_ok()))
+						.unwrap_or_default();
+					self.items.extend(nested(&path, contents));
+				}
+				(item, _) => self.items.push(item),
+			}
+		}
+	}
+}
+
+/// The source file of a `#[path = "..."] mod` recorded by `#[backend]`.
+fn source_file(module: &syn::ItemMod) -> Option<String> {
+	let attr = module.attrs.iter().find(|attr| attr.path().is_ident("path"))?;
+	match &attr.meta {
+		syn::Meta::NameValue(syn::MetaNameValue {
+			value: syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(file), .. }),
+			..
+		}) => Some(file.value()),
+		_ => None,
+	}
+}
+
+/// Module path of `file` in the crate whose root is `root`, following the file layout of `mod` declarations.
+fn file_module_path(file: &Path, root: &Path) -> Option<Vec<String>> {
+	if file == root {
+		return Some(vec![]);
+	}
+	let relative = file.strip_prefix(root.parent()?).ok()?;
+	let mut path: Vec<_> =
+		relative.parent()?.iter().map(|segment| segment.to_string_lossy().into_owned()).collect();
+	match relative.file_stem()?.to_str()? {
+		"mod" => (),
+		stem => path.push(stem.to_string()),
+	}
+	Some(path)
+}
+
+/// `items` in a `mod` for each segment of `path`.
+fn nested(path: &[String], items: Vec<syn::Item>) -> Vec<syn::Item> {
+	match path.split_first() {
+		Some((segment, rest)) => {
+			let ident = quote::format_ident!("{}", segment);
+			let items = nested(rest, items);
+			vec![syn::parse_quote!(mod #ident { #(#items)* })]
+		}
+		None => items,
+	}
+}
+
+/// Reads the manifests recorded under `target_dir`, in a stable order.
+///
+/// `#[backend]` only writes a manifest while expanding items, so a crate whose items were all removed leaves its last
+/// manifest behind. A manifest written before the latest successful compilation of its crate is read as empty.
+pub fn read_manifests(target_dir: &Path) -> io::Result<Vec<Manifest>> {
+	let compiled = last_compiled(target_dir)?;
+	let mut manifests = vec![];
+	for package_dir in sorted_dirs(&target_dir.join("turbocharger"))? {
+		for dir in sorted_dirs(&package_dir)? {
+			let path = dir.join("api.rs");
+			if !path.is_file() {
+				continue;
+			}
+			let (package, unit) = (file_name(&package_dir), file_name(&dir));
+			let items = match compiled.get(&(package.clone(), unit.clone())) {
+				Some(compiled) if *compiled > fs::metadata(&path)?.modified()? => vec![],
+				_ => {
+					syn::parse_file(&fs::read_to_string(&path)?)
+						.map_err(|e| {
+							io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+						})?
+						.items
+				}
+			};
+			manifests.push(Manifest { package, unit, items, dir });
+		}
+	}
+	Ok(manifests)
+}
+
+/// When cargo started the latest successful compilation of each crate, by `(package, unit)` named like manifests are,
+/// from the fingerprints in `{target dir}/{profile}` and `{target dir}/{target triple}/{profile}`.
+fn last_compiled(target_dir: &Path) -> io::Result<HashMap<(String, String), SystemTime>> {
+	let mut profile_dirs = sorted_dirs(target_dir)?;
+	for dir in profile_dirs.clone() {
+		profile_dirs.extend(sorted_dirs(&dir)?);
+	}
+	let mut compiled = HashMap::new();
+	for profile_dir in profile_dirs {
+		for dir in sorted_dirs(&profile_dir.join(".fingerprint"))? {
+			// Named `{package}-{hash}`, with a file named after each crate compiled, written when it succeeds.
+			let dir_name = file_name(&dir);
+			let (Some((package, _)), Ok(invoked)) = (
+				dir_name.rsplit_once('-'),
+				fs::metadata(dir.join("invoked.timestamp")).and_then(|metadata| metadata.modified()),
+			) else {
+				continue;
+			};
+			for file in fs::read_dir(&dir)? {
+				let file = file?;
+				let name = file.file_name().to_string_lossy().into_owned();
+				let name = name.strip_prefix("test-").unwrap_or(&name);
+				let unit = match (name.strip_prefix("lib-"), name.strip_prefix("bin-")) {
+					(Some(lib), _) => lib.replace('-', "_"),
+					(_, Some(bin)) => format!("bin-{}", bin.replace('-', "_")),
+					_ => continue,
+				};
+				if unit.ends_with(".json") || file.metadata()?.modified()? < invoked {
+					continue;
+				}
+				let latest = compiled.entry((package.to_string(), unit)).or_insert(invoked);
+				*latest = (*latest).max(invoked);
+			}
+		}
+	}
+	Ok(compiled)
+}
+
+/// `backend_api.rs` for `manifests`: the items of workspace members at the top level and those of dependencies in a
+/// `mod` named after the package, sorted by name, and without duplicates, e.g. from binaries sharing a module. Fails
+/// if different functions, or different types, in one module have the same name, since clients couldn't tell them
+/// apart.
+pub fn assemble(
+	manifests: &[Manifest],
+	is_workspace_member: impl Fn(&str) -> bool,
+) -> Result<String, String> {
+	let mut items = vec![];
+	let mut dependencies = std::collections::BTreeMap::<_, Vec<_>>::new();
+	for manifest in manifests {
+		if is_workspace_member(&manifest.package) {
+			items.extend(manifest.items.iter().cloned());
+		} else {
+			dependencies
+				.entry(manifest.package.replace('-', "_"))
+				.or_default()
+				.extend(manifest.items.iter().cloned());
+		}
+	}
+	for (package, dependency_items) in dependencies {
+		let ident = quote::format_ident!("{}", package);
+		let dependency_items = sorted(dependency_items);
+		items.push(syn::parse_quote! {
+			mod #ident {
+				#(#dependency_items)*
+			}
+		});
+	}
+
+	let items = sorted(items);
+	check_names(&items, "")?;
+	let mut output = HEADER.to_string();
+	for item in items {
+		output.push('\n');
+		output.push_str(&unparse(&item));
+	}
+	Ok(output)
+}
+
+/// Fails if two of `items`, in the module with path prefix `module`, are functions or types with the same name.
+fn check_names(items: &[syn::Item], module: &str) -> Result<(), String> {
+	let mut names = std::collections::HashSet::new();
+	for item in items {
+		match item {
+			syn::Item::Fn(_) | syn::Item::Struct(_) | syn::Item::Enum(_)
+				if !names.insert((matches!(item, syn::Item::Fn(_)), name(item))) =>
+			{
+				return Err(format!("more than one item is named `{}{}`", module, name(item)));
+			}
+			syn::Item::Mod(syn::ItemMod { ident, content: Some((_, contents)), .. }) => {
+				check_names(contents, &format!("{}{}::", module, ident))?
+			}
+			_ => (),
+		}
+	}
+	Ok(())
+}
+
+/// `items` sorted by name, then by source, without duplicates, and with `mod`s of the same name merged, e.g. a module
+/// shared by binaries that expose different items in it.
+fn sorted(items: Vec<syn::Item>) -> Vec<syn::Item> {
+	let mut merged: Vec<syn::Item> = vec![];
+	for item in items {
+		if let syn::Item::Mod(syn::ItemMod { ident, content: Some((_, contents)), .. }) = &item {
+			let existing = merged.iter_mut().find_map(|existing| match existing {
+				syn::Item::Mod(existing) if existing.ident == *ident => existing.content.as_mut(),
+				_ => None,
+			});
+			if let Some((_, existing)) = existing {
+				existing.extend(contents.iter().cloned());
+				continue;
+			}
+		}
+		merged.push(item);
+	}
+	for item in &mut merged {
+		if let syn::Item::Mod(syn::ItemMod { content: Some((_, contents)), .. }) = item {
+			*contents = sorted(std::mem::take(contents));
+		}
+	}
+
+	let mut items: Vec<_> =
+		merged.into_iter().map(|item| ((name(&item).to_lowercase(), unparse(&item)), item)).collect();
+	items.sort_by(|(a, _), (b, _)| a.cmp(b));
+	items.dedup_by(|(a, _), (b, _)| a == b);
+	items.into_iter().map(|(_, item)| item).collect()
+}
+
+fn name(item: &syn::Item) -> String {
+	match item {
+		syn::Item::Struct(s) => s.ident.to_string(),
+		syn::Item::Enum(e) => e.ident.to_string(),
+		syn::Item::Fn(f) => f.sig.ident.to_string(),
+		syn::Item::Mod(m) => m.ident.to_string(),
+		syn::Item::Impl(i) => {
+			let self_ty = &i.self_ty;
+			quote::quote!(#self_ty).to_string()
+		}
+		item => unparse(item),
+	}
+}
+
+fn unparse(item: &syn::Item) -> String {
+	prettyplease::unparse(&syn::File { shebang: None, attrs: vec![], items: vec![item.clone()] })
+}
+
+fn sorted_dirs(dir: &Path) -> io::Result<Vec<PathBuf>> {
+	if !dir.is_dir() {
+		return Ok(vec![]);
+	}
+	let mut dirs = vec![];
+	for entry in fs::read_dir(dir)? {
+		let path = entry?.path();
+		if path.is_dir() {
+			dirs.push(path);
+		}
+	}
+	dirs.sort();
+	Ok(dirs)
+}
+
+fn file_name(path: &Path) -> String {
+	path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn manifest(package: &str, unit: &str, source: &str) -> Manifest {
+		Manifest {
+			package: package.into(),
+			unit: unit.into(),
+			items: syn::parse_file(source).unwrap().items,
+			dir: PathBuf::new(),
+		}
+	}
+
+	#[test]
+	fn test_assemble() {
+		let manifests = [
+			manifest(
+				"app",
+				"bin-server",
+				"async fn get_user() {} struct User {} mod admin { async fn get_user() {} async fn ban() {} }",
+			),
+			manifest(
+				"app",
+				"bin-wasm",
+				"struct User {} mod admin { async fn get_user() {} async fn audit() {} }",
+			),
+			manifest("auth-lib", "auth_lib", "async fn login() {} async fn check() {}"),
+		];
+		let output = assemble(&manifests, |package| package == "app").unwrap();
+		assert_eq!(
+			output,
+			HEADER.to_string()
+				+ "\nmod admin {\n    async fn audit() {}\n    async fn ban() {}\n    async fn get_user() {}\n}\n"
+				+ "\nmod auth_lib {\n    async fn check() {}\n    async fn login() {}\n}\n"
+				+ "\nasync fn get_user() {}\n"
+				+ "\nstruct User {}\n"
+		);
+
+		let mut reversed = manifests;
+		reversed.reverse();
+		assert_eq!(assemble(&reversed, |package| package == "app").unwrap(), output);
+
+		let manifests = [
+			manifest("app", "bin-server", "mod admin { async fn get_user() -> i32 {} }"),
+			manifest("app", "bin-wasm", "mod admin { async fn get_user() -> String {} }"),
+		];
+		assert_eq!(
+			assemble(&manifests, |package| package == "app").unwrap_err(),
+			"more than one item is named `admin::get_user`"
+		);
+	}
+
+	#[test]
+	fn test_file_module_path() {
+		let path = |file: &str, root: &str| file_module_path(Path::new(file), Path::new(root));
+		assert_eq!(path("/app/src/server.rs", "/app/src/server.rs"), Some(vec![]));
+		assert_eq!(path("/app/src/api.rs", "/app/src/server.rs"), Some(vec!["api".into()]));
+		assert_eq!(path("/app/src/api/mod.rs", "/app/src/lib.rs"), Some(vec!["api".into()]));
+		assert_eq!(
+			path("/app/src/api/people.rs", "/app/src/lib.rs"),
+			Some(vec!["api".into(), "people".into()])
+		);
+		assert_eq!(path("/other/src/api.rs", "/app/src/lib.rs"), None);
+	}
+
+	#[test]
+	fn test_place_in_modules() {
+		let mut manifest = manifest(
+			"app",
+			"bin-server",
+			r#"
+			async fn root() {}
+			#[path = "/app/src/server.rs"]
+			mod source_file { async fn main_fn() {} }
+			#[path = "/app/src/api/people.rs"]
+			mod source_file { async fn add() {} mod inline { async fn add() {} } }
+			#[path = "/app/src/strings.rs"]
+			mod source_file { async fn add() {} }
+			#[path = "/elsewhere/generated.rs"]
+			mod source_file { async fn generated() {} }
+			"#,
+		);
+		manifest.place_in_modules(Path::new("/app/src/server.rs"));
+		assert_eq!(
+			assemble(&[manifest], |package| package == "app").unwrap(),
+			HEADER.to_string()
+				+ "\nmod api {\n    mod people {\n        async fn add() {}\n        mod inline {\n            async fn add() {}\n        }\n    }\n}\n"
+				+ "\nasync fn generated() {}\n"
+				+ "\nasync fn main_fn() {}\n"
+				+ "\nasync fn root() {}\n"
+				+ "\nmod strings {\n    async fn add() {}\n}\n"
+		);
+	}
+
+	fn modified(path: &Path) -> SystemTime {
+		fs::metadata(path).unwrap().modified().unwrap()
+	}
+
+	/// Writes `path` with a modification time after `after`, waiting out filesystems with coarse timestamps.
+	fn write_after(path: &Path, contents: &str, after: SystemTime) {
+		fs::create_dir_all(path.parent().unwrap()).unwrap();
+		loop {
+			fs::write(path, contents).unwrap();
+			if modified(path) > after {
+				return;
+			}
+			std::thread::sleep(std::time::Duration::from_millis(10));
+		}
+	}
+
+	#[test]
+	fn test_removed_items() {
+		let target_dir =
+			std::env::temp_dir().join(format!("turbocharger-api-test-{}", std::process::id()));
+		let api_rs = target_dir.join("turbocharger/app/bin-server/api.rs");
+		let fingerprint = target_dir.join("debug/.fingerprint/app-0123456789abcdef");
+		let (invoked, compiled) = (fingerprint.join("invoked.timestamp"), fingerprint.join("bin-server"));
+		let assembled =
+			|| assemble(&read_manifests(&target_dir).unwrap(), |package| package == "app").unwrap();
+
+		// A compilation that records `get_user`.
+		write_after(&invoked, "", SystemTime::UNIX_EPOCH);
+		write_after(&api_rs, "async fn get_user() {}", modified(&invoked));
+		write_after(&compiled, "", modified(&api_rs));
+		assert_eq!(assembled(), HEADER.to_string() + "\nasync fn get_user() {}\n");
+
+		// One that fails doesn't count.
+		write_after(&invoked, "", modified(&compiled));
+		assert_eq!(assembled(), HEADER.to_string() + "\nasync fn get_user() {}\n");
+
+		// One after every item was removed doesn't write the manifest.
+		write_after(&compiled, "", modified(&invoked));
+		assert_eq!(assembled(), HEADER);
+
+		fs::remove_dir_all(&target_dir).unwrap();
+	}
+}
@@ -0,0 +1,147 @@
+//! `turbocharger-api assemble [--check]`: merges the API manifests recorded while building the current workspace into
+//! `backend_api.rs` in its root, and deletes those of crates that are no longer part of it. With `--check`, changes
+//! nothing, and fails if `backend_api.rs` is out of date.
+//!
+//! `turbocharger-api diff <old> <new>`: lists the changes between two versions of `backend_api.rs`, where `-` reads
+//! one from stdin, and fails if any of them breaks clients built against the old version.
+//!
+//! `turbocharger-api schema <backend_api.rs>`: prints a JSON description of the API, for use outside of Rust.
+//!
+//! `turbocharger-api typescript <backend_api.rs>`: prints a TypeScript client for the API.
+
+use std::{
+	collections::{HashMap, HashSet},
+	path::Path,
+	process::ExitCode,
+};
+
+const USAGE: &str = "usage: turbocharger-api assemble [--check]
+       turbocharger-api diff <old backend_api.rs> <new backend_api.rs>
+       turbocharger-api schema <backend_api.rs>
+       turbocharger-api typescript <backend_api.rs>";
+
+fn main() -> ExitCode {
+	let args: Vec<_> = std::env::args().skip(1).collect();
+	let result = match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+		["assemble"] => assemble(false),
+		["assemble", "--check"] => assemble(true).map(|up_to_date| {
+			if !up_to_date {
+				eprintln!("backend_api.rs is out of date; run `turbocharger-api assemble`");
+			}
+			up_to_date
+		}),
+		["diff", old, new] => diff(old, new),
+		["schema", path] => schema(path),
+		["typescript", path] => typescript(path),
+		_ => {
+			eprintln!("{}", USAGE);
+			return ExitCode::from(2);
+		}
+	};
+
+	match result {
+		Ok(true) => ExitCode::SUCCESS,
+		Ok(false) => ExitCode::FAILURE,
+		Err(e) => {
+			eprintln!("error: {}", e);
+			ExitCode::FAILURE
+		}
+	}
+}
+
+/// Prints the changes from `old` to `new`; returns `false` if any are breaking.
+fn diff(old: &str, new: &str) -> Result<bool, Box<dyn std::error::Error>> {
+	let changes = turbocharger_api::diff(&read(old)?, &read(new)?)?;
+	for change in &changes {
+		println!("{}", change);
+	}
+	let breaking =
+		changes.iter().filter(|change| change.severity == turbocharger_api::Severity::Breaking).count();
+	if breaking > 0 {
+		eprintln!("{} breaking change{}", breaking, if breaking == 1 { "" } else { "s" });
+	}
+	Ok(breaking == 0)
+}
+
+/// Returns `false` if `check` finds `backend_api.rs` out of date.
+fn assemble(check: bool) -> Result<bool, Box<dyn std::error::Error>> {
+	let metadata = cargo_metadata()?;
+	let field =
+		|name: &str| metadata[name].as_str().ok_or(format!("cargo metadata has no `{}`", name));
+	let target_dir = Path::new(field("target_directory")?);
+	let backend_api_rs = Path::new(field("workspace_root")?).join("backend_api.rs");
+	let member_ids: HashSet<_> = metadata["workspace_members"]
+		.as_array()
+		.into_iter()
+		.flatten()
+		.filter_map(|id| id.as_str())
+		.collect();
+
+	// The root source file of every crate in the build, by `(package, crate name)` like manifests are named, with
+	// `bin-` for binaries.
+	let mut units = HashMap::new();
+	let mut members = HashSet::new();
+	for package in metadata["packages"].as_array().into_iter().flatten() {
+		let name = package["name"].as_str().unwrap_or_default();
+		if member_ids.contains(package["id"].as_str().unwrap_or_default()) {
+			members.insert(name);
+		}
+		for target in package["targets"].as_array().into_iter().flatten() {
+			let crate_name = target["name"].as_str().unwrap_or_default().replace('-', "_");
+			let is_bin = target["kind"].as_array().into_iter().flatten().any(|kind| kind == "bin");
+			let unit = if is_bin { format!("bin-{}", crate_name) } else { crate_name };
+			units.insert((name, unit), Path::new(target["src_path"].as_str().unwrap_or_default()));
+		}
+	}
+
+	// Manifests of crates that left the build, or that have no items anymore, are deleted.
+	let (mut manifests, stale): (Vec<_>, Vec<_>) =
+		turbocharger_api::read_manifests(target_dir)?.into_iter().partition(|manifest| {
+			!manifest.items.is_empty()
+				&& units.contains_key(&(manifest.package.as_str(), manifest.unit.clone()))
+		});
+	for manifest in &mut manifests {
+		manifest.place_in_modules(units[&(manifest.package.as_str(), manifest.unit.clone())]);
+	}
+	let output = turbocharger_api::assemble(&manifests, |package| members.contains(package))?;
+	let up_to_date = std::fs::read_to_string(&backend_api_rs).ok().as_deref() == Some(output.as_str());
+
+	if !check {
+		for manifest in stale {
+			manifest.remove()?;
+		}
+		if !up_to_date {
+			std::fs::write(&backend_api_rs, output)?;
+		}
+		return Ok(true);
+	}
+	Ok(up_to_date)
+}
+
+fn schema(path: &str) -> Result<bool, Box<dyn std::error::Error>> {
+	print!("{}", turbocharger_api::schema(&read(path)?)?);
+	Ok(true)
+}
+
+fn typescript(path: &str) -> Result<bool, Box<dyn std::error::Error>> {
+	print!("{}", turbocharger_api::typescript(&read(path)?)?);
+	Ok(true)
+}
+
+/// Reads the file at `path`, or stdin for `-`.
+fn read(path: &str) -> std::io::Result<String> {
+	match path {
+		"-" => std::io::read_to_string(std::io::stdin()),
+		path => std::fs::read_to_string(path),
+	}
+}
+
+fn cargo_metadata() -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+	let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+	let output =
+		std::process::Command::new(cargo).args(["metadata", "--format-version", "1"]).output()?;
+	if !output.status.success() {
+		return Err(format!("cargo metadata failed: {}", String::from_utf8_lossy(&output.stderr)).into());
+	}
+	Ok(serde_json::from_slice(&output.stdout)?)
+}
@@ -0,0 +1,252 @@
+//! A language-neutral description of the API in `backend_api.rs`, as JSON, for generating clients and documentation
+//! outside of Rust.
+//!
+//! Each function has its dispatch name, docs, parameters, return type, whether it streams, and its error type. Types
+//! are given both as written in Rust and as a JSON Schema of their shape, as serde represents them in JSON; the
+//! structs and enums of the API are under `$defs`. Enums are adjacently tagged, like in JS: `{ "type": "Circle",
+//! "value": { "radius": 1.0 } }`.
+
+use crate::api::{docs, path_type_args, split_result, stream_item, substitute, type_string, Api};
+use serde_json::{json, Map, Value};
+
+/// The JSON description of `backend_api_rs`, the contents of a `backend_api.rs`.
+pub fn schema(backend_api_rs: &str) -> syn::Result<String> {
+	let api = Api::parse(backend_api_rs)?;
+	let typed = |ty: &syn::Type| json!({ "type": type_string(ty), "schema": type_schema(ty, &api) });
+
+	let functions: Vec<_> = api
+		.fns
+		.iter()
+		.map(|(name, function)| {
+			let params: Vec<_> = function
+				.sig
+				.inputs
+				.iter()
+				.filter_map(|input| match input {
+					syn::FnArg::Typed(pat_type) => {
+						let mut param = typed(&substitute(&pat_type.ty, &function.type_args));
+						param["name"] = type_string(&pat_type.pat).into();
+						Some(param)
+					}
+					syn::FnArg::Receiver(_) => None,
+				})
+				.collect();
+
+			let output = match &function.sig.output {
+				syn::ReturnType::Type(_, ty) => substitute(ty, &function.type_args),
+				syn::ReturnType::Default => syn::parse_quote!(()),
+			};
+			let stream_item = stream_item(&output);
+			let (returns, error) = split_result(stream_item.as_ref().unwrap_or(&output));
+
+			json!({
+				"name": name,
+				"docs": docs(&function.attrs),
+				"params": params,
+				"returns": typed(&returns),
+				"stream": stream_item.is_some(),
+				"error": error.as_ref().map(typed),
+			})
+		})
+		.collect();
+
+	let defs: Map<_, _> =
+		api.types.iter().map(|(path, item)| (path.clone(), item_schema(item, &api))).collect();
+
+	let schema = json!({
+		"$schema": "https://json-schema.org/draft/2020-12/schema",
+		"functions": functions,
+		"$defs": defs,
+	});
+	Ok(serde_json::to_string_pretty(&schema).unwrap() + "\n")
+}
+
+/// JSON Schema of a struct or enum.
+fn item_schema(item: &syn::Item, api: &Api) -> Value {
+	match item {
+		syn::Item::Struct(s) => described(fields_schema(&s.fields, api), &s.attrs),
+		syn::Item::Enum(e) => {
+			let variants: Vec<_> = e
+				.variants
+				.iter()
+				.map(|variant| {
+					let tag = json!({ "const": variant.ident.to_string() });
+					let schema = match &variant.fields {
+						syn::Fields::Unit => json!({
+							"type": "object",
+							"properties": { "type": tag },
+							"required": ["type"],
+						}),
+						fields => json!({
+							"type": "object",
+							"properties": { "type": tag, "value": fields_schema(fields, api) },
+							"required": ["type", "value"],
+						}),
+					};
+					described(schema, &variant.attrs)
+				})
+				.collect();
+			described(json!({ "oneOf": variants }), &e.attrs)
+		}
+		_ => json!({}),
+	}
+}
+
+/// JSON Schema of the fields of a struct or enum variant: an object, a newtype's value, or an array for tuples.
+fn fields_schema(fields: &syn::Fields, api: &Api) -> Value {
+	match fields {
+		syn::Fields::Named(fields) => {
+			let names: Vec<_> = fields.named.iter().map(|f| type_string(&f.ident)).collect();
+			let properties: Map<_, _> = fields
+				.named
+				.iter()
+				.zip(&names)
+				.map(|(f, name)| (name.clone(), described(type_schema(&f.ty, api), &f.attrs)))
+				.collect();
+			json!({ "type": "object", "properties": properties, "required": names })
+		}
+		syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+			type_schema(&fields.unnamed[0].ty, api)
+		}
+		syn::Fields::Unnamed(fields) => {
+			tuple_schema(fields.unnamed.iter().map(|f| type_schema(&f.ty, api)).collect())
+		}
+		syn::Fields::Unit => json!({ "type": "null" }),
+	}
+}
+
+/// JSON Schema of a Rust type's serde representation; `{}`, which allows any value, for types it doesn't know.
+fn type_schema(ty: &syn::Type, api: &Api) -> Value {
+	match ty {
+		syn::Type::Reference(syn::TypeReference { elem, .. })
+		| syn::Type::Paren(syn::TypeParen { elem, .. })
+		| syn::Type::Group(syn::TypeGroup { elem, .. }) => type_schema(elem, api),
+		syn::Type::Slice(syn::TypeSlice { elem, .. }) | syn::Type::Array(syn::TypeArray { elem, .. }) => {
+			json!({ "type": "array", "items": type_schema(elem, api) })
+		}
+		syn::Type::Tuple(syn::TypeTuple { elems, .. }) if elems.is_empty() => json!({ "type": "null" }),
+		syn::Type::Tuple(syn::TypeTuple { elems, .. }) => {
+			tuple_schema(elems.iter().map(|ty| type_schema(ty, api)).collect())
+		}
+		syn::Type::Path(syn::TypePath { qself: None, path }) => path_schema(path, api),
+		_ => json!({}),
+	}
+}
+
+fn path_schema(path: &syn::Path, api: &Api) -> Value {
+	let segment = path.segments.last().unwrap();
+	let ident = segment.ident.to_string();
+	let args = path_type_args(segment);
+
+	match (ident.as_str(), args.as_slice()) {
+		(
+			"i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128"
+			| "usize",
+			[],
+		) => json!({ "type": "integer", "format": ident }),
+		("f32" | "f64", []) => json!({ "type": "number", "format": ident }),
+		("bool", []) => json!({ "type": "boolean" }),
+		("String" | "str" | "char", []) => json!({ "type": "string" }),
+		("Box" | "Arc" | "Rc" | "Cow", [.., ty]) => type_schema(ty, api),
+		("Option", [ty]) => json!({ "anyOf": [type_schema(ty, api), { "type": "null" }] }),
+		("Vec" | "VecDeque", [ty]) => json!({ "type": "array", "items": type_schema(ty, api) }),
+		("HashSet" | "BTreeSet", [ty]) => {
+			json!({ "type": "array", "items": type_schema(ty, api), "uniqueItems": true })
+		}
+		("HashMap" | "BTreeMap", [_, v]) => {
+			json!({ "type": "object", "additionalProperties": type_schema(v, api) })
+		}
+		("Result", [t, e]) => json!({
+			"oneOf": [
+				{ "type": "object", "properties": { "Ok": type_schema(t, api) }, "required": ["Ok"] },
+				{ "type": "object", "properties": { "Err": type_schema(e, api) }, "required": ["Err"] },
+			]
+		}),
+		_ => match api.type_path(path) {
+			Some(def_path) => json!({ "$ref": format!("#/$defs/{}", def_path) }),
+			None => json!({}),
+		},
+	}
+}
+
+fn tuple_schema(elems: Vec<Value>) -> Value {
+	json!({ "type": "array", "prefixItems": elems, "minItems": elems.len(), "items": false })
+}
+
+/// `schema` with the doc comment in `attrs` as its `description`.
+fn described(mut schema: Value, attrs: &[syn::Attribute]) -> Value {
+	let docs = docs(attrs);
+	if !docs.is_empty() {
+		schema["description"] = docs.into();
+	}
+	schema
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_schema() {
+		let schema: Value = serde_json::from_str(
+			&schema(
+				r#"
+				/// Looks up a user.
+				async fn get_user(id: i64) -> Result<Option<User>, tracked::StringError> {}
+				#[backend(instantiate(T = String))]
+				fn watch<T>(key: T) -> impl Stream<Item = Vec<T>> {}
+				struct User {
+					/// Full name.
+					name: String,
+					tags: HashSet<String>,
+				}
+				enum Shape {
+					Empty,
+					Rect(f64, f64),
+				}
+				"#,
+			)
+			.unwrap(),
+		)
+		.unwrap();
+
+		assert_eq!(
+			schema["functions"][0],
+			json!({
+				"name": "get_user",
+				"docs": "Looks up a user.",
+				"params": [{ "name": "id", "type": "i64", "schema": { "type": "integer", "format": "i64" } }],
+				"returns": {
+					"type": "Option<User>",
+					"schema": { "anyOf": [{ "$ref": "#/$defs/User" }, { "type": "null" }] },
+				},
+				"stream": false,
+				"error": { "type": "tracked::StringError", "schema": {} },
+			})
+		);
+		assert_eq!(schema["functions"][1]["name"], "watch<String>");
+		assert_eq!(schema["functions"][1]["params"][0]["type"], "String");
+		assert_eq!(schema["functions"][1]["returns"]["type"], "Vec<String>");
+		assert_eq!(schema["functions"][1]["stream"], true);
+		assert_eq!(
+			schema["$defs"]["User"],
+			json!({
+				"type": "object",
+				"properties": {
+					"name": { "type": "string", "description": "Full name." },
+					"tags": { "type": "array", "items": { "type": "string" }, "uniqueItems": true },
+				},
+				"required": ["name", "tags"],
+			})
+		);
+		assert_eq!(
+			schema["$defs"]["Shape"]["oneOf"][1]["properties"]["value"],
+			json!({
+				"type": "array",
+				"prefixItems": [{ "type": "number", "format": "f64" }, { "type": "number", "format": "f64" }],
+				"minItems": 2,
+				"items": false,
+			})
+		);
+	}
+}
@@ -0,0 +1,747 @@
+//! A TypeScript client for the API in `backend_api.rs`, which speaks the Turbocharger protocol over its own WebSocket,
+//! so pages can call the backend without loading the WASM module.
+//!
+//! The client has no dependencies: it includes the runtime in `client.ts`, which encodes requests and decodes
+//! responses like bincode does. 64- and 128-bit integers are `bigint`s, `Option`s are `T | undefined`, maps are `Map`s,
+//! and enums are `{ type, value }` discriminated unions, like with `#[backend(js)]`. Streaming functions return async
+//! iterators. Functions that use types the client can't encode, because they aren't in `backend_api.rs`, are left out.
+
+use crate::api::{
+	docs, last_segment, path_type_args, split_result, stream_item, substitute, type_string, Api,
+	Function,
+};
+use std::collections::{BTreeMap, HashSet};
+use syn::ext::IdentExt;
+
+const RUNTIME: &str = include_str!("client.ts");
+
+/// Names that generated code uses, which parameters and types are renamed to avoid.
+const RESERVED: &[&str] = &[
+	"arguments",
+	"await",
+	"break",
+	"case",
+	"catch",
+	"class",
+	"const",
+	"continue",
+	"debugger",
+	"default",
+	"delete",
+	"do",
+	"else",
+	"enum",
+	"eval",
+	"export",
+	"extends",
+	"false",
+	"finally",
+	"for",
+	"function",
+	"if",
+	"implements",
+	"import",
+	"in",
+	"instanceof",
+	"interface",
+	"let",
+	"new",
+	"null",
+	"package",
+	"private",
+	"protected",
+	"public",
+	"return",
+	"static",
+	"super",
+	"switch",
+	"this",
+	"throw",
+	"true",
+	"try",
+	"typeof",
+	"var",
+	"void",
+	"while",
+	"with",
+	"yield",
+	"w",
+	"r",
+	"v",
+	"encode",
+	"decode",
+	"unwrap",
+	"connection",
+	"Writer",
+	"Reader",
+	"Encoder",
+	"Decoder",
+	"Result",
+	"Connection",
+	"BackendError",
+];
+
+/// The TypeScript client for `backend_api_rs`, the contents of a `backend_api.rs`.
+pub fn typescript(backend_api_rs: &str) -> syn::Result<String> {
+	let api = Api::parse(backend_api_rs)?;
+	let generator = Generator::new(&api);
+
+	let mut output = String::from(
+		"// This file is auto-generated by Turbocharger from backend_api.rs.\n// To regenerate: \"turbocharger-api typescript backend_api.rs\"\n\n",
+	);
+	output.push_str(RUNTIME);
+	output.push_str("\n/** The connection the functions below call the server with. */\nexport const connection = new Connection();\n");
+
+	for (path, item) in &api.types {
+		output.push('\n');
+		output.push_str(&match generator.unsupported.contains(path) {
+			true => {
+				format!("// `{}` is left out, because it uses types that aren't in backend_api.rs.\n", path)
+			}
+			false => generator.item(path, item),
+		});
+	}
+
+	// Functions in modules and `impl` blocks are grouped into objects named after them.
+	let mut root = Group::default();
+	for (name, function) in &api.fns {
+		let group = function
+			.path
+			.iter()
+			.chain(&function.service)
+			.fold(&mut root, |group, segment| group.groups.entry(segment.clone()).or_default());
+		group.fns.push(generator.function(name, function));
+	}
+	for (name, function) in root.fns {
+		output.push('\n');
+		output.push_str(&match function {
+			Ok(function) => format!("{}export function {}\n", function.docs, function.body),
+			Err(e) => format!("// `{}` is left out, because {}\n", name, e),
+		});
+	}
+	for (name, group) in root.groups {
+		output.push_str(&format!("\nexport const {} = {};\n", ts_ident(&name), group.object(0)));
+	}
+	Ok(output)
+}
+
+/// The functions of a module or `impl` block, and its nested modules.
+#[derive(Default)]
+struct Group {
+	fns: Vec<(String, Result<TsFunction, String>)>,
+	groups: BTreeMap<String, Group>,
+}
+
+impl Group {
+	/// An object literal with the functions as methods, `indent` tabs deep.
+	fn object(&self, indent: usize) -> String {
+		let tabs = "\t".repeat(indent + 1);
+		let mut output = String::from("{\n");
+		for (name, function) in &self.fns {
+			match function {
+				Ok(function) => {
+					let docs = function.docs.lines().map(|line| format!("{}{}\n", tabs, line));
+					output.extend(docs);
+					output.push_str(&format!(
+						"{}{},\n",
+						tabs,
+						function.body.replace('\n', &format!("\n{}", tabs))
+					));
+				}
+				Err(e) => output.push_str(&format!("{}// `{}` is left out, because {}\n", tabs, name, e)),
+			}
+		}
+		for (name, group) in &self.groups {
+			output.push_str(&format!("{}{}: {},\n", tabs, ts_ident(name), group.object(indent + 1)));
+		}
+		output.push_str(&"\t".repeat(indent));
+		output.push('}');
+		output
+	}
+}
+
+struct TsFunction {
+	/// JSDoc comment, with a trailing newline, or empty.
+	docs: String,
+	/// The function without `function`, e.g. `add(a: number): Promise<number> { ... }`.
+	body: String,
+}
+
+/// How a Rust type is encoded.
+enum Ty<'a> {
+	/// Encoded by the `Writer` and `Reader` method of this name, as this TypeScript type.
+	Primitive(&'static str, &'static str),
+	Unit,
+	Option(&'a syn::Type),
+	Seq(&'a syn::Type),
+	Array(&'a syn::Type, usize),
+	Map(&'a syn::Type, &'a syn::Type),
+	Tuple(Vec<&'a syn::Type>),
+	Result(&'a syn::Type, &'a syn::Type),
+	/// A struct or enum of the API, by path, with its type arguments.
+	Def(String, Vec<&'a syn::Type>),
+	/// A type parameter of the struct or enum being generated.
+	Param(String),
+}
+
+struct Generator<'a> {
+	api: &'a Api,
+	/// TypeScript names of the structs and enums, by path: their Rust names, or, if several have the same name, their
+	/// paths with `_` instead of `::`.
+	names: BTreeMap<String, String>,
+	/// Structs and enums that use types that aren't in the API.
+	unsupported: HashSet<String>,
+}
+
+impl<'a> Generator<'a> {
+	fn new(api: &'a Api) -> Self {
+		let names = api
+			.types
+			.keys()
+			.map(|path| {
+				let name = last_segment(path);
+				let is_unique = api.types.keys().filter(|other| last_segment(other) == name).count() == 1;
+				let name = if is_unique { name.to_string() } else { path.replace("::", "_") };
+				(path.clone(), ts_ident(&name))
+			})
+			.collect();
+		let mut generator = Self { api, names, unsupported: HashSet::new() };
+
+		// Until no more are found, since types can use each other.
+		loop {
+			let unsupported: Vec<_> = api
+				.types
+				.iter()
+				.filter(|(path, item)| {
+					!generator.unsupported.contains(*path) && generator.check_item(item).is_err()
+				})
+				.map(|(path, _)| path.clone())
+				.collect();
+			if unsupported.is_empty() {
+				break generator;
+			}
+			generator.unsupported.extend(unsupported);
+		}
+	}
+
+	fn classify<'t>(&self, ty: &'t syn::Type, params: &[String]) -> Result<Ty<'t>, String> {
+		let unsupported = || Err(format!("`{}` isn't in backend_api.rs", type_string(ty)));
+		let path = match ty {
+			syn::Type::Reference(syn::TypeReference { elem, .. })
+			| syn::Type::Paren(syn::TypeParen { elem, .. })
+			| syn::Type::Group(syn::TypeGroup { elem, .. }) => return self.classify(elem, params),
+			syn::Type::Slice(syn::TypeSlice { elem, .. }) => return Ok(Ty::Seq(elem)),
+			syn::Type::Array(syn::TypeArray {
+				elem,
+				len: syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Int(len), .. }),
+				..
+			}) => return len.base10_parse().map(|len| Ty::Array(elem, len)).or_else(|_| unsupported()),
+			syn::Type::Tuple(syn::TypeTuple { elems, .. }) if elems.is_empty() => return Ok(Ty::Unit),
+			syn::Type::Tuple(syn::TypeTuple { elems, .. }) => return Ok(Ty::Tuple(elems.iter().collect())),
+			syn::Type::Path(syn::TypePath { qself: None, path }) => path,
+			_ => return unsupported(),
+		};
+
+		let segment = path.segments.last().unwrap();
+		let ident = segment.ident.to_string();
+		let args = path_type_args(segment);
+		if path.segments.len() == 1 && args.is_empty() && params.contains(&ident) {
+			return Ok(Ty::Param(ident));
+		}
+		if let Some(def_path) = self.api.type_path(path) {
+			return match self.unsupported.contains(&def_path) {
+				true => Err(format!("`{}` uses types that aren't in backend_api.rs", def_path)),
+				false => Ok(Ty::Def(def_path, args)),
+			};
+		}
+		Ok(match (ident.as_str(), args.as_slice()) {
+			("u8", []) => Ty::Primitive("u8", "number"),
+			("i8", []) => Ty::Primitive("i8", "number"),
+			("u16", []) => Ty::Primitive("u16", "number"),
+			("i16", []) => Ty::Primitive("i16", "number"),
+			("u32", []) => Ty::Primitive("u32", "number"),
+			("i32", []) => Ty::Primitive("i32", "number"),
+			("u64" | "usize", []) => Ty::Primitive("u64", "bigint"),
+			("i64" | "isize", []) => Ty::Primitive("i64", "bigint"),
+			("u128", []) => Ty::Primitive("u128", "bigint"),
+			("i128", []) => Ty::Primitive("i128", "bigint"),
+			("f32", []) => Ty::Primitive("f32", "number"),
+			("f64", []) => Ty::Primitive("f64", "number"),
+			("bool", []) => Ty::Primitive("bool", "boolean"),
+			("String" | "str", []) => Ty::Primitive("string", "string"),
+			("char", []) => Ty::Primitive("char", "string"),
+			// `tracked::StringError` is a `String`.
+			("StringError", []) => Ty::Primitive("string", "string"),
+			("Box" | "Arc" | "Rc" | "Cow", [.., ty]) => return self.classify(ty, params),
+			("Option", [ty]) => Ty::Option(ty),
+			("Vec" | "VecDeque" | "HashSet" | "BTreeSet", [ty]) => Ty::Seq(ty),
+			("HashMap" | "BTreeMap", [k, v]) => Ty::Map(k, v),
+			("Result", [t, e]) => Ty::Result(t, e),
+			_ => return unsupported(),
+		})
+	}
+
+	/// Checks that `ty` and the types it contains can be encoded.
+	fn check(&self, ty: &syn::Type, params: &[String]) -> Result<(), String> {
+		match self.classify(ty, params)? {
+			Ty::Primitive(..) | Ty::Unit | Ty::Param(_) => Ok(()),
+			Ty::Option(ty) | Ty::Seq(ty) | Ty::Array(ty, _) => self.check(ty, params),
+			Ty::Map(a, b) | Ty::Result(a, b) => self.check(a, params).and_then(|_| self.check(b, params)),
+			Ty::Tuple(tys) | Ty::Def(_, tys) => tys.into_iter().try_for_each(|ty| self.check(ty, params)),
+		}
+	}
+
+	fn check_item(&self, item: &syn::Item) -> Result<(), String> {
+		let params = type_params(item);
+		item_fields(item).try_for_each(|field| self.check(&field.ty, &params))
+	}
+
+	fn ts_type(&self, ty: &syn::Type, params: &[String]) -> String {
+		match self.classify(ty, params).unwrap() {
+			Ty::Primitive(_, ts) => ts.into(),
+			Ty::Unit => "null".into(),
+			Ty::Option(ty) => format!("{} | undefined", self.ts_type(ty, params)),
+			Ty::Seq(ty) | Ty::Array(ty, _) => {
+				let ts = self.ts_type(ty, params);
+				match ts.contains(' ') {
+					true => format!("({})[]", ts),
+					false => format!("{}[]", ts),
+				}
+			}
+			Ty::Map(k, v) => format!("Map<{}, {}>", self.ts_type(k, params), self.ts_type(v, params)),
+			Ty::Tuple(tys) => format!("[{}]", self.ts_types(&tys, params)),
+			Ty::Result(t, e) => format!("Result<{}, {}>", self.ts_type(t, params), self.ts_type(e, params)),
+			Ty::Def(path, args) if args.is_empty() => self.names[&path].clone(),
+			Ty::Def(path, args) => format!("{}<{}>", self.names[&path], self.ts_types(&args, params)),
+			Ty::Param(param) => param,
+		}
+	}
+
+	fn ts_types(&self, tys: &[&syn::Type], params: &[String]) -> String {
+		tys.iter().map(|ty| self.ts_type(ty, params)).collect::<Vec<_>>().join(", ")
+	}
+
+	/// An `Encoder` of `ty`.
+	fn encoder(&self, ty: &syn::Type, params: &[String]) -> String {
+		let encoders =
+			|tys: &[&syn::Type]| tys.iter().map(|ty| self.encoder(ty, params)).collect::<Vec<_>>();
+		match self.classify(ty, params).unwrap() {
+			Ty::Primitive(method, _) => format!("encode.{}", method),
+			Ty::Unit => "encode.unit".into(),
+			Ty::Option(ty) => format!("encode.option({})", self.encoder(ty, params)),
+			Ty::Seq(ty) => format!("encode.seq({})", self.encoder(ty, params)),
+			Ty::Array(ty, _) => format!("encode.array({})", self.encoder(ty, params)),
+			Ty::Map(k, v) => format!("encode.map({}, {})", self.encoder(k, params), self.encoder(v, params)),
+			Ty::Tuple(tys) => format!("encode.tuple({})", encoders(&tys).join(", ")),
+			Ty::Result(t, e) => {
+				format!("encode.result({}, {})", self.encoder(t, params), self.encoder(e, params))
+			}
+			Ty::Def(path, args) if args.is_empty() => format!("encode_{}", self.names[&path]),
+			Ty::Def(path, args) => format!(
+				"(w: Writer, v: {}) => encode_{}(w, v, {})",
+				self.ts_type(ty, params),
+				self.names[&path],
+				encoders(&args).join(", ")
+			),
+			Ty::Param(param) => format!("encode_{}", param),
+		}
+	}
+
+	/// A `Decoder` of `ty`.
+	fn decoder(&self, ty: &syn::Type, params: &[String]) -> String {
+		let decoders =
+			|tys: &[&syn::Type]| tys.iter().map(|ty| self.decoder(ty, params)).collect::<Vec<_>>();
+		match self.classify(ty, params).unwrap() {
+			Ty::Primitive(method, _) => format!("decode.{}", method),
+			Ty::Unit => "decode.unit".into(),
+			Ty::Option(ty) => format!("decode.option({})", self.decoder(ty, params)),
+			Ty::Seq(ty) => format!("decode.seq({})", self.decoder(ty, params)),
+			Ty::Array(ty, len) => format!("decode.array({}, {})", self.decoder(ty, params), len),
+			Ty::Map(k, v) => format!("decode.map({}, {})", self.decoder(k, params), self.decoder(v, params)),
+			Ty::Tuple(tys) => format!("decode.tuple({})", decoders(&tys).join(", ")),
+			Ty::Result(t, e) => {
+				format!("decode.result({}, {})", self.decoder(t, params), self.decoder(e, params))
+			}
+			Ty::Def(path, args) if args.is_empty() => format!("decode_{}", self.names[&path]),
+			Ty::Def(path, args) => {
+				format!("(r: Reader) => decode_{}(r, {})", self.names[&path], decoders(&args).join(", "))
+			}
+			Ty::Param(param) => format!("decode_{}", param),
+		}
+	}
+
+	/// A statement that writes `value`, of type `ty`, to `w`.
+	fn encode(&self, ty: &syn::Type, value: &str, params: &[String]) -> String {
+		match self.classify(ty, params).unwrap() {
+			Ty::Primitive(method, _) => format!("w.{}({});", method, value),
+			Ty::Unit => String::new(),
+			Ty::Def(path, args) if !args.is_empty() => {
+				let encoders: Vec<_> = args.iter().map(|ty| self.encoder(ty, params)).collect();
+				format!("encode_{}(w, {}, {});", self.names[&path], value, encoders.join(", "))
+			}
+			_ => format!("{}(w, {});", self.encoder(ty, params), value),
+		}
+	}
+
+	/// An expression that reads a `ty` from `r`.
+	fn decode(&self, ty: &syn::Type, params: &[String]) -> String {
+		match self.classify(ty, params).unwrap() {
+			Ty::Primitive(method, _) => format!("r.{}()", method),
+			Ty::Unit => "null".into(),
+			Ty::Def(path, args) if !args.is_empty() => {
+				let decoders: Vec<_> = args.iter().map(|ty| self.decoder(ty, params)).collect();
+				format!("decode_{}(r, {})", self.names[&path], decoders.join(", "))
+			}
+			_ => format!("{}(r)", self.decoder(ty, params)),
+		}
+	}
+
+	/// The TypeScript type of a struct or enum, and its `encode_` and `decode_` functions.
+	fn item(&self, path: &str, item: &syn::Item) -> String {
+		let attrs = match item {
+			syn::Item::Struct(s) => &s.attrs,
+			syn::Item::Enum(e) => &e.attrs,
+			_ => unreachable!(),
+		};
+		let name = &self.names[path];
+		let params = type_params(item);
+		let (generics, encode_params, decode_params) = match params.is_empty() {
+			true => (String::new(), String::new(), String::new()),
+			false => (
+				format!("<{}>", params.join(", ")),
+				params.iter().map(|p| format!(", encode_{}: Encoder<{}>", p, p)).collect(),
+				params.iter().map(|p| format!(", decode_{}: Decoder<{}>", p, p)).collect(),
+			),
+		};
+
+		let (definition, encode, decode) = match item {
+			syn::Item::Struct(s) => {
+				let definition = match &s.fields {
+					syn::Fields::Named(_) => {
+						format!("export interface {}{} {}", name, generics, self.fields_type(&s.fields, &params, 0))
+					}
+					fields => {
+						format!("export type {}{} = {};", name, generics, self.fields_type(fields, &params, 0))
+					}
+				};
+				let encode = self.encode_fields(&s.fields, "v", &params, 1);
+				let decode = format!("\treturn {};\n", self.decode_fields(&s.fields, &params, 1));
+				(definition, encode, decode)
+			}
+			syn::Item::Enum(e) => {
+				let variants: Vec<_> = e
+					.variants
+					.iter()
+					.map(|variant| {
+						let value = match &variant.fields {
+							syn::Fields::Unit => String::new(),
+							fields => format!("; value: {}", self.fields_type(fields, &params, 1)),
+						};
+						format!("{}\t| {{ type: \"{}\"{} }}", ts_docs(&variant.attrs, 1), variant.ident, value)
+					})
+					.collect();
+				let definition = format!("export type {}{} =\n{};", name, generics, variants.join("\n"));
+
+				let mut encode = String::from("\tswitch (v.type) {\n");
+				let mut decode = String::from("\tswitch (r.u32()) {\n");
+				for (i, variant) in e.variants.iter().enumerate() {
+					encode.push_str(&format!("\t\tcase \"{}\":\n\t\t\tw.u32({});\n", variant.ident, i));
+					encode.push_str(&self.encode_fields(&variant.fields, "v.value", &params, 3));
+					encode.push_str("\t\t\tbreak;\n");
+					let value = match &variant.fields {
+						syn::Fields::Unit => String::new(),
+						fields => format!(", value: {}", self.decode_fields(fields, &params, 3)),
+					};
+					decode.push_str(&format!(
+						"\t\tcase {}:\n\t\t\treturn {{ type: \"{}\"{} }};\n",
+						i, variant.ident, value
+					));
+				}
+				encode.push_str("\t}\n");
+				decode.push_str(&format!(
+					"\t\tdefault:\n\t\t\tthrow new Error(\"turbocharger: invalid variant of {}\");\n\t}}\n",
+					name
+				));
+				(definition, encode, decode)
+			}
+			_ => unreachable!(),
+		};
+
+		format!(
+			"{docs}{definition}\n\nfunction encode_{name}{generics}(w: Writer, v: {name}{generics}{encode_params}) {{\n{encode}}}\n\nfunction decode_{name}{generics}(r: Reader{decode_params}): {name}{generics} {{\n{decode}}}\n",
+			docs = ts_docs(attrs, 0),
+			definition = definition,
+			name = name,
+			generics = generics,
+			encode_params = encode_params,
+			encode = encode,
+			decode_params = decode_params,
+			decode = decode,
+		)
+	}
+
+	/// The TypeScript type of the fields of a struct or enum variant: an object, a newtype's value, a tuple, or `null`.
+	fn fields_type(&self, fields: &syn::Fields, params: &[String], indent: usize) -> String {
+		let tabs = "\t".repeat(indent);
+		match fields {
+			syn::Fields::Named(fields) => {
+				let fields: Vec<_> = fields
+					.named
+					.iter()
+					.map(|f| {
+						format!(
+							"{}{}\t{}: {};\n",
+							ts_docs(&f.attrs, indent + 1),
+							tabs,
+							f.ident.as_ref().unwrap().unraw(),
+							self.ts_type(&f.ty, params)
+						)
+					})
+					.collect();
+				format!("{{\n{}{}}}", fields.concat(), tabs)
+			}
+			syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+				self.ts_type(&fields.unnamed[0].ty, params)
+			}
+			syn::Fields::Unnamed(fields) => {
+				let tys: Vec<_> = fields.unnamed.iter().map(|f| &f.ty).collect();
+				format!("[{}]", self.ts_types(&tys, params))
+			}
+			syn::Fields::Unit => "null".into(),
+		}
+	}
+
+	/// Statements that write the fields of `value`.
+	fn encode_fields(
+		&self,
+		fields: &syn::Fields,
+		value: &str,
+		params: &[String],
+		indent: usize,
+	) -> String {
+		let tabs = "\t".repeat(indent);
+		let statements: Vec<_> = match fields {
+			syn::Fields::Named(fields) => fields
+				.named
+				.iter()
+				.map(|f| {
+					self.encode(&f.ty, &format!("{}.{}", value, f.ident.as_ref().unwrap().unraw()), params)
+				})
+				.collect(),
+			syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+				vec![self.encode(&fields.unnamed[0].ty, value, params)]
+			}
+			syn::Fields::Unnamed(fields) => fields
+				.unnamed
+				.iter()
+				.enumerate()
+				.map(|(i, f)| self.encode(&f.ty, &format!("{}[{}]", value, i), params))
+				.collect(),
+			syn::Fields::Unit => vec![],
+		};
+		statements.into_iter().filter(|s| !s.is_empty()).map(|s| format!("{}{}\n", tabs, s)).collect()
+	}
+
+	/// An expression that reads the fields of a struct or enum variant.
+	fn decode_fields(&self, fields: &syn::Fields, params: &[String], indent: usize) -> String {
+		let tabs = "\t".repeat(indent);
+		match fields {
+			syn::Fields::Named(fields) => {
+				let fields: Vec<_> = fields
+					.named
+					.iter()
+					.map(|f| {
+						format!("{}\t{}: {},\n", tabs, f.ident.as_ref().unwrap().unraw(), self.decode(&f.ty, params))
+					})
+					.collect();
+				format!("{{\n{}{}}}", fields.concat(), tabs)
+			}
+			syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+				self.decode(&fields.unnamed[0].ty, params)
+			}
+			syn::Fields::Unnamed(fields) => {
+				let values: Vec<_> = fields.unnamed.iter().map(|f| self.decode(&f.ty, params)).collect();
+				format!("[{}]", values.join(", "))
+			}
+			syn::Fields::Unit => "null".into(),
+		}
+	}
+
+	/// The function calling `name`, or why it's left out.
+	fn function(&self, name: &str, function: &Function) -> (String, Result<TsFunction, String>) {
+		let mut ts_name = function.sig.ident.unraw().to_string();
+		for (_, ty) in &function.type_args {
+			ts_name.push('_');
+			ts_name.extend(type_string(ty).chars().map(|c| if c.is_alphanumeric() { c } else { '_' }));
+		}
+		let ts_name = ts_ident(&ts_name);
+
+		let params: Vec<_> = function
+			.sig
+			.inputs
+			.iter()
+			.filter_map(|input| match input {
+				syn::FnArg::Typed(pat_type) => Some(pat_type),
+				syn::FnArg::Receiver(_) => None,
+			})
+			.enumerate()
+			.map(|(i, pat_type)| {
+				let name = match &*pat_type.pat {
+					syn::Pat::Ident(pat) => ts_ident(&pat.ident.unraw().to_string()),
+					_ => format!("arg{}", i),
+				};
+				(name, substitute(&pat_type.ty, &function.type_args))
+			})
+			.collect();
+		let output = match &function.sig.output {
+			syn::ReturnType::Type(_, ty) => substitute(ty, &function.type_args),
+			syn::ReturnType::Default => syn::parse_quote!(()),
+		};
+		let stream_item = stream_item(&output);
+		let returned = stream_item.clone().unwrap_or(output);
+
+		if let Err(e) =
+			params.iter().map(|(_, ty)| ty).chain([&returned]).try_for_each(|ty| self.check(ty, &[]))
+		{
+			return (ts_name, Err(e));
+		}
+
+		let (ok, error) = split_result(&returned);
+		let response = match (error, self.decode(&returned, &[])) {
+			(Some(_), decode) => format!("(r) => unwrap({})", decode),
+			(None, decode) if decode == "null" => "() => null".to_string(),
+			(None, decode) => format!("(r) => {}", decode),
+		};
+		let statements: Vec<_> =
+			params.iter().map(|(name, ty)| self.encode(ty, name, &[])).filter(|s| !s.is_empty()).collect();
+		let encode_params = match statements.is_empty() {
+			true => "() => {}".to_string(),
+			false => format!("(w) => {{\n\t\t{}\n\t}}", statements.join("\n\t\t")),
+		};
+		let mut signature: Vec<_> =
+			params.iter().map(|(name, ty)| format!("{}: {}", name, self.ts_type(ty, &[]))).collect();
+		let (method, return_type, options) = match stream_item {
+			Some(_) => ("stream", format!("AsyncIterableIterator<{}>", self.ts_type(&ok, &[])), None),
+			None => {
+				// calls take `{ signal }` after their parameters, so they can be aborted
+				let mut options = "options".to_string();
+				while params.iter().any(|(name, _)| *name == options) {
+					options.push('_');
+				}
+				signature.push(format!("{}?: CallOptions", options));
+				("call", format!("Promise<{}>", self.ts_type(&ok, &[])), Some(options))
+			}
+		};
+		let options = options.map(|options| format!(", {}", options)).unwrap_or_default();
+
+		let body = format!(
+			"{}({}): {} {{\n\treturn connection.{}({:?}, {}, {}{});\n}}",
+			ts_name,
+			signature.join(", "),
+			return_type,
+			method,
+			name,
+			encode_params,
+			response,
+			options
+		);
+		(ts_name, Ok(TsFunction { docs: ts_docs(&function.attrs, 0), body }))
+	}
+}
+
+fn type_params(item: &syn::Item) -> Vec<String> {
+	let generics = match item {
+		syn::Item::Struct(s) => &s.generics,
+		syn::Item::Enum(e) => &e.generics,
+		_ => unreachable!(),
+	};
+	generics.type_params().map(|param| param.ident.to_string()).collect()
+}
+
+fn item_fields(item: &syn::Item) -> Box<dyn Iterator<Item = &syn::Field> + '_> {
+	match item {
+		syn::Item::Struct(s) => Box::new(s.fields.iter()),
+		syn::Item::Enum(e) => Box::new(e.variants.iter().flat_map(|variant| variant.fields.iter())),
+		_ => unreachable!(),
+	}
+}
+
+/// `name`, renamed if generated code uses it.
+fn ts_ident(name: &str) -> String {
+	match RESERVED.contains(&name) {
+		true => format!("{}_", name),
+		false => name.to_string(),
+	}
+}
+
+/// The doc comment in `attrs` as a JSDoc comment, `indent` tabs deep, or nothing.
+fn ts_docs(attrs: &[syn::Attribute], indent: usize) -> String {
+	let docs = docs(attrs);
+	if docs.is_empty() {
+		return String::new();
+	}
+	let tabs = "\t".repeat(indent);
+	let lines: Vec<_> =
+		docs.replace("*/", "*\\/").lines().map(|line| format!("{} * {}", tabs, line)).collect();
+	format!("{}/**\n{}\n{} */\n", tabs, lines.join("\n"), tabs)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_typescript() {
+		let ts = typescript(
+			r#"
+			/// Looks up a user.
+			async fn get_user(id: i64) -> Result<Option<User>, tracked::StringError> {}
+			#[backend(instantiate(T = String))]
+			fn watch<T>(key: T) -> impl Stream<Item = Vec<T>> {}
+			fn upload(file: std::fs::File) {}
+			async fn search(options: String) -> Vec<String> {}
+			mod users {
+				fn rename(user: User, default: String) -> () {}
+			}
+			struct User {
+				/// Full name.
+				name: String,
+				tags: HashMap<String, [u8; 4]>,
+			}
+			enum Shape {
+				Empty,
+				Rect(f64, f64),
+			}
+			"#,
+		)
+		.unwrap();
+
+		assert!(ts.contains("export class Connection {"));
+		assert!(ts.contains(
+			"/**\n * Looks up a user.\n */\nexport function get_user(id: bigint, options?: CallOptions): Promise<User | undefined> {\n\treturn connection.call(\"get_user\", (w) => {\n\t\tw.i64(id);\n\t}, (r) => unwrap(decode.result(decode.option(decode_User), decode.string)(r)), options);\n}\n"
+		));
+		assert!(ts.contains(
+			"export function watch_String(key: string): AsyncIterableIterator<string[]> {\n\treturn connection.stream(\"watch<String>\","
+		));
+		assert!(ts.contains("// `upload` is left out, because `std::fs::File` isn't in backend_api.rs"));
+		assert!(ts.contains(
+			"export function search(options: string, options_?: CallOptions): Promise<string[]> {"
+		));
+		assert!(ts.contains(
+			"export const users = {\n\trename(user: User, default_: string, options?: CallOptions): Promise<null> {"
+		));
+		assert!(ts
+			.contains("\t\tencode_User(w, user);\n\t\t\tw.string(default_);\n\t\t}, () => null, options);"));
+		assert!(ts.contains(
+			"export interface User {\n\t/**\n\t * Full name.\n\t */\n\tname: string;\n\ttags: Map<string, number[]>;\n}"
+		));
+		assert!(ts.contains("\tencode.map(encode.string, encode.array(encode.u8))(w, v.tags);\n"));
+		assert!(ts.contains("\t\ttags: decode.map(decode.string, decode.array(decode.u8, 4))(r),\n"));
+		assert!(ts.contains(
+			"export type Shape =\n\t| { type: \"Empty\" }\n\t| { type: \"Rect\"; value: [number, number] };"
+		));
+		assert!(ts.contains(
+			"\t\tcase \"Rect\":\n\t\t\tw.u32(1);\n\t\t\tw.f64(v.value[0]);\n\t\t\tw.f64(v.value[1]);\n"
+		));
+	}
+}
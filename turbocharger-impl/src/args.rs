@@ -0,0 +1,214 @@
+use syn::{parse::ParseStream, punctuated::Punctuated, Token};
+
+/// Arguments of `#[backend(...)]`.
+#[derive(Default)]
+pub struct BackendArgs {
+	/// `js`: also generate a JS frontend binding.
+	pub js: bool,
+	/// `plain`: with `js`, pass structs to and from JS as plain objects with TypeScript interfaces, rather than as
+	/// `wasm-bindgen` classes; on a function, pass all of its parameters and its return value that way.
+	pub plain: bool,
+	/// `connection_local`: on an `impl` block, keep one receiver per connection instead of resolving it from shared state.
+	pub connection_local: bool,
+	/// `instantiate(T = User, T = Order)`: monomorphizations of a generic function; each one assigns every type parameter.
+	pub instantiate: Vec<Vec<(syn::Ident, syn::Type)>>,
+	/// `name = "users.list"`: a dispatch name to use instead of the module path and function name, so the function can
+	/// be moved or renamed without breaking clients. On an `impl` block or `mod`, it prefixes the names of its items.
+	pub name: Option<String>,
+	/// `invalidates = "get_person"` or `invalidates("get_person", "list_people")`: backend functions whose results a
+	/// call of this one changes, so Dioxus hooks showing them run again when it completes. Names match dispatch names
+	/// exactly or by their end, e.g. `api::get_person`.
+	pub invalidates: Vec<String>,
+	/// Not an argument; the `name` prefix of the enclosing `impl` block or `mod`, e.g. `api::users`.
+	pub namespace: Option<String>,
+}
+
+impl syn::parse::Parse for BackendArgs {
+	fn parse(input: ParseStream) -> syn::Result<Self> {
+		let mut args = Self::default();
+
+		for meta in Punctuated::<syn::Meta, Token![,]>::parse_terminated(input)? {
+			match &meta {
+				syn::Meta::Path(path) if path.is_ident("js") => args.js = true,
+				syn::Meta::Path(path) if path.is_ident("plain") => args.plain = true,
+				syn::Meta::Path(path) if path.is_ident("connection_local") => args.connection_local = true,
+				syn::Meta::NameValue(syn::MetaNameValue {
+					path,
+					value: syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(name), .. }),
+					..
+				})
+					if path.is_ident("name") =>
+				{
+					if name.value().is_empty() {
+						return Err(syn::Error::new_spanned(name, "Dispatch names can't be empty."));
+					}
+					args.name = Some(name.value());
+				}
+				syn::Meta::NameValue(syn::MetaNameValue {
+					path,
+					value: syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(name), .. }),
+					..
+				})
+					if path.is_ident("invalidates") =>
+				{
+					args.invalidates.push(invalidated_name(name)?);
+				}
+				syn::Meta::List(list) if list.path.is_ident("invalidates") => {
+					for name in list.parse_args_with(Punctuated::<syn::LitStr, Token![,]>::parse_terminated)? {
+						args.invalidates.push(invalidated_name(&name)?);
+					}
+				}
+				syn::Meta::List(list) if list.path.is_ident("instantiate") => {
+					args.instantiate.extend(
+						list
+							.parse_args_with(|input: ParseStream| {
+								Punctuated::<Instantiation, Token![,]>::parse_terminated(input)
+							})?
+							.into_iter()
+							.map(|instantiation| instantiation.0),
+					);
+				}
+				_ => return Err(syn::Error::new_spanned(meta, "Unknown #[backend] argument.")),
+			}
+		}
+
+		Ok(args)
+	}
+}
+
+impl BackendArgs {
+	/// Arguments of an item in a `#[backend] mod`, which are the module's arguments plus the item's own.
+	pub fn merged(&self, item_args: Self) -> Self {
+		Self {
+			js: self.js || item_args.js,
+			plain: self.plain || item_args.plain,
+			connection_local: item_args.connection_local,
+			instantiate: item_args.instantiate,
+			name: item_args.name,
+			invalidates: item_args.invalidates,
+			namespace: self.namespace.clone(),
+		}
+	}
+
+	/// The `namespace` of the items of an `impl` block or `mod` named `ident` with these arguments.
+	pub fn namespace_for(&self, ident: &syn::Ident) -> Option<String> {
+		match (&self.name, &self.namespace) {
+			(Some(name), _) => Some(name.clone()),
+			(None, Some(namespace)) => Some(format!("{}::{}", namespace, ident)),
+			(None, None) => None,
+		}
+	}
+
+	/// The `name` and `instantiate(...)` arguments, as written, for `backend_api.rs`.
+	pub fn api_attr(&self) -> Option<syn::Attribute> {
+		if self.name.is_none() && self.instantiate.is_empty() {
+			return None;
+		}
+		let mut args: Vec<_> = self.name.iter().map(|name| quote::quote!(name = #name)).collect();
+		let entries = self.instantiate.iter().map(|assignments| {
+			let assignments = assignments.iter().map(|(ident, ty)| quote::quote!(#ident = #ty));
+			match assignments.len() {
+				1 => quote::quote!(#(#assignments)*),
+				_ => quote::quote!((#(#assignments),*)),
+			}
+		});
+		if !self.instantiate.is_empty() {
+			args.push(quote::quote!(instantiate(#(#entries),*)));
+		}
+		Some(syn::parse_quote!(#[backend(#(#args),*)]))
+	}
+}
+
+fn invalidated_name(name: &syn::LitStr) -> syn::Result<String> {
+	match name.value() {
+		value if value.is_empty() => {
+			Err(syn::Error::new_spanned(name, "Invalidated names can't be empty."))
+		}
+		value => Ok(value),
+	}
+}
+
+/// `T = User`, or `(K = String, V = User)` for functions with several type parameters.
+struct Instantiation(Vec<(syn::Ident, syn::Type)>);
+
+impl syn::parse::Parse for Instantiation {
+	fn parse(input: ParseStream) -> syn::Result<Self> {
+		fn assignment(input: ParseStream) -> syn::Result<(syn::Ident, syn::Type)> {
+			let ident = input.parse()?;
+			let _: Token![=] = input.parse()?;
+			Ok((ident, input.parse()?))
+		}
+
+		if input.peek(syn::token::Paren) {
+			let content;
+			syn::parenthesized!(content in input);
+			Ok(Self(
+				Punctuated::<_, Token![,]>::parse_terminated_with(&content, assignment)?.into_iter().collect(),
+			))
+		} else {
+			Ok(Self(vec![assignment(input)?]))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_backend_args() {
+		let args: BackendArgs = syn::parse_str("").unwrap();
+		assert!(!args.js && args.instantiate.is_empty());
+
+		let args: BackendArgs = syn::parse_str("js").unwrap();
+		assert!(args.js && !args.plain);
+
+		let args: BackendArgs = syn::parse_str("js, plain").unwrap();
+		assert!(args.js && args.plain);
+
+		let args: BackendArgs = syn::parse_str("instantiate(T = User, T = Vec<Order>)").unwrap();
+		assert_eq!(args.instantiate.len(), 2);
+		assert_eq!(args.instantiate[1][0].0, "T");
+		assert_eq!(
+			args.api_attr().unwrap(),
+			syn::parse_quote!(#[backend(instantiate(T = User, T = Vec<Order>))])
+		);
+
+		let args: BackendArgs = syn::parse_str("instantiate((K = String, V = User))").unwrap();
+		assert_eq!(args.instantiate[0].len(), 2);
+
+		assert!(syn::parse_str::<BackendArgs>("jss").is_err());
+
+		let args: BackendArgs = syn::parse_str("js, name = \"users.list\"").unwrap();
+		assert_eq!(args.name.as_deref(), Some("users.list"));
+		assert_eq!(args.api_attr().unwrap(), syn::parse_quote!(#[backend(name = "users.list")]));
+		assert!(syn::parse_str::<BackendArgs>("name = \"\"").is_err());
+		assert!(syn::parse_str::<BackendArgs>("name = users").is_err());
+
+		let args: BackendArgs =
+			syn::parse_str("invalidates = \"get_person\", invalidates(\"list_people\", \"api::count\")")
+				.unwrap();
+		assert_eq!(args.invalidates, ["get_person", "list_people", "api::count"]);
+		assert!(args.api_attr().is_none());
+		assert!(syn::parse_str::<BackendArgs>("invalidates = \"\"").is_err());
+		assert!(syn::parse_str::<BackendArgs>("invalidates(get_person)").is_err());
+
+		let module = BackendArgs {
+			js: true,
+			plain: true,
+			name: Some("api".into()),
+			namespace: Some("api".into()),
+			..Default::default()
+		};
+		let merged = module.merged(syn::parse_str("instantiate(T = User)").unwrap());
+		assert!(
+			merged.js
+				&& merged.plain
+				&& merged.instantiate.len() == 1
+				&& merged.namespace.as_deref() == Some("api")
+		);
+		assert!(merged.name.is_none());
+		assert_eq!(merged.namespace_for(&syn::parse_quote!(users)).as_deref(), Some("api::users"));
+		assert_eq!(BackendArgs::default().namespace_for(&syn::parse_quote!(users)), None);
+	}
+}
@@ -45,6 +45,10 @@ pub fn extract_result(ty: &syn::Type) -> Option<&syn::Type> {
 	generic_type_with_ident(ty, "Result")
 }
 
+pub fn extract_option(ty: &syn::Type) -> Option<&syn::Type> {
+	generic_type_with_ident(ty, "Option")
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
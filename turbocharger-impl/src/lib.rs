@@ -4,7 +4,10 @@
 
 #![forbid(unsafe_code)]
 
+mod args;
 mod extract;
+mod manifest;
+mod typescript;
 
 use proc_macro_error::{abort, proc_macro_error};
 use quote::{format_ident, quote, quote_spanned};
@@ -20,9 +23,18 @@ pub fn user_agent(_: proc_macro::TokenStream) -> proc_macro::TokenStream {
 	quote!(_turbocharger_connection_info.as_ref().and_then(|ref i| i.user_agent.as_ref())).into()
 }
 
+/// Resolves shared state registered with `turbocharger::register_state` or a `StateMap` extension, e.g. `state!(AppState)`.
+#[proc_macro]
+pub fn state(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+	let ty = parse_macro_input!(input as syn::Type);
+	quote!(::turbocharger::State::< #ty >::_resolve(_turbocharger_connection_info.as_ref())).into()
+}
+
 struct ConnectionLocal {
 	pub ident: syn::Ident,
 	pub ty: syn::Type,
+	pub optional: bool,
+	pub init: Option<syn::Expr>,
 }
 
 impl syn::parse::Parse for ConnectionLocal {
@@ -30,36 +42,104 @@ impl syn::parse::Parse for ConnectionLocal {
 		let ident = input.parse()?;
 
 		let _: syn::Token![:] = input.parse()?;
+
+		let optional = input.peek(syn::Ident) && input.fork().parse::<syn::Ident>()? == "Option";
+		if optional {
+			let _: syn::Ident = input.parse()?;
+			let _: syn::Token![<] = input.parse()?;
+		}
+
 		let _: syn::Token![&] = input.parse()?;
 		let _: syn::Token![mut] = input.parse()?;
 
 		let ty = input.parse()?;
-		Ok(Self { ident, ty })
+
+		if optional {
+			let _: syn::Token![>] = input.parse()?;
+		}
+
+		let init = if input.peek(syn::Token![=]) {
+			let _: syn::Token![=] = input.parse()?;
+			Some(input.parse()?)
+		} else {
+			None
+		};
+
+		Ok(Self { ident, ty, optional, init })
 	}
 }
 
+/// Binds a value stored per connection, e.g. `connection_local!(count: &mut i32)`.
+///
+/// The value is initialized with `T::default()`, or with an optional initializer expression, which may `.await`:
+/// `connection_local!(conn: &mut Conn = Conn::open().await)`. The value is locked until the end of the function.
+/// Use `Option<&mut T>` to get `None`, rather than a panic, when the function is called directly on the server.
 #[proc_macro]
 pub fn connection_local(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-	let ConnectionLocal { ident, ty } = parse_macro_input!(input as ConnectionLocal);
+	let local = parse_macro_input!(input as ConnectionLocal);
+	let ident_str = local.ident.to_string();
+	let ty = &local.ty;
+	let lock = quote! {
+		match _turbocharger_connection_info.as_ref() {
+			Some(c) => Some(c.connection_local._lock::< #ty >( #ident_str ).await),
+			None => None,
+		}
+	};
+	local_binding(local, lock, quote!(None), "connection_local").into()
+}
+
+/// Like `connection_local!()`, but the value is kept in the client's session, so it survives reconnects.
+/// `T` must implement `Serialize` and `Deserialize`. Sessions must be enabled with `turbocharger::session::set_session_store`.
+#[proc_macro]
+pub fn session_local(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+	let local = parse_macro_input!(input as ConnectionLocal);
+	let ident_str = local.ident.to_string();
+	let ty = &local.ty;
+	let lock = quote! {
+		match _turbocharger_connection_info.as_ref().and_then(|c| c.session.as_ref()) {
+			Some(s) => Some(s._lock::< #ty >( #ident_str ).await),
+			None => None,
+		}
+	};
+	local_binding(local, lock, quote!(local._stored()), "session_local").into()
+}
+
+fn local_binding(
+	ConnectionLocal { ident, ty, optional, init }: ConnectionLocal,
+	lock: proc_macro2::TokenStream,
+	stored: proc_macro2::TokenStream,
+	macro_name: &str,
+) -> proc_macro2::TokenStream {
 	let ident_str = ident.to_string();
+	let local = format_ident!("_turbocharger_{}_{}", macro_name, ident);
+	let init = init.unwrap_or_else(|| parse_quote!(< #ty as ::std::default::Default >::default()));
+	let maybe_expect = if optional {
+		quote!()
+	} else {
+		let msg = format!(
+			"{}!({}) called without a connection; use `Option<&mut T>` for direct server-side calls",
+			macro_name, ident_str
+		);
+		quote! {
+			let #ident = #ident.expect(#msg);
+		}
+	};
 
 	quote! {
-		let mut _turbocharger_connection_local_map = match _turbocharger_connection_info.as_ref() {
-			Some(c) => Some(c.connection_local.lock().await),
-			None => None,
-		};
+		let mut #local = #lock;
 
-		let #ident = {
-			_turbocharger_connection_local_map
-				.as_mut()
-				.unwrap()
-				.entry(( #ident_str , std::any::TypeId::of::< #ty >()))
-				.or_insert_with(|| Box::new( #ty ::default()))
-				.downcast_mut::< #ty >()
-				.unwrap()
-		};
+		if let Some(local) = #local.as_mut() {
+			if local._is_empty() {
+				match #stored {
+					Some(value) => local._set(value),
+					None => local._set( #init ),
+				}
+			}
+		}
+
+		let #ident: Option<&mut #ty> = #local.as_deref_mut();
+		#maybe_expect
 	}
-	.into()
 }
 
 /// Apply this to an item to make it available on the server target only.
@@ -104,162 +184,676 @@ pub fn backend(
 	args: proc_macro::TokenStream,
 	input: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
-	backend_item(args, syn::parse_macro_input!(input as syn::Item)).into()
+	let args: args::BackendArgs = match syn::parse(args) {
+		Ok(args) => args,
+		Err(e) => abort!(e.span(), "{}", e),
+	};
+
+	let (output, api_item) = backend_item(args, syn::parse_macro_input!(input as syn::Item));
+
+	if !is_rust_analyzer() {
+		record_api_item(api_item);
+	}
+
+	output.into()
 }
 
-fn backend_item(args: proc_macro::TokenStream, orig_item: syn::Item) -> proc_macro2::TokenStream {
+/// Expands a `#[backend]` item, returning it along with its entry for `backend_api.rs`.
+fn backend_item(
+	args: args::BackendArgs,
+	orig_item: syn::Item,
+) -> (proc_macro2::TokenStream, syn::Item) {
+	if args.connection_local && !matches!(orig_item, syn::Item::Impl(_)) {
+		abort!(orig_item, "`connection_local` only applies to `impl` blocks.");
+	}
+	if args.name.is_some() && matches!(orig_item, syn::Item::Struct(_) | syn::Item::Enum(_)) {
+		abort!(orig_item, "`name` applies to functions, `impl` blocks and modules.");
+	}
+	if args.plain && !args.js {
+		abort!(orig_item, "`plain` applies to items exposed to JS."; help = "Add `js`.");
+	}
+	if !args.invalidates.is_empty() && !matches!(orig_item, syn::Item::Fn(_)) {
+		abort!(orig_item, "`invalidates` applies to functions.");
+	}
+
 	match orig_item {
 		syn::Item::Fn(orig) => backend_fn(args, orig),
-		syn::Item::Struct(orig) => backend_struct(orig),
-		// syn::Item::Mod(orig) => backend_mod(orig),
-		_ => abort!(orig_item, "Apply #[backend] to `fn` or `struct`."),
+		syn::Item::Struct(orig) => backend_struct(args, orig),
+		syn::Item::Enum(orig) => backend_enum(args, orig),
+		syn::Item::Impl(orig) => backend_impl(args, orig),
+		syn::Item::Mod(orig) => backend_mod(args, orig),
+		_ => abort!(orig_item, "Apply #[backend] to `fn`, `struct`, `enum`, `impl` or `mod`."),
+	}
+}
+
+/// Exposes the items of an inline `mod`: its `pub async fn`s (and `pub fn`s returning a stream), `pub struct`s,
+/// `pub enum`s, nested `pub mod`s, and any items marked `#[backend]`, such as `impl` blocks. The module's arguments
+/// apply to all of them, and items can add their own with `#[backend(...)]`. A module's `name` prefixes the dispatch
+/// names of its items, e.g. `api::get_person`.
+fn backend_mod(
+	args: args::BackendArgs,
+	orig_mod: syn::ItemMod,
+) -> (proc_macro2::TokenStream, syn::Item) {
+	if !args.instantiate.is_empty() {
+		abort!(orig_mod, "`instantiate` applies to functions, not modules.");
+	}
+	let content = orig_mod.content.clone();
+	let content = content
+		.unwrap_or_else(|| abort!(orig_mod, "Apply #[backend] to a `mod` with a body."; help = "Move the module's contents into `mod {} {{ ... }}`.", orig_mod.ident))
+		.1;
+
+	let syn::ItemMod { attrs, vis, ident, .. } = orig_mod;
+	let api_attr = args.api_attr();
+	let args = args::BackendArgs { namespace: args.namespace_for(&ident), ..args };
+
+	let (items, api_items): (Vec<_>, Vec<_>) =
+		content.into_iter().map(|item| backend_mod_item(&args, item)).unzip();
+	let api_items = api_items.into_iter().flatten();
+
+	let api_attrs = attrs.iter().filter(|attr| attr.path().is_ident("doc"));
+	let api_mod = parse_quote! {
+		#(#api_attrs)*
+		#api_attr
+		mod #ident {
+			#(#api_items)*
+		}
+	};
+
+	let output = quote! {
+		#(#attrs)*
+		#vis mod #ident {
+			#(#items)*
+		}
+	};
+
+	(output, api_mod)
+}
+
+fn backend_mod_item(
+	args: &args::BackendArgs,
+	mut item: syn::Item,
+) -> (proc_macro2::TokenStream, Option<syn::Item>) {
+	let is_pub = |vis: &syn::Visibility| matches!(vis, syn::Visibility::Public(_));
+	let attrs = match &mut item {
+		syn::Item::Fn(syn::ItemFn { attrs, .. })
+		| syn::Item::Struct(syn::ItemStruct { attrs, .. })
+		| syn::Item::Enum(syn::ItemEnum { attrs, .. })
+		| syn::Item::Impl(syn::ItemImpl { attrs, .. })
+		| syn::Item::Mod(syn::ItemMod { attrs, .. }) => attrs,
+		_ => return (quote!(#item), None),
+	};
+	if attrs.iter().any(|attr| {
+		["server_only", "wasm_only", "frontend"].iter().any(|name| attr.path().is_ident(name))
+	}) {
+		return (quote!(#item), None);
+	}
+
+	// The item's own `#[backend(...)]`, if any.
+	let mut item_args = None;
+	for attr in std::mem::take(attrs) {
+		if attr.path().is_ident("backend") {
+			let args = match attr.meta {
+				syn::Meta::List(_) => attr.parse_args().unwrap_or_else(|e| abort!(e.span(), "{}", e)),
+				_ => args::BackendArgs::default(),
+			};
+			item_args = Some(args);
+		} else {
+			attrs.push(attr);
+		}
+	}
+
+	let is_backend = item_args.is_some()
+		|| match &item {
+			syn::Item::Fn(f) => {
+				is_pub(&f.vis)
+					&& (f.sig.asyncness.is_some()
+						|| matches!(&f.sig.output, syn::ReturnType::Type(_, ty) if extract::extract_stream(ty).is_some()))
+			}
+			syn::Item::Struct(s) => is_pub(&s.vis),
+			syn::Item::Enum(e) => is_pub(&e.vis),
+			syn::Item::Mod(m) => is_pub(&m.vis) && m.content.is_some(),
+			_ => false,
+		};
+	if !is_backend {
+		return (quote!(#item), None);
+	}
+
+	// A module's `js` and `plain` don't apply to items that can't be called from JS.
+	let item_args = item_args.unwrap_or_default();
+	let (item_js, item_plain) = (item_args.js, item_args.plain);
+	let mut args = args.merged(item_args);
+	let is_js_capable = match &item {
+		syn::Item::Impl(_) => false,
+		syn::Item::Fn(f) => f.sig.generics.type_params().next().is_none(),
+		syn::Item::Struct(s) => s.generics.params.is_empty(),
+		syn::Item::Enum(e) => e.generics.params.is_empty(),
+		_ => true,
+	};
+	if !is_js_capable {
+		args.js = item_js;
+		args.plain = item_plain;
 	}
+	let (output, api_item) = backend_item(args, item);
+	(output, Some(api_item))
 }
 
-// fn backend_mod_item(orig_item: syn::Item) -> proc_macro2::TokenStream {
-//  match orig_item {
-//   syn::Item::Fn(orig) => backend_fn(orig),
-//   syn::Item::Struct(orig) => backend_struct(orig),
-//   orig => quote! { #orig },
-//  }
-// }
+fn backend_struct(
+	args: args::BackendArgs,
+	orig_struct: syn::ItemStruct,
+) -> (proc_macro2::TokenStream, syn::Item) {
+	let mut api_struct = orig_struct.clone();
+	api_struct.vis = parse_quote!();
+	api_struct.attrs.retain(|attr| attr.path().is_ident("doc"));
+	for field in &mut api_struct.fields {
+		field.vis = parse_quote!();
+		field.attrs.retain(|attr| attr.path().is_ident("doc"));
+	}
 
-// fn backend_mod(orig_mod: syn::ItemMod) -> proc_macro2::TokenStream {
-//  let content = orig_mod.content.clone();
+	let mut item = orig_struct.clone();
+	let attrs = std::mem::take(&mut item.attrs);
+	item.vis = parse_quote!(pub);
+	let ident = &item.ident;
 
-//  let items: Vec<_> = content
-//   .unwrap_or_else(|| abort!(orig_mod, "Apply #[backend] to a `mod` with a body."))
-//   .1
-//   .into_iter()
-//   .map(backend_mod_item)
-//   .collect();
+	// `wasm-bindgen` can't export generic structs, so those are only available to Rust frontends. Plain structs are
+	// converted to and from JS objects with `serde-wasm-bindgen`, like JS enums.
+	let (wasm_bindgen_attr, wasm_bindgen_impl) = if args.plain {
+		if !item.generics.params.is_empty() {
+			abort!(item.generics, "Generic structs can't be used from JS."; help = "Remove `js` and `plain`.");
+		}
+		(quote!(), js_value_abi(ident, &typescript::ts_interface(&orig_struct)))
+	} else if item.generics.params.is_empty() {
+		(
+			quote!(#[cfg_attr(target_arch = "wasm32", wasm_bindgen(getter_with_clone, inspectable))]),
+			quote! {
+				#[cfg(target_arch = "wasm32")]
+				#[wasm_bindgen]
+				impl #ident {
+					#[wasm_bindgen(constructor)]
+					pub fn new() -> #ident {
+						#ident::default()
+					}
+				}
+			},
+		)
+	} else {
+		(quote!(), quote!())
+	};
 
-//  quote! { #(#items)* }
-// }
+	let output = quote! {
+		#[cfg(target_arch = "wasm32")]
+		#[allow(unused_imports)]
+		use wasm_bindgen::prelude::*;
 
-fn backend_struct(orig_struct: syn::ItemStruct) -> proc_macro2::TokenStream {
-	if !is_rust_analyzer() {
-		let mut api_struct = orig_struct.clone();
-		api_struct.vis = parse_quote!();
-		api_struct.attrs.retain(|attr| attr.path().is_ident("doc"));
-		for field in &mut api_struct.fields {
+		#wasm_bindgen_attr
+		#[derive(::turbocharger::serde::Serialize, ::turbocharger::serde::Deserialize, Clone)]
+		#(#attrs)*
+		#[serde(crate = "::turbocharger::serde")]
+		#item
+
+		#wasm_bindgen_impl
+	};
+
+	write_expansion(&orig_struct.ident, &output);
+
+	(output, syn::Item::Struct(api_struct))
+}
+
+fn backend_enum(
+	args: args::BackendArgs,
+	orig_enum: syn::ItemEnum,
+) -> (proc_macro2::TokenStream, syn::Item) {
+	let is_js = args.js;
+
+	let mut api_enum = orig_enum.clone();
+	api_enum.vis = parse_quote!();
+	api_enum.attrs.retain(|attr| attr.path().is_ident("doc"));
+	for variant in &mut api_enum.variants {
+		variant.attrs.retain(|attr| attr.path().is_ident("doc"));
+		for field in &mut variant.fields {
 			field.vis = parse_quote!();
 			field.attrs.retain(|attr| attr.path().is_ident("doc"));
 		}
+	}
 
-		let lockfile = std::fs::File::create(std::env::temp_dir().join("turbocharger.lock")).unwrap();
-		fs2::FileExt::lock_exclusive(&lockfile).unwrap();
+	let syn::ItemEnum { attrs, ident, generics, variants, .. } = orig_enum.clone();
+	let where_clause = &generics.where_clause;
 
-		// add api_struct to file if it doesn't already exist, or replace it if it does
-		let mut file = read_backend_api_rs();
+	let output = if !is_js {
+		quote! {
+			#[derive(::turbocharger::serde::Serialize, ::turbocharger::serde::Deserialize, Clone)]
+			#(#attrs)*
+			#[serde(crate = "::turbocharger::serde")]
+			pub enum #ident #generics #where_clause { #variants }
+		}
+	} else if !generics.params.is_empty() {
+		abort!(generics, "Generic enums can't be used from JS."; help = "Remove `js`.");
+	} else {
+		// JS gets an adjacently tagged `{ type, value }` representation, which is a TypeScript discriminated union,
+		// but bincode can't deserialize that, so pick the representation based on `is_human_readable()`.
+		let ident_str = ident.to_string();
+		let bincode_remote = format_ident!("_TURBOCHARGER_BINCODE_{}", ident);
+		let js_remote = format_ident!("_TURBOCHARGER_JSREPR_{}", ident);
+		let js_value_abi = js_value_abi(&ident, &typescript::ts_enum(&orig_enum));
+
+		let (serde_attrs, attrs): (Vec<_>, Vec<_>) =
+			attrs.into_iter().partition(|attr| attr.path().is_ident("serde"));
+		let mut plain_variants = variants.clone();
+		for variant in &mut plain_variants {
+			variant.attrs.retain(|attr| !attr.path().is_ident("serde"));
+			for field in &mut variant.fields {
+				field.attrs.retain(|attr| !attr.path().is_ident("serde"));
+			}
+		}
 
-		let mut found = false;
-		for item in &mut file.items {
-			if let syn::Item::Struct(ref mut item) = item {
-				if item.ident == api_struct.ident {
-					found = true;
-					*item = api_struct.clone();
-					break;
+		quote! {
+			#[derive(Clone)]
+			#(#attrs)*
+			pub enum #ident { #plain_variants }
+
+			#[allow(non_camel_case_types, dead_code)]
+			#[derive(::turbocharger::serde::Serialize, ::turbocharger::serde::Deserialize)]
+			#(#serde_attrs)*
+			#[serde(crate = "::turbocharger::serde", remote = #ident_str)]
+			enum #bincode_remote { #variants }
+
+			#[allow(non_camel_case_types, dead_code)]
+			#[derive(::turbocharger::serde::Serialize, ::turbocharger::serde::Deserialize)]
+			#(#serde_attrs)*
+			#[serde(crate = "::turbocharger::serde", remote = #ident_str, tag = "type", content = "value")]
+			enum #js_remote { #variants }
+
+			impl ::turbocharger::serde::Serialize for #ident {
+				fn serialize<S: ::turbocharger::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+					if serializer.is_human_readable() {
+						#js_remote::serialize(self, serializer)
+					} else {
+						#bincode_remote::serialize(self, serializer)
+					}
 				}
 			}
-		}
-		if !found {
-			file.items.push(syn::Item::Struct(api_struct));
-		}
 
-		write_backend_api_rs(file);
-	}
+			impl<'de> ::turbocharger::serde::Deserialize<'de> for #ident {
+				fn deserialize<D: ::turbocharger::serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+					if deserializer.is_human_readable() {
+						#js_remote::deserialize(deserializer)
+					} else {
+						#bincode_remote::deserialize(deserializer)
+					}
+				}
+			}
 
-	#[allow(clippy::redundant_clone)]
-	let syn::ItemStruct { attrs, ident, fields, .. } = orig_struct.clone();
+			#js_value_abi
+		}
+	};
 
 	let output = quote! {
 		#[cfg(target_arch = "wasm32")]
 		#[allow(unused_imports)]
 		use wasm_bindgen::prelude::*;
 
-		#[cfg_attr(target_arch = "wasm32", wasm_bindgen(getter_with_clone, inspectable))]
-		#[derive(::turbocharger::serde::Serialize, ::turbocharger::serde::Deserialize, Clone)]
-		#(#attrs)*
-		#[serde(crate = "::turbocharger::serde")]
-		pub struct #ident #fields
+		#output
+	};
+
+	write_expansion(&ident, &output);
+
+	(output, syn::Item::Enum(api_enum))
+}
+
+/// Passes `ident` to and from JS as the value `serde-wasm-bindgen` converts it to, declared in TypeScript by
+/// `ts_declaration`.
+fn js_value_abi(ident: &syn::Ident, ts_declaration: &str) -> proc_macro2::TokenStream {
+	let ident_str = ident.to_string();
+	let js_type = format_ident!("_TURBOCHARGER_JSTYPE_{}", ident);
+	let ts_section = format_ident!("_TURBOCHARGER_TS_{}", ident);
+
+	quote! {
+		#[cfg(target_arch = "wasm32")]
+		#[wasm_bindgen(typescript_custom_section)]
+		#[allow(non_upper_case_globals)]
+		const #ts_section: &'static str = #ts_declaration;
 
 		#[cfg(target_arch = "wasm32")]
 		#[wasm_bindgen]
-		impl #ident {
-			#[wasm_bindgen(constructor)]
-			pub fn new() -> #ident {
-				#ident::default()
+		extern "C" {
+			#[wasm_bindgen(typescript_type = #ident_str)]
+			#[allow(non_camel_case_types)]
+			pub type #js_type;
+		}
+
+		#[cfg(target_arch = "wasm32")]
+		impl wasm_bindgen::describe::WasmDescribe for #ident {
+			fn describe() {
+				<#js_type as wasm_bindgen::describe::WasmDescribe>::describe()
 			}
 		}
-	};
 
-	std::fs::create_dir_all("target/generated/turbocharger").ok();
-	std::fs::write(
-		project_root_path_with(format!("target/generated/turbocharger/{}.rs", orig_struct.ident)),
-		prettyplease::unparse(&parse_quote!( #output )),
-	)
-	.unwrap();
-
-	// std::process::Command::new("rustfmt")
-	//  .current_dir(project_root_path_with("debug_expansions"))
-	//  .arg("--edition")
-	//  .arg("2021")
-	//  .arg("--config")
-	//  .arg("tab_spaces=1")
-	//  .arg(format!("{}.rs", orig_struct.ident))
-	//  .output()
-	//  .unwrap();
-
-	output
+		#[cfg(target_arch = "wasm32")]
+		impl wasm_bindgen::convert::IntoWasmAbi for #ident {
+			type Abi = <#js_type as wasm_bindgen::convert::IntoWasmAbi>::Abi;
+			fn into_abi(self) -> Self::Abi {
+				let value = wasm_bindgen::JsCast::unchecked_into::<#js_type>(JsValue::from(self));
+				wasm_bindgen::convert::IntoWasmAbi::into_abi(value)
+			}
+		}
+
+		#[cfg(target_arch = "wasm32")]
+		impl wasm_bindgen::convert::OptionIntoWasmAbi for #ident {
+			fn none() -> Self::Abi {
+				<#js_type as wasm_bindgen::convert::OptionIntoWasmAbi>::none()
+			}
+		}
+
+		#[cfg(target_arch = "wasm32")]
+		impl wasm_bindgen::convert::FromWasmAbi for #ident {
+			type Abi = <#js_type as wasm_bindgen::convert::FromWasmAbi>::Abi;
+			#[allow(unsafe_code)]
+			unsafe fn from_abi(js: Self::Abi) -> Self {
+				let value: JsValue = <#js_type as wasm_bindgen::convert::FromWasmAbi>::from_abi(js).into();
+				::turbocharger::serde_wasm_bindgen::from_value(value).unwrap_throw()
+			}
+		}
+
+		#[cfg(target_arch = "wasm32")]
+		impl wasm_bindgen::convert::OptionFromWasmAbi for #ident {
+			fn is_none(abi: &Self::Abi) -> bool {
+				<#js_type as wasm_bindgen::convert::OptionFromWasmAbi>::is_none(abi)
+			}
+		}
+
+		#[cfg(target_arch = "wasm32")]
+		impl From<#ident> for JsValue {
+			fn from(value: #ident) -> JsValue {
+				::turbocharger::serde_wasm_bindgen::to_value(&value).unwrap_throw()
+			}
+		}
+	}
 }
 
-fn backend_fn(args: proc_macro::TokenStream, orig_fn: syn::ItemFn) -> proc_macro2::TokenStream {
-	let is_js = args.to_string() == "js";
+fn backend_fn(
+	args: args::BackendArgs,
+	orig_fn: syn::ItemFn,
+) -> (proc_macro2::TokenStream, syn::Item) {
+	let ident = orig_fn.sig.ident.clone();
+	let api_fn = api_fn(&args, &orig_fn);
 
-	if !is_rust_analyzer() {
-		let mut api_fn = orig_fn.clone();
-		api_fn.vis = parse_quote!();
-		api_fn.attrs.retain(|attr| attr.path().is_ident("doc"));
-		api_fn.block = parse_quote!({});
-
-		let lockfile = std::fs::File::create(std::env::temp_dir().join("turbocharger.lock")).unwrap();
-		fs2::FileExt::lock_exclusive(&lockfile).unwrap();
-
-		// add api_fn to file if it doesn't already exist, or replace it if it does
-		let mut file = read_backend_api_rs();
-
-		let mut found = false;
-		for item in &mut file.items {
-			if let syn::Item::Fn(ref mut item) = item {
-				if item.sig.ident == api_fn.sig.ident {
-					found = true;
-					*item = api_fn.clone();
-					break;
+	let BackendFn { server_fns, client_fn, items } = backend_fn_parts(&args, orig_fn, None);
+
+	let output = quote! {
+		#[cfg(target_arch = "wasm32")]
+		#[allow(unused_imports)]
+		use wasm_bindgen::prelude::*;
+
+		#[cfg(not(target_arch = "wasm32"))]
+		#[allow(unused_imports)]
+		use turbocharger::prelude::*;
+
+		#server_fns
+		#client_fn
+		#items
+	};
+
+	write_expansion(&ident, &output);
+
+	(output, syn::Item::Fn(api_fn))
+}
+
+/// Exposes the `pub` methods of a service's `impl` block that take `&self` (or `&mut self`), through a generated
+/// `{Service}Client` struct on the frontend. Methods can have their own `#[backend(instantiate(...))]`.
+fn backend_impl(
+	args: args::BackendArgs,
+	orig_impl: syn::ItemImpl,
+) -> (proc_macro2::TokenStream, syn::Item) {
+	if let Some((_, path, _)) = &orig_impl.trait_ {
+		abort!(path, "Apply #[backend] to an inherent `impl` block, not a trait impl.");
+	}
+	if !orig_impl.generics.params.is_empty() {
+		abort!(orig_impl.generics, "#[backend] `impl` blocks can't be generic."; help = "Make the methods generic instead.");
+	}
+	if args.js {
+		abort!(orig_impl, "`impl` blocks can't be called from JS yet."; help = "Remove `js`, or wrap the methods in `#[backend(js)]` functions.");
+	}
+	let ident = match &*orig_impl.self_ty {
+		syn::Type::Path(syn::TypePath { qself: None, path }) if path.get_ident().is_some() => {
+			path.get_ident().unwrap().clone()
+		}
+		ty => abort!(
+			ty,
+			"Apply #[backend] to an `impl` block for a type in this module, e.g. `impl UserService`."
+		),
+	};
+	let service = Service { ident: ident.clone(), connection_local: args.connection_local };
+	let namespace = args.namespace_for(&ident);
+	let client_ident = format_ident!("{}Client", ident);
+	let client_doc = format!("Calls the `#[backend]` methods of `{}` on the server.", ident);
+
+	let mut server_items = vec![];
+	let mut client_fns = vec![];
+	let mut items = vec![];
+	let mut api_fns = vec![];
+
+	for item in orig_impl.items {
+		match item {
+			syn::ImplItem::Fn(method)
+				if matches!(method.vis, syn::Visibility::Public(_)) && method.sig.receiver().is_some() =>
+			{
+				let (backend_attrs, attrs): (Vec<_>, Vec<_>) =
+					method.attrs.into_iter().partition(|attr| attr.path().is_ident("backend"));
+				let mut method_args = args::BackendArgs { namespace: namespace.clone(), ..Default::default() };
+				for attr in backend_attrs {
+					if let syn::Meta::List(_) = attr.meta {
+						let attr_args: args::BackendArgs =
+							attr.parse_args().unwrap_or_else(|e| abort!(e.span(), "{}", e));
+						if attr_args.js || attr_args.plain || attr_args.connection_local {
+							abort!(attr, "Only `name`, `instantiate` and `invalidates` apply to methods.");
+						}
+						method_args.instantiate.extend(attr_args.instantiate);
+						method_args.invalidates.extend(attr_args.invalidates);
+						method_args.name = attr_args.name.or(method_args.name);
+					}
 				}
+
+				let orig_fn =
+					syn::ItemFn { attrs, vis: method.vis, sig: method.sig, block: Box::new(method.block) };
+				api_fns.push(api_fn(&method_args, &orig_fn));
+				let BackendFn { server_fns, client_fn, items: fn_items } =
+					backend_fn_parts(&method_args, orig_fn, Some(&service));
+				server_items.push(server_fns);
+				client_fns.push(client_fn);
+				items.push(fn_items);
 			}
+			item => server_items.push(quote!(#item)),
 		}
-		if !found {
-			file.items.push(syn::Item::Fn(api_fn));
+	}
+
+	let api_attrs = orig_impl.attrs.iter().filter(|attr| attr.path().is_ident("doc"));
+	let api_attr = args.api_attr();
+	let api_impl = parse_quote! {
+		#(#api_attrs)*
+		#api_attr
+		impl #ident {
+			#(#api_fns)*
 		}
+	};
 
-		write_backend_api_rs(file);
-	}
+	let attrs = orig_impl.attrs;
+
+	let output = quote! {
+		#[cfg(target_arch = "wasm32")]
+		#[allow(unused_imports)]
+		use wasm_bindgen::prelude::*;
+
+		#[cfg(not(target_arch = "wasm32"))]
+		#[allow(unused_imports)]
+		use turbocharger::prelude::*;
+
+		#[cfg(not(target_arch = "wasm32"))]
+		#(#attrs)*
+		impl #ident {
+			#(#server_items)*
+		}
+
+		#[cfg(target_arch = "wasm32")]
+		#[doc = #client_doc]
+		#[derive(Clone, Copy, Debug, Default)]
+		pub struct #client_ident;
+
+		#[cfg(target_arch = "wasm32")]
+		impl #client_ident {
+			#(#client_fns)*
+		}
+
+		#(#items)*
+	};
+
+	write_expansion(&ident, &output);
+
+	(output, api_impl)
+}
+
+/// `orig_fn` as listed in `backend_api.rs`.
+fn api_fn(args: &args::BackendArgs, orig_fn: &syn::ItemFn) -> syn::ItemFn {
+	let mut api_fn = orig_fn.clone();
+	api_fn.vis = parse_quote!();
+	api_fn.attrs.retain(|attr| attr.path().is_ident("doc"));
+	api_fn.attrs.extend(args.api_attr());
+	api_fn.block = parse_quote!({});
+	api_fn
+}
+
+/// The `impl` block of a `#[backend]` method.
+struct Service {
+	/// The implementing type, e.g. `UserService`.
+	ident: syn::Ident,
+	/// The receiver is kept per connection, rather than resolved from shared state.
+	connection_local: bool,
+}
+
+/// The expansion of a `#[backend]` function, in parts, so methods can be placed into `impl` blocks.
+struct BackendFn {
+	/// The original function, callable directly on the server, and its variant taking a `ConnectionInfo`.
+	server_fns: proc_macro2::TokenStream,
+	/// The frontend function (or method of the service's client struct) that calls the server.
+	client_fn: proc_macro2::TokenStream,
+	/// Everything else; goes at module level.
+	items: proc_macro2::TokenStream,
+}
+
+fn backend_fn_parts(
+	args: &args::BackendArgs,
+	orig_fn: syn::ItemFn,
+	service: Option<&Service>,
+) -> BackendFn {
+	let is_js = args.js;
+	let is_plain = args.plain;
 
 	let orig_fn_ident = orig_fn.sig.ident.clone();
-	let orig_fn_string = orig_fn_ident.to_string();
-	let orig_fn_params = orig_fn.sig.inputs.clone();
+	let orig_fn_string = match service {
+		Some(service) => format!("{}::{}", service.ident, orig_fn_ident),
+		None => orig_fn_ident.to_string(),
+	};
+	let explicit_name = match (&args.name, &args.namespace) {
+		(Some(name), _) => Some(name.clone()),
+		(None, Some(namespace)) => Some(format!("{}::{}", namespace, orig_fn_ident)),
+		(None, None) => None,
+	};
+	let instances = fn_instances(args, &orig_fn);
+	if let Some(explicit_name) = &explicit_name {
+		for (suffix, _) in &instances {
+			register_explicit_name(&format!("{}{}", explicit_name, suffix), &orig_fn_ident);
+		}
+	}
+	// Without an explicit name, the dispatch name is found at runtime, from the module path.
+	let dispatch_name_of = |suffix: &str| match &explicit_name {
+		Some(explicit_name) => {
+			let name = format!("{}{}", explicit_name, suffix);
+			quote!(#name)
+		}
+		None => {
+			let name = format!("{}{}", orig_fn_string, suffix);
+			quote!(::turbocharger::_dispatch_name(::core::env!("CARGO_PKG_NAME"), ::core::module_path!(), #name))
+		}
+	};
+	let receiver = match (service, orig_fn.sig.receiver()) {
+		(Some(_), Some(receiver)) => Some(receiver.clone()),
+		(None, Some(receiver)) => {
+			abort!(receiver, "I don't know what to do with `self` here."; help = "Apply #[backend] to the `impl` block.")
+		}
+		_ => None,
+	};
+	let orig_fn_params: syn::punctuated::Punctuated<syn::FnArg, syn::Token![,]> =
+		orig_fn.sig.inputs.iter().filter(|p| !matches!(p, syn::FnArg::Receiver(_))).cloned().collect();
 	let orig_fn_stmts = &orig_fn.block.stmts;
 	let orig_fn_stmts = quote!(#( #orig_fn_stmts )*);
 
-	let store_name = format_ident!("_TURBOCHARGER_STORE_{}", orig_fn_ident);
-	let dispatch = format_ident!("_TURBOCHARGER_DISPATCH_{}", orig_fn_ident);
-	let req = format_ident!("_TURBOCHARGER_REQ_{}", orig_fn_ident);
-	let resp = format_ident!("_TURBOCHARGER_RESP_{}", orig_fn_ident);
-	let js_fn_ident = format_ident!("_TURBOCHARGER_JS_{}", orig_fn_ident);
-	let remote_fn_ident = format_ident!("remote_{}", orig_fn_ident);
-	let remote_impl_ident = format_ident!("_TURBOCHARGER_REMOTEIMPL_{}", orig_fn_ident);
-	let subscriber_fn_ident = format_ident!("_TURBOCHARGER_SUBSCRIBERFN_{}", orig_fn_ident);
+	// Methods' generated items are prefixed with the service name, e.g. `_TURBOCHARGER_REQ_UserService_list`.
+	let base_ident = match service {
+		Some(service) => format_ident!("{}_{}", service.ident, orig_fn_ident),
+		None => orig_fn_ident.clone(),
+	};
+	let store_name = format_ident!("_TURBOCHARGER_STORE_{}", base_ident);
+	let dispatch = format_ident!("_TURBOCHARGER_DISPATCH_{}", base_ident);
+	let req = format_ident!("_TURBOCHARGER_REQ_{}", base_ident);
+	let resp = format_ident!("_TURBOCHARGER_RESP_{}", base_ident);
+	let js_fn_ident = format_ident!("_TURBOCHARGER_JS_{}", base_ident);
+	let remote_fn_ident = format_ident!("remote_{}", base_ident);
+	let remote_impl_ident = format_ident!("_TURBOCHARGER_REMOTEIMPL_{}", base_ident);
+	let subscriber_fn_ident = format_ident!("_TURBOCHARGER_SUBSCRIBERFN_{}", base_ident);
+	let instances_trait = format_ident!("_TURBOCHARGER_INSTANCES_{}", base_ident);
+
+	// Generic functions get generic request and response structs, and one `RPC` impl per instantiation. Clients look
+	// up the dispatch name of their instantiation through `#instances_trait`, implemented for tuples of type arguments.
+	let type_params: Vec<_> = orig_fn.sig.generics.type_params().map(|p| p.ident.clone()).collect();
+	let is_generic = !type_params.is_empty();
+	let (struct_generics, turbofish, phantom_field, phantom_init, dispatch_name) = if is_generic {
+		(
+			quote!(<#(#type_params),*>),
+			quote!(::<#(#type_params),*>),
+			quote! {
+				#[serde(skip)]
+				_phantom: ::std::marker::PhantomData<fn() -> (#(#type_params,)*)>,
+			},
+			quote!(_phantom: ::std::marker::PhantomData,),
+			quote!(<(#(#type_params,)*) as #instances_trait>::dispatch_name()),
+		)
+	} else {
+		(quote!(), quote!(), quote!(), quote!(), dispatch_name_of(""))
+	};
+	let mut client_generics = orig_fn.sig.generics.clone();
+	if is_generic {
+		for param in client_generics.type_params_mut() {
+			param.bounds.push(parse_quote!(::turbocharger::serde::Serialize));
+			param.bounds.push(parse_quote!(::turbocharger::serde::de::DeserializeOwned));
+			param.bounds.push(parse_quote!('static));
+		}
+		client_generics
+			.make_where_clause()
+			.predicates
+			.push(parse_quote!((#(#type_params,)*): #instances_trait));
+	}
+	let (client_generics, _, client_where_clause) = client_generics.split_for_impl();
+	let instances_trait_impls = if is_generic {
+		let impls = instances.iter().map(|(suffix, tys)| {
+			let dispatch_name = dispatch_name_of(suffix);
+			quote! {
+				impl #instances_trait for (#(#tys,)*) {
+					fn dispatch_name() -> &'static str {
+						#dispatch_name
+					}
+				}
+			}
+		});
+		quote! {
+			#[doc(hidden)]
+			#[allow(non_camel_case_types)]
+			pub trait #instances_trait {
+				fn dispatch_name() -> &'static str;
+			}
+
+			#(#impls)*
+		}
+	} else {
+		quote!()
+	};
 
 	let orig_fn_ret_ty = match orig_fn.sig.output.clone() {
 		syn::ReturnType::Type(_, path) => *path,
@@ -270,7 +864,17 @@ fn backend_fn(args: proc_macro::TokenStream, orig_fn: syn::ItemFn) -> proc_macro
 		abort!(orig_fn.sig.asyncness, "#[backend] functions that return a stream must not be async."; help = "Remove the `async`."
 		);
 	}
+	let is_stream = stream_inner_ty.is_some();
+	if is_stream && !args.invalidates.is_empty() {
+		abort!(orig_fn.sig.output, "`invalidates` applies to functions that don't return a stream.");
+	}
+	let invalidates = &args.invalidates;
+	let invalidate =
+		(!invalidates.is_empty()).then(|| quote!(::turbocharger::_invalidate(&[#(#invalidates),*]);));
 	let result_inner_ty = extract::extract_result(stream_inner_ty.unwrap_or(&orig_fn_ret_ty));
+	let plain_ret_ts =
+		typescript::ts_type(result_inner_ty.or(stream_inner_ty).unwrap_or(&orig_fn_ret_ty));
+	let call_ok_ty = result_inner_ty.unwrap_or(&orig_fn_ret_ty);
 	let store_value_ty = if result_inner_ty.is_some() {
 		quote! { Result<#result_inner_ty, JsValue> }
 	} else {
@@ -282,55 +886,74 @@ fn backend_fn(args: proc_macro::TokenStream, orig_fn: syn::ItemFn) -> proc_macro
 		None => quote! {},
 	};
 
-	let send_value_to_subscription = if result_inner_ty.is_some() {
-		quote! {
-			if let Some(value) = self.value.lock().unwrap().clone() {
-				let promise: ::turbocharger::js_sys::Promise = match value.clone() {
-					Ok(t) => ::turbocharger::js_sys::Promise::resolve(&t.into()).into(),
-					Err(e) => ::turbocharger::js_sys::Promise::reject(&e.into()).into(),
-				};
-				subscription.call1(&JsValue::null(), &promise).ok();
+	// Plain functions pass their parameters and results to JS as the values `serde-wasm-bindgen` converts them to.
+	let to_js = |value: proc_macro2::TokenStream| match is_plain {
+		true => quote!(::turbocharger::serde_wasm_bindgen::to_value(&#value).unwrap_throw()),
+		false => quote!(JsValue::from(#value)),
+	};
+	// A stream store's `value` as JS, where `Result` streams have promises, so errors reject.
+	let store_value_to_js = match result_inner_ty {
+		Some(_) => {
+			let t_to_js = to_js(quote!(t));
+			quote! {
+				match value {
+					Ok(t) => JsValue::from(::turbocharger::js_sys::Promise::resolve(&#t_to_js)),
+					Err(e) => JsValue::from(::turbocharger::js_sys::Promise::reject(&e)),
+				}
 			}
 		}
-	} else {
-		quote! {
-			if let Some(value) = self.value.lock().unwrap().clone() {
-				subscription.call1(&JsValue::null(), &value.into()).ok();
+		None => to_js(quote!(value)),
+	};
+	let item_ts = match stream_inner_ty {
+		Some(ty) => {
+			let ty = result_inner_ty.unwrap_or(ty);
+			if is_plain {
+				typescript::ts_type(ty)
+			} else {
+				typescript::ts_abi_type(ty)
 			}
 		}
+		None => String::new(),
 	};
-
-	let send_value_to_subscriptions = if result_inner_ty.is_some() {
-		quote! {
-			if let Some(value) = value.lock().unwrap().clone() {
-				let promise: ::turbocharger::js_sys::Promise = match value.clone() {
-					Ok(t) => ::turbocharger::js_sys::Promise::resolve(&t.into()).into(),
-					Err(e) => ::turbocharger::js_sys::Promise::reject(&e.into()).into(),
-				};
-				for subscription in subscriptions.lock().unwrap().iter() {
-					if let Some(subscription) = subscription.lock().unwrap().as_ref() {
-						subscription.call1(&JsValue::null(), &promise).ok();
-					}
+	let store_value_ts = match result_inner_ty {
+		Some(_) => format!("Promise<{}>", item_ts),
+		None => item_ts.clone(),
+	};
+	// A stream item as the value an async iterator over the store yields, or the error it throws.
+	let iterator_item_to_js = match result_inner_ty {
+		Some(_) => {
+			let t_to_js = to_js(quote!(t));
+			quote! {
+				match result {
+					Ok(t) => Ok(#t_to_js),
+					Err(e) => Err(::turbocharger::js_sys::Error::new(&e.to_string()).into()),
 				}
 			}
 		}
-	} else {
-		quote! {
-			if let Some(value) = value.lock().unwrap().clone() {
-				for subscription in subscriptions.lock().unwrap().iter() {
-					if let Some(subscription) = subscription.lock().unwrap().as_ref() {
-						subscription.call1(&JsValue::null(), &value.clone().into()).ok();
-					}
+		None => {
+			let value_to_js = to_js(quote!(result));
+			quote!(Ok(#value_to_js))
+		}
+	};
+
+	let send_value_to_subscription = quote! {
+		if let Some(value) = self.value.lock().unwrap().clone() {
+			let value = #store_value_to_js;
+			subscription.call1(&JsValue::null(), &value).ok();
+		}
+	};
+
+	let send_value_to_subscriptions = quote! {
+		if let Some(value) = value.lock().unwrap().clone() {
+			let value = #store_value_to_js;
+			for subscription in subscriptions.lock().unwrap().iter() {
+				if let Some(subscription) = subscription.lock().unwrap().as_ref() {
+					subscription.call1(&JsValue::null(), &value).ok();
 				}
 			}
 		}
 	};
 
-	let bindgen_ret_ty = match (stream_inner_ty, result_inner_ty) {
-		(None, Some(ty)) => quote! { Result<#ty, JsValue> },
-		(Some(_ty), _) => quote! { #store_name },
-		(None, None) => quote! { #orig_fn_ret_ty },
-	};
 	let serialize_ret_ty = match &stream_inner_ty {
 		Some(ty) => quote! { #ty },
 		None => quote! { #orig_fn_ret_ty },
@@ -342,7 +965,7 @@ fn backend_fn(args: proc_macro::TokenStream, orig_fn: syn::ItemFn) -> proc_macro
 		quote_spanned! {orig_fn_ret_ty.span()=> #orig_fn_ret_ty }
 	};
 
-	let tuple_indexes = (0..orig_fn_params.len()).map(syn::Index::from);
+	let tuple_indexes: Vec<_> = (0..orig_fn_params.len()).map(syn::Index::from).collect();
 	let orig_fn_param_names: Vec<_> = orig_fn_params
 		.iter()
 		.map(|p| match p {
@@ -364,9 +987,35 @@ fn backend_fn(args: proc_macro::TokenStream, orig_fn: syn::ItemFn) -> proc_macro
 
 	let orig_fn_params_maybe_comma = if orig_fn_params.is_empty() { quote!() } else { quote!( , ) };
 
+	// Calls of free, non-generic, unary functions made while the server renders a page run as requests from the page's
+	// connection would, and their results are embedded in the page, where the client's first call finds its result.
+	let is_prefetchable =
+		service.is_none() && !is_generic && !is_stream && orig_fn.sig.asyncness.is_some();
+	let prefetch_key = quote!(&(#dispatch_name, (#( &#orig_fn_param_names, )*)));
+	let (ssr_call, take_prefetched) = if is_prefetchable {
+		(
+			quote! {
+				if let Some(connection_info) = ::turbocharger::_ssr_connection_info() {
+					let key = ::turbocharger::bincode::serialize(#prefetch_key).unwrap();
+					let result = #remote_impl_ident(Some(connection_info) #orig_fn_params_maybe_comma #( #orig_fn_param_names ),*).await;
+					::turbocharger::_ssr_record(key, &result);
+					return result;
+				}
+			},
+			quote! {
+				if let Some(result) = ::turbocharger::_prefetched(#prefetch_key) {
+					return ::turbocharger::bincode::deserialize(&result).unwrap();
+				}
+			},
+		)
+	} else {
+		(quote!(), quote!())
+	};
+
 	let mut orig_fn = orig_fn;
 	orig_fn.sig.output = parse_quote! { -> #orig_fn_ret_ty };
 	orig_fn.block = parse_quote!({
+		#ssr_call
 		let _turbocharger_connection_info: Option<::turbocharger::ConnectionInfo> = None;
 		#orig_fn_stmts
 	});
@@ -380,106 +1029,356 @@ fn backend_fn(args: proc_macro::TokenStream, orig_fn: syn::ItemFn) -> proc_macro
 	);
 	remote_impl_fn.block = parse_quote!({ #orig_fn_stmts });
 
-	let executebody = match &stream_inner_ty {
-		Some(_ty) => quote! {
-			use ::turbocharger::futures_util::stream::StreamExt as _;
-			use ::turbocharger::stream_cancel::StreamExt as _;
-			let stream = #remote_impl_ident(_turbocharger_connection_info #orig_fn_params_maybe_comma #( self.params. #tuple_indexes .clone() ),*);
-			::turbocharger::futures_util::pin_mut!(stream);
-
-			if let Some(tripwire) = tripwire {
-				let mut incoming = stream.take_until_if(tripwire);
-				while let Some(result) = incoming.next().await {
-					let response = #resp {
-						txid: self.txid,
-						result: result.clone()
-					};
-					sender(::turbocharger::bincode::serialize(&response).unwrap());
+	// A method's `remote_impl_fn` is a method too, called by a free function that resolves the receiver.
+	let service_remote_impl_fn = match (service, &receiver) {
+		(Some(service), Some(receiver)) => {
+			let method_ident = format_ident!("_TURBOCHARGER_REMOTEIMPL_{}", orig_fn_ident);
+			let mut wrapper_fn = remote_impl_fn.clone();
+			remote_impl_fn.vis = parse_quote!();
+			remote_impl_fn.sig.ident = method_ident.clone();
+			remote_impl_fn.sig.inputs.insert(0, syn::FnArg::Receiver(receiver.clone()));
+
+			let service_ident = &service.ident;
+			let service_string = service_ident.to_string();
+			let resolve_receiver = if service.connection_local {
+				if receiver.reference.is_none() {
+					abort!(receiver, "#[backend] methods must take `&self` or `&mut self`.");
 				}
-			}
-			else {
-				while let Some(result) = stream.next().await {
-					let response = #resp {
-						txid: self.txid,
-						result: result.clone()
-					};
-					sender(::turbocharger::bincode::serialize(&response).unwrap());
+				let msg = format!("{}::{} called without a connection", service_ident, orig_fn_ident);
+				quote! {
+					let mut receiver = _turbocharger_connection_info
+						.as_ref()
+						.expect(#msg)
+						.connection_local
+						._lock::<#service_ident>(#service_string)
+						.await;
+					if receiver._is_empty() {
+						receiver._set(::std::default::Default::default());
+					}
+				}
+			} else {
+				if receiver.reference.is_none() || receiver.mutability.is_some() {
+					abort!(receiver, "#[backend] methods must take `&self`, because the service is shared state."; help = "Use interior mutability, or `#[backend(connection_local)]` on the `impl` block for a service per connection.");
+				}
+				quote! {
+					let receiver = ::turbocharger::State::<#service_ident>::_resolve(_turbocharger_connection_info.as_ref());
 				}
-			}
-		},
-		None => quote! {
-			let result = #remote_impl_ident(_turbocharger_connection_info #orig_fn_params_maybe_comma #( self.params. #tuple_indexes .clone() ),*).await;
-			let response = #resp {
-				txid: self.txid,
-				result
 			};
-			sender(::turbocharger::bincode::serialize(&response).unwrap());
-		},
+			let call = quote! {
+				receiver.#method_ident(_turbocharger_connection_info #orig_fn_params_maybe_comma #( #orig_fn_param_names ),*)
+			};
+			wrapper_fn.vis = parse_quote!();
+			wrapper_fn.block = match (stream_inner_ty.is_some(), service.connection_local) {
+				(false, _) => parse_quote!({
+					#resolve_receiver
+					#call.await
+				}),
+				(true, false) => parse_quote!({
+					#resolve_receiver
+					#call
+				}),
+				// Locking the receiver is async, but a stream is returned synchronously.
+				(true, true) => parse_quote!({
+					::turbocharger::futures_util::StreamExt::flatten(::turbocharger::futures_util::stream::once(async move {
+						#resolve_receiver
+						#call
+					}))
+				}),
+			};
+
+			quote! {
+				#[cfg(not(target_arch = "wasm32"))]
+				#[allow(non_snake_case)]
+				#wrapper_fn
+			}
+		}
+		_ => quote!(),
 	};
 
-	let maybe_svelte_typescript_type = if cfg!(feature = "svelte") {
+	let rpc_impls = instances.iter().map(|(suffix, tys)| {
+		let (type_args, turbofish) =
+			if is_generic { (quote!(<#(#tys),*>), quote!(::<#(#tys),*>)) } else { (quote!(), quote!()) };
+		let name = format!("{}{}", orig_fn_string, suffix);
+		// Each explicit name is in a static exported under a symbol named after it, which the registration refers to
+		// so that it's linked, and linking fails if another crate registers the same name.
+		let explicit_name = match &explicit_name {
+			Some(explicit_name) => {
+				let explicit_name = format!("{}{}", explicit_name, suffix);
+				let symbol = format!("turbocharger dispatch name {}", explicit_name);
+				quote!(Some({
+					#[export_name = #symbol]
+					static EXPLICIT_NAME: &str = #explicit_name;
+					&EXPLICIT_NAME
+				}))
+			}
+			None => quote!(None),
+		};
+		let dispatch_name = dispatch_name_of(suffix);
+
+		let maybe_report_error = if result_inner_ty.is_some() {
+			quote! {
+				if let Err(e) = &result {
+					use ::turbocharger::telemetry::{DescribeDisplay as _, DescribeFallback as _};
+					::turbocharger::telemetry::rpc_error(#dispatch_name, (&::turbocharger::telemetry::Describe(e)).describe());
+				}
+			}
+		} else {
+			quote!()
+		};
+
+		let executebody = match &stream_inner_ty {
+			Some(_ty) => quote! {
+				use ::turbocharger::futures_util::stream::StreamExt as _;
+				use ::turbocharger::stream_cancel::StreamExt as _;
+				let stream = #remote_impl_ident #turbofish (_turbocharger_connection_info #orig_fn_params_maybe_comma #( self.params. #tuple_indexes .clone() ),*);
+				::turbocharger::futures_util::pin_mut!(stream);
+
+				if let Some(tripwire) = tripwire {
+					let mut incoming = stream.take_until_if(tripwire);
+					while let Some(result) = incoming.next().await {
+						#maybe_report_error
+						let response = #resp #turbofish {
+							txid: self.txid,
+							result: result.clone(),
+							#phantom_init
+						};
+						sender(::turbocharger::bincode::serialize(&response).unwrap());
+					}
+				}
+				else {
+					while let Some(result) = stream.next().await {
+						#maybe_report_error
+						let response = #resp #turbofish {
+							txid: self.txid,
+							result: result.clone(),
+							#phantom_init
+						};
+						sender(::turbocharger::bincode::serialize(&response).unwrap());
+					}
+				}
+			},
+			None => quote! {
+				let call = #remote_impl_ident #turbofish (_turbocharger_connection_info #orig_fn_params_maybe_comma #( self.params. #tuple_indexes .clone() ),*);
+				let result = match tripwire {
+					Some(tripwire) => {
+						use ::turbocharger::futures_util::future::{select, Either};
+						match select(Box::pin(call), tripwire).await {
+							Either::Left((result, _)) => result,
+							// the client aborted the call
+							Either::Right((true, _)) => return,
+							// the tripwire was disabled, e.g. because the client disconnected
+							Either::Right((false, call)) => call.await,
+						}
+					}
+					None => call.await,
+				};
+				#maybe_report_error
+				let response = #resp #turbofish {
+					txid: self.txid,
+					result,
+					#phantom_init
+				};
+				sender(::turbocharger::bincode::serialize(&response).unwrap());
+			},
+		};
+
 		quote! {
-			#[cfg(target_arch = "wasm32")]
-			#[wasm_bindgen]
-			extern "C" {
-				#[wasm_bindgen(typescript_type = "Subscriber<any>")]
-				#[allow(non_camel_case_types)]
-				pub type #subscriber_fn_ident;
+			#[cfg(not(target_arch = "wasm32"))]
+			::turbocharger::inventory::submit! {
+				::turbocharger::_Dispatch {
+					package: ::core::env!("CARGO_PKG_NAME"),
+					module_path: ::core::module_path!(),
+					name: #name,
+					explicit_name: #explicit_name,
+					invalidates: &[#(#invalidates),*],
+					deserialize: |data| Ok(Box::new(::turbocharger::bincode::deserialize::<#dispatch #type_args>(data)?)),
+				}
+			}
+
+			#[cfg(not(target_arch = "wasm32"))]
+			#[allow(non_snake_case)]
+			#[::turbocharger::async_trait]
+			impl ::turbocharger::RPC for #dispatch #type_args {
+				async fn execute(
+					&self,
+					sender: Box<dyn Fn(Vec<u8>) + Send>,
+					tripwire: Option<::turbocharger::stream_cancel::Tripwire>,
+					_turbocharger_connection_info: Option<::turbocharger::ConnectionInfo>
+				) {
+					#executebody
+				}
+				fn txid(&self) -> i64 {
+					self.txid
+				}
+				fn name(&self) -> &'static str {
+					#dispatch_name
+				}
+				fn is_stream(&self) -> bool {
+					#is_stream
+				}
 			}
 		}
-	} else {
-		quote!()
-	};
+	});
 
+	// `Subscriber` is Svelte's with the `svelte` feature, and otherwise declared by `turbocharger`.
+	let subscriber_ts = format!("Subscriber<{}>", store_value_ts);
+	let store_value_or_undefined_ts = format!("{} | undefined", store_value_ts);
+	let iterator_ts = format!("AsyncIterator<{}>", item_ts);
+	// wasm-bindgen can't declare symbol-keyed methods, so the store's class is merged with this interface.
+	let store_iterable_ts =
+		format!("export interface {} {{\n  [Symbol.asyncIterator](): {};\n}}", store_name, iterator_ts);
+	let store_name_ts = store_name.to_string();
+	let store_iterable_ts_ident = format_ident!("_TURBOCHARGER_TS_{}", store_name);
+
+	let client_receiver = if service.is_some() { quote!(&self,) } else { quote!() };
 	let wasm_side = match &stream_inner_ty {
 		Some(_ty) => quote! {
 			#[cfg(target_arch = "wasm32")]
-			pub fn #orig_fn_ident(#orig_fn_params) -> #orig_fn_ret_ty {
+			pub fn #orig_fn_ident #client_generics (#client_receiver #orig_fn_params) -> #orig_fn_ret_ty #client_where_clause {
 				let tx = ::turbocharger::_Transaction::new();
-				let req = ::turbocharger::bincode::serialize(&#req {
+				let req = ::turbocharger::bincode::serialize(&#req #turbofish {
 					typetag_const_one: 1,
-					dispatch_name: #orig_fn_string,
+					dispatch_name: #dispatch_name,
 					txid: tx.txid,
 					params: (#( #orig_fn_param_names ),* #orig_fn_params_maybe_comma),
+					#phantom_init
 				})
 				.unwrap();
-				tx.send_ws(req);
-
-				let (resp_tx, resp_rx) = ::turbocharger::futures_channel::mpsc::unbounded();
-
-				tx.set_sender(Box::new(move |response| {
-					use ::turbocharger::futures_util::SinkExt as _;
-					let #resp { result, .. } =
+				::turbocharger::_track_query(#dispatch_name);
+				::turbocharger::futures_util::StreamExt::map(tx.stream(req), |response| {
+					let #resp #turbofish { result, .. } =
 						::turbocharger::bincode::deserialize(&response).unwrap();
-					let mut resp_tx = resp_tx.clone();
-					wasm_bindgen_futures::spawn_local(async move {
-						resp_tx.send(result).await.unwrap();
-					});
-				}));
-
-				resp_rx
+					result
+				})
 			}
 		},
 		None => quote! {
 			#[cfg(target_arch = "wasm32")]
-			pub async fn #orig_fn_ident(#orig_fn_params) -> #orig_fn_ret_ty {
+			pub async fn #orig_fn_ident #client_generics (#client_receiver #orig_fn_params) -> #orig_fn_ret_ty #client_where_clause {
+				::turbocharger::_track_query(#dispatch_name);
+				#take_prefetched
 				let tx = ::turbocharger::_Transaction::new();
-				let req = ::turbocharger::bincode::serialize(&#req {
+				let req = ::turbocharger::bincode::serialize(&#req #turbofish {
 					typetag_const_one: 1,
-					dispatch_name: #orig_fn_string,
+					dispatch_name: #dispatch_name,
 					txid: tx.txid,
 					params: (#( #orig_fn_param_names ),* #orig_fn_params_maybe_comma),
+					#phantom_init
 				})
 				.unwrap();
-				tx.send_ws(req);
-				let response = tx.resp().await;
-				let #resp { result, .. } =
+				let response = tx.call(req).await;
+				let #resp #turbofish { result, .. } =
 					::turbocharger::bincode::deserialize(&response).unwrap();
+				#invalidate
 				result
 			}
 		},
 	};
 
+	// Plain functions take `JsValue`s, typed for TypeScript, and deserialize them, which can fail.
+	let (js_fn_params, js_fn_param_conversions, store_fn_ret_ty, store_fn_ok) = if is_plain {
+		let ts_tys = orig_fn_param_tys.iter().map(|ty| typescript::ts_type(ty));
+		(
+			orig_fn_param_names
+				.iter()
+				.zip(ts_tys)
+				.map(|(name, ts_ty)| quote!(#[wasm_bindgen(unchecked_param_type = #ts_ty)] #name: JsValue))
+				.collect::<Vec<_>>(),
+			quote!(#( let #orig_fn_param_names: #orig_fn_param_tys = ::turbocharger::serde_wasm_bindgen::from_value(#orig_fn_param_names)?; )*),
+			quote!(Result<JsValue, JsValue>),
+			quote!(Ok),
+		)
+	} else {
+		(orig_fn_params.iter().map(|p| quote!(#p)).collect(), quote!(), quote!(JsValue), quote!())
+	};
+
+	// Unary JS functions make the request themselves, so the `options` JS passes after the arguments can abort it.
+	let js_call = quote! {
+		let tx = ::turbocharger::_Transaction::new();
+		let req = ::turbocharger::bincode::serialize(&#req {
+			typetag_const_one: 1,
+			dispatch_name: #dispatch_name,
+			txid: tx.txid,
+			params: (#( #orig_fn_param_names ),* #orig_fn_params_maybe_comma),
+		})
+		.unwrap();
+		let response = tx.call_with_options(req, _turbocharger_options).await?;
+		let #resp { result, .. } = ::turbocharger::bincode::deserialize(&response).unwrap();
+	};
+	let js_options_param = quote! {
+		#[wasm_bindgen(js_name = "options", unchecked_optional_param_type = "CallOptions")]
+		_turbocharger_options: Option<::turbocharger::_CallOptions>
+	};
+	let (js_call_ret_ty, js_call_result) = match result_inner_ty {
+		Some(ty) => (quote!(Result<#ty, JsValue>), quote!(result #maybe_map_err_jsvalue)),
+		None => (quote!(Result<#orig_fn_ret_ty, JsValue>), quote!(Ok(result))),
+	};
+
+	let new_req = quote! {
+		#req {
+			typetag_const_one: 1,
+			dispatch_name: #dispatch_name,
+			txid: 1,
+			params: (#( #orig_fn_param_names ),* #orig_fn_params_maybe_comma),
+		}
+	};
+
+	// With the `react` feature, a `use{Name}` hook too, which runs again when the arguments' values change.
+	let react_hook = if cfg!(feature = "react") && is_js {
+		let hook_name = typescript::react_hook_name(orig_fn_ident.to_string().trim_start_matches("r#"));
+		let hook_ident = format_ident!("_TURBOCHARGER_REACT_{}", orig_fn_ident);
+		let (hook_ret_ty, hook_ok) = match is_plain {
+			true => (quote!(Result<JsValue, JsValue>), quote!(Ok)),
+			false => (quote!(JsValue), quote!()),
+		};
+		let deserialize_params = quote! {
+			let (#( #orig_fn_param_names ),* #orig_fn_params_maybe_comma): (#( #orig_fn_param_tys ),* #orig_fn_params_maybe_comma) =
+				::turbocharger::bincode::deserialize(&params).unwrap();
+		};
+		let (hook_ts, use_hook) = match stream_inner_ty {
+			Some(_) => (
+				format!("StreamState<{}>", item_ts),
+				quote! {
+					::turbocharger::_use_stream(params, move |params| {
+						#deserialize_params
+						#store_name::_new(#new_req)
+					})
+				},
+			),
+			None => {
+				let ok_ts =
+					if is_plain { typescript::ts_type(call_ok_ty) } else { typescript::ts_abi_type(call_ok_ty) };
+				let result_to_js = to_js(quote!(result?));
+				(
+					format!("QueryState<{}>", ok_ts),
+					quote! {
+						::turbocharger::_use_query(params, move |params, _turbocharger_options| {
+							let params = params.to_vec();
+							wasm_bindgen_futures::future_to_promise(async move {
+								#deserialize_params
+								#js_call
+								let result: #js_call_ret_ty = #js_call_result;
+								Ok(#result_to_js)
+							})
+						})
+					},
+				)
+			}
+		};
+		quote! {
+			#[cfg(target_arch = "wasm32")]
+			#[allow(non_snake_case)]
+			#[wasm_bindgen(js_name = #hook_name, unchecked_return_type = #hook_ts)]
+			pub fn #hook_ident(#( #js_fn_params ),*) -> #hook_ret_ty {
+				#js_fn_param_conversions
+				let params = ::turbocharger::bincode::serialize(&(#( &#orig_fn_param_names ),* #orig_fn_params_maybe_comma)).unwrap();
+				#hook_ok(#use_hook)
+			}
+		}
+	} else {
+		quote!()
+	};
+
 	let js_side = if !is_js {
 		quote!()
 	} else {
@@ -494,12 +1393,78 @@ fn backend_fn(args: proc_macro::TokenStream, orig_fn: syn::ItemFn) -> proc_macro
 					subscriptions: std::sync::Arc<std::sync::Mutex<Vec<std::sync::Arc<std::sync::Mutex<Option<::turbocharger::js_sys::Function>>>>>>,
 				}
 
-				#maybe_svelte_typescript_type
+				#[cfg(target_arch = "wasm32")]
+				#[wasm_bindgen]
+				extern "C" {
+					#[wasm_bindgen(typescript_type = #subscriber_ts)]
+					#[allow(non_camel_case_types)]
+					pub type #subscriber_fn_ident;
+				}
+
+				#[cfg(target_arch = "wasm32")]
+				#[wasm_bindgen(typescript_custom_section)]
+				const #store_iterable_ts_ident: &'static str = #store_iterable_ts;
+
+				#[cfg(target_arch = "wasm32")]
+				impl #store_name {
+					fn _new(req: #req) -> JsValue {
+					let req = std::sync::Arc::new(std::sync::Mutex::new(req));
+					let store = JsValue::from(#store_name {
+						req: req.clone(),
+						value: Default::default(),
+						subscriptions: Default::default()
+					});
+					let iterator = Closure::wrap(Box::new(move || #store_name::_iterator(&req)) as Box<dyn Fn() -> JsValue>);
+					::turbocharger::js_sys::Reflect::set(
+						&store,
+						&::turbocharger::js_sys::Symbol::async_iterator(),
+						&iterator.into_js_value(),
+					)
+					.unwrap_throw();
+					store
+				}
+
+				fn _iterator(req: &std::sync::Mutex<#req>) -> JsValue {
+						::turbocharger::_stream_iterator(
+							|txid| {
+								// the iterator's own request, leaving the store's subscriptions alone
+								let mut req = req.lock().unwrap();
+								let store_txid = std::mem::replace(&mut req.txid, txid);
+								let iterator_req = ::turbocharger::bincode::serialize(&*req).unwrap();
+								req.txid = store_txid;
+								iterator_req
+							},
+							|response| {
+								let #resp { result, .. } =
+									::turbocharger::bincode::deserialize(response).unwrap();
+								#iterator_item_to_js
+							},
+						)
+					}
+				}
 
 				#[cfg(target_arch = "wasm32")]
 				#[wasm_bindgen]
 				impl #store_name {
-					#[wasm_bindgen]
+					/// An async iterator over the items of a separate run of the stream, which is what `for await`
+					/// loops over the store use.
+					#[wasm_bindgen(unchecked_return_type = #iterator_ts)]
+					pub fn iterator(&self) -> JsValue {
+						Self::_iterator(&self.req)
+					}
+
+					/// The latest item, or `undefined` before the first one arrives.
+					#[wasm_bindgen(getter, unchecked_return_type = #store_value_or_undefined_ts)]
+					pub fn value(&self) -> JsValue {
+						match self.value.lock().unwrap().clone() {
+							Some(value) => #store_value_to_js,
+							None => JsValue::UNDEFINED,
+						}
+					}
+
+					/// Calls `subscription` with each item, starting with the latest one, and returns a function that
+					/// unsubscribes. The stream runs while there are subscriptions.
+					#[wasm_bindgen(unchecked_return_type = "() => void")]
 					pub fn subscribe(&mut self, subscription: #subscriber_fn_ident) -> JsValue {
 						let subscription: ::turbocharger::js_sys::Function = JsValue::from(subscription).into();
 						if self.subscriptions.lock().unwrap().is_empty() {
@@ -536,41 +1501,42 @@ fn backend_fn(args: proc_macro::TokenStream, orig_fn: syn::ItemFn) -> proc_macro
 
 				#[cfg(target_arch = "wasm32")]
 				#[allow(non_snake_case)]
-				#[wasm_bindgen(js_name = #orig_fn_ident)]
-				pub fn #js_fn_ident(#orig_fn_params) -> #bindgen_ret_ty {
-					let req = #req {
-						typetag_const_one: 1,
-						dispatch_name: #orig_fn_string,
-						txid: 1,
-						params: (#( #orig_fn_param_names ),* #orig_fn_params_maybe_comma),
-					};
-					#store_name {
-						req: std::sync::Arc::new(std::sync::Mutex::new(req)),
-						value: Default::default(),
-						subscriptions: Default::default()
-					}
+				#[wasm_bindgen(js_name = #orig_fn_ident, unchecked_return_type = #store_name_ts)]
+				pub fn #js_fn_ident(#( #js_fn_params ),*) -> #store_fn_ret_ty {
+					#js_fn_param_conversions
+					#store_fn_ok(#store_name::_new(#new_req))
 				}
+
+				#react_hook
+			},
+			None if is_plain => quote! {
+				#[cfg(target_arch = "wasm32")]
+				#[allow(non_snake_case)]
+				#[wasm_bindgen(js_name = #orig_fn_ident, unchecked_return_type = #plain_ret_ts)]
+				pub async fn #js_fn_ident(#( #js_fn_params, )* #js_options_param) -> Result<JsValue, JsValue> {
+					#js_fn_param_conversions
+					#js_call
+					let result: #js_call_ret_ty = #js_call_result;
+					Ok(::turbocharger::serde_wasm_bindgen::to_value(&result?)?)
+				}
+
+				#react_hook
 			},
 			None => quote! {
 				#[cfg(target_arch = "wasm32")]
 				#[allow(non_snake_case)]
 				#[wasm_bindgen(js_name = #orig_fn_ident)]
-				pub async fn #js_fn_ident(#orig_fn_params) -> #bindgen_ret_ty {
-					#orig_fn_ident(#( #orig_fn_param_names ),*) .await #maybe_map_err_jsvalue
+				pub async fn #js_fn_ident(#( #js_fn_params, )* #js_options_param) -> #js_call_ret_ty {
+					#js_call
+					#js_call_result
 				}
+
+				#react_hook
 			},
 		}
 	};
 
-	let output = quote! {
-		#[cfg(target_arch = "wasm32")]
-		#[allow(unused_imports)]
-		use wasm_bindgen::prelude::*;
-
-		#[cfg(not(target_arch = "wasm32"))]
-		#[allow(unused_imports)]
-		use turbocharger::prelude::*;
-
+	let server_fns = quote! {
 		#[cfg(not(target_arch = "wasm32"))]
 		#[allow(dead_code)]
 		#[tracked]
@@ -580,126 +1546,202 @@ fn backend_fn(args: proc_macro::TokenStream, orig_fn: syn::ItemFn) -> proc_macro
 		#[allow(non_snake_case)]
 		#[tracked]
 		#remote_impl_fn
+	};
 
-		#[cfg(not(target_arch = "wasm32"))]
-		#[allow(non_snake_case)]
-		#[::turbocharger::typetag::serde(name = #orig_fn_string)]
-		#[::turbocharger::async_trait]
-		impl ::turbocharger::RPC for #dispatch {
-			async fn execute(
-				&self,
-				sender: Box<dyn Fn(Vec<u8>) + Send>,
-				tripwire: Option<::turbocharger::stream_cancel::Tripwire>,
-				_turbocharger_connection_info: Option<::turbocharger::ConnectionInfo>
-			) {
-				#executebody
-			}
-			fn txid(&self) -> i64 {
-				self.txid
+	let remote_fn = if service.is_none() {
+		quote! {
+			#[cfg(not(target_arch = "wasm32"))]
+			#[allow(non_snake_case)]
+			async fn #remote_fn_ident #client_generics (peer: &str, #orig_fn_params) -> #serialize_ret_ty #client_where_clause {
+				let tx = ::turbocharger::_Transaction::new();
+				let req = ::turbocharger::bincode::serialize(&#req #turbofish {
+					typetag_const_one: 1,
+					dispatch_name: #dispatch_name,
+					txid: tx.txid,
+					params: (#( #orig_fn_param_names ),* #orig_fn_params_maybe_comma),
+					#phantom_init
+				})
+				.unwrap();
+				tx.send_udp(peer, req).await;
+				let response = tx.resp().await;
+				let #resp #turbofish { result, .. } =
+					::turbocharger::bincode::deserialize(&response).unwrap();
+				result
 			}
 		}
+	} else {
+		quote!()
+	};
+
+	let items = quote! {
+		#service_remote_impl_fn
+
+		#(#rpc_impls)*
+
+		#instances_trait_impls
 
-		#wasm_side
 		#js_side
 
-		#[cfg(not(target_arch = "wasm32"))]
-		#[allow(non_snake_case)]
-		async fn #remote_fn_ident(peer: &str, #orig_fn_params) -> #serialize_ret_ty {
-			let tx = ::turbocharger::_Transaction::new();
-			let req = ::turbocharger::bincode::serialize(&#req {
-				typetag_const_one: 1,
-				dispatch_name: #orig_fn_string,
-				txid: tx.txid,
-				params: (#( #orig_fn_param_names ),* #orig_fn_params_maybe_comma),
-			})
-			.unwrap();
-			tx.send_udp(peer, req).await;
-			let response = tx.resp().await;
-			let #resp { result, .. } =
-				::turbocharger::bincode::deserialize(&response).unwrap();
-			result
-		}
+		#remote_fn
 
 		#[allow(non_camel_case_types)]
 		#[derive(::turbocharger::serde::Serialize, ::turbocharger::serde::Deserialize)]
 		#[serde(crate = "::turbocharger::serde")]
-		struct #req {
+		struct #req #struct_generics {
 			typetag_const_one: i64,
 			dispatch_name: &'static str,
 			txid: i64,
 			params: (#( #orig_fn_param_tys ),* #orig_fn_params_maybe_comma),
+			#phantom_field
 		}
 
 		#[allow(non_camel_case_types)]
 		#[derive(::turbocharger::serde::Serialize, ::turbocharger::serde::Deserialize)]
 		#[serde(crate = "::turbocharger::serde")]
-		struct #dispatch {
+		struct #dispatch #struct_generics {
 			txid: i64,
 			params: (#( #orig_fn_param_tys ),* #orig_fn_params_maybe_comma),
+			#phantom_field
 		}
 
 		#[allow(non_camel_case_types)]
 		#[derive(::turbocharger::serde::Serialize, ::turbocharger::serde::Deserialize)]
 		#[serde(crate = "::turbocharger::serde")]
-		struct #resp {
+		struct #resp #struct_generics {
 			txid: i64,
 			result: #serialize_ret_ty,
+			#phantom_field
 		}
 	};
 
-	std::fs::create_dir_all("target/generated/turbocharger").ok();
-	std::fs::write(
-		project_root_path_with(format!("target/generated/turbocharger/{}.rs", orig_fn_string)),
-		prettyplease::unparse(&parse_quote!( #output )),
-	)
-	.unwrap();
-
-	// std::process::Command::new("rustfmt")
-	//  .current_dir(project_root_path_with("debug_expansions"))
-	//  .arg("--edition")
-	//  .arg("2021")
-	//  .arg("--config")
-	//  .arg("tab_spaces=1")
-	//  .arg(format!("{}.rs", orig_fn_string))
-	//  .output()
-	//  .unwrap();
-
-	output
+	BackendFn { server_fns, client_fn: wasm_side, items }
+}
+
+/// The dispatch name suffix, e.g. `<User,i64>`, and type arguments of each instantiation of a `#[backend]` function;
+/// just an empty suffix if it's not generic.
+fn fn_instances(args: &args::BackendArgs, orig_fn: &syn::ItemFn) -> Vec<(String, Vec<syn::Type>)> {
+	let generics = &orig_fn.sig.generics;
+	let type_params: Vec<_> = generics.type_params().map(|p| &p.ident).collect();
+
+	if let Some(param) = generics.const_params().next() {
+		abort!(param, "#[backend] functions can't have const generic parameters.");
+	}
+	if type_params.is_empty() {
+		if let Some(((ident, _), _)) = args.instantiate.first().and_then(|i| i.split_first()) {
+			abort!(ident, "`instantiate` only applies to generic functions.");
+		}
+		return vec![(String::new(), vec![])];
+	}
+	if args.instantiate.is_empty() {
+		abort!(generics, "Generic #[backend] functions need explicit instantiations."; help = "Add e.g. `#[backend(instantiate({} = MyType))]`.", type_params[0]);
+	}
+	if args.js {
+		abort!(generics, "Generic #[backend] functions can't be called from JS."; help = "Remove `js`, or write a non-generic wrapper function for JS.");
+	}
+
+	let mut instances: Vec<(String, Vec<syn::Type>)> = vec![];
+	for assignments in &args.instantiate {
+		for (ident, _) in assignments {
+			if !type_params.contains(&ident) {
+				abort!(ident, "`{}` is not a type parameter of `{}`.", ident, orig_fn.sig.ident);
+			}
+		}
+		let tys: Vec<_> = type_params
+			.iter()
+			.map(|param| match assignments.iter().find(|(ident, _)| ident == *param) {
+				Some((_, ty)) => ty.clone(),
+				None => abort!(assignments[0].0, "Instantiation does not assign type parameter `{}`.", param),
+			})
+			.collect();
+		let suffix = format!(
+			"<{}>",
+			tys.iter().map(|ty| quote!(#ty).to_string().replace(' ', "")).collect::<Vec<_>>().join(",")
+		);
+		if instances.iter().any(|(existing, _)| *existing == suffix) {
+			abort!(assignments[0].0, "`{}{}` is instantiated twice.", orig_fn.sig.ident, suffix);
+		}
+		instances.push((suffix, tys));
+	}
+	instances
 }
 
-fn read_backend_api_rs() -> syn::File {
-	syn::parse_file(&std::fs::read_to_string(backend_api_rs_path()).unwrap_or_default()).unwrap()
+/// Aborts if `name`, from `#[backend(name = "...")]`, is already the dispatch name of another function in this crate,
+/// with a clearer error than the duplicate symbol that each explicit name also gets, which catches names used in
+/// several crates when linking. The server checks explicit names against module-path names when it starts.
+fn register_explicit_name(name: &str, ident: &syn::Ident) {
+	static NAMES: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+	// rust-analyzer expands items again as they're edited.
+	if is_rust_analyzer() {
+		return;
+	}
+	let mut names = NAMES.lock().unwrap();
+	if names.iter().any(|existing| existing == name) {
+		abort!(ident, "Dispatch name `{}` is already used by another #[backend] function.", name; help = "Give one of them a different `#[backend(name = \"...\")]`.");
+	}
+	names.push(name.to_string());
 }
 
-fn write_backend_api_rs(file: syn::File) {
-	let mut output = "// This file is auto-generated by Turbocharger.\n// Check it into version control to track API changes over time.\n// To regenerate: \"cargo clean && rm backend_api.rs && cargo check\"\n".to_string();
-	let mut items = file.items;
-	items.sort_by_key(|item| match item {
-		syn::Item::Struct(s) => s.ident.to_string().to_lowercase(),
-		syn::Item::Fn(f) => f.sig.ident.to_string().to_lowercase(),
-		_ => unreachable!(),
+/// Records `api_item` in the API manifest of the crate being compiled, `{unit dir}/api.rs`, along with the file it's in,
+/// from which `turbocharger-api assemble` finds its module path, so functions with the same name in different modules
+/// stay apart, like their dispatch names. The manifest lists the items recorded during this compilation, so removed items drop out when the crate is
+/// rebuilt; `turbocharger-api assemble` merges the manifests into `backend_api.rs`, and ignores a crate's manifest once
+/// it's been rebuilt without writing one, i.e. without any items left.
+fn record_api_item(api_item: syn::Item) {
+	static ITEMS: std::sync::Mutex<Vec<(Option<String>, String)>> = std::sync::Mutex::new(Vec::new());
+
+	let mut items = ITEMS.lock().unwrap();
+	items.push((manifest::source_file(), prettyplease::unparse(&parse_quote!( #api_item ))));
+	items.sort();
+	write_unit_file("api.rs", manifest::render(&items));
+}
+
+/// Writes the expansion of the item named `ident` to `{unit dir}/expanded/{ident}.rs`, for debugging.
+fn write_expansion(ident: &dyn std::fmt::Display, output: &proc_macro2::TokenStream) {
+	// Expansions from an earlier compilation may be of items that no longer exist.
+	static CLEAR: std::sync::Once = std::sync::Once::new();
+	CLEAR.call_once(|| {
+		std::fs::remove_dir_all(unit_dir().join("expanded")).ok();
 	});
-	for item in items {
-		output.push('\n');
-		output.push_str(&prettyplease::unparse(&parse_quote!( #item )));
+
+	write_unit_file(
+		&format!("expanded/{}.rs", ident),
+		prettyplease::unparse(&parse_quote!( #output )),
+	);
+}
+
+/// Replaces `{unit dir}/{path}` with `contents` atomically, since another build of the same crate, e.g. `cargo check`
+/// alongside `cargo build`, may be writing it too. Failures are ignored; these files don't affect the build.
+fn write_unit_file(path: &str, contents: String) {
+	// Doctests are compiled with their package's environment, and would pass for its library.
+	if std::env::var_os("UNSTABLE_RUSTDOC_TEST_PATH").is_some() {
+		return;
 	}
-	if output != std::fs::read_to_string(backend_api_rs_path()).unwrap_or_default() {
-		std::fs::write(backend_api_rs_path(), output).unwrap();
+	let path = unit_dir().join(path);
+	let tmp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+	if let Some(dir) = path.parent() {
+		std::fs::create_dir_all(dir).ok();
+	}
+	if std::fs::write(&tmp_path, contents).is_ok() && std::fs::rename(&tmp_path, &path).is_err() {
+		std::fs::remove_file(&tmp_path).ok();
 	}
 }
 
-fn backend_api_rs_path() -> std::path::PathBuf {
-	project_root_path_with("backend_api.rs")
+/// Where `#[backend]` records the crate being compiled: `{target dir}/turbocharger/{package}/{crate name}`, with the
+/// crate name prefixed by `bin-` for binaries, so builds of different crates never write the same files.
+fn unit_dir() -> std::path::PathBuf {
+	let package = std::env::var("CARGO_PKG_NAME").unwrap_or_default();
+	let crate_name = std::env::var("CARGO_CRATE_NAME").unwrap_or_default();
+	let unit = match std::env::var_os("CARGO_BIN_NAME") {
+		Some(_) => format!("bin-{}", crate_name),
+		None => crate_name,
+	};
+	target_dir().join("turbocharger").join(package).join(unit)
 }
 
-fn project_root_path_with<P: AsRef<std::path::Path>>(pushpath: P) -> std::path::PathBuf {
-	let mut path = std::path::PathBuf::from(env!("OUT_DIR"));
-	while path.file_name() != Some(std::ffi::OsStr::new("target")) {
-		path.pop();
-	}
-	path.pop();
-	path.push(pushpath);
-	path
+/// The target directory, found from this crate's `OUT_DIR`: `{target dir}/{profile}/build/turbocharger-impl-{hash}/out`.
+fn target_dir() -> std::path::PathBuf {
+	std::path::Path::new(env!("OUT_DIR")).ancestors().nth(4).unwrap().to_path_buf()
 }
 
 fn is_rust_analyzer() -> bool {
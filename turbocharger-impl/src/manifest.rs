@@ -0,0 +1,48 @@
+//! The API manifest of the crate being compiled, with the items of each source file in a `mod` of their own.
+
+/// The source file the item being expanded is in, if it's known.
+pub(crate) fn source_file() -> Option<String> {
+	let file = proc_macro::Span::call_site().local_file()?;
+	Some(file.canonicalize().unwrap_or(file).to_string_lossy().into_owned())
+}
+
+/// The manifest of `items`, each already unparsed, along with the source file it's in. The items of each file are in a
+/// `#[path = "..."] mod source_file`, which `turbocharger-api assemble` replaces with the file's module path, since
+/// proc macros can't tell which module they're expanding in.
+pub(crate) fn render(items: &[(Option<String>, String)]) -> String {
+	let mut output = vec![];
+	let mut files = std::collections::BTreeMap::<_, Vec<_>>::new();
+	for (file, item) in items {
+		match file {
+			Some(file) => files.entry(file).or_default().push(item.as_str()),
+			None => output.push(item.clone()),
+		}
+	}
+	for (file, file_items) in files {
+		let module = format!("#[path = {:?}]\nmod source_file {{\n{}}}", file, file_items.concat());
+		output.push(match syn::parse_file(&module) {
+			Ok(file) => prettyplease::unparse(&file),
+			Err(_) => module + "\n",
+		});
+	}
+	output.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_render() {
+		let items = [
+			(None, "async fn root() {}\n".to_string()),
+			(Some("/app/src/app.rs".into()), "async fn run_test() -> i32 {}\n".into()),
+			(Some("/app/src/app.rs".into()), "mod nested {\n    async fn run_test() -> i32 {}\n}\n".into()),
+			(Some("/app/src/other.rs".into()), "async fn run_test() -> i32 {}\n".into()),
+		];
+		assert_eq!(
+			render(&items),
+			"async fn root() {}\n\n#[path = \"/app/src/app.rs\"]\nmod source_file {\n    async fn run_test() -> i32 {}\n    mod nested {\n        async fn run_test() -> i32 {}\n    }\n}\n\n#[path = \"/app/src/other.rs\"]\nmod source_file {\n    async fn run_test() -> i32 {}\n}\n"
+		);
+	}
+}
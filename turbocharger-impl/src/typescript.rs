@@ -0,0 +1,254 @@
+use quote::ToTokens;
+
+/// TypeScript type of a Rust type's `serde-wasm-bindgen` representation.
+pub fn ts_type(ty: &syn::Type) -> String {
+	match ty {
+		syn::Type::Reference(syn::TypeReference { elem, .. })
+		| syn::Type::Paren(syn::TypeParen { elem, .. })
+		| syn::Type::Group(syn::TypeGroup { elem, .. }) => ts_type(elem),
+		syn::Type::Slice(syn::TypeSlice { elem, .. }) | syn::Type::Array(syn::TypeArray { elem, .. }) => {
+			format!("{}[]", ts_type_grouped(elem))
+		}
+		syn::Type::Tuple(syn::TypeTuple { elems, .. }) if elems.is_empty() => "null".into(),
+		syn::Type::Tuple(syn::TypeTuple { elems, .. }) => {
+			format!("[{}]", elems.iter().map(ts_type).collect::<Vec<_>>().join(", "))
+		}
+		syn::Type::Path(syn::TypePath { qself: None, path }) => ts_path(path),
+		_ => "any".into(),
+	}
+}
+
+fn ts_path(path: &syn::Path) -> String {
+	let segment = path.segments.last().unwrap();
+	let args = type_args(segment);
+	let ident = segment.ident.to_string();
+
+	match (ident.as_str(), args.as_slice()) {
+		(
+			"i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128"
+			| "usize" | "f32" | "f64",
+			[],
+		) => "number".into(),
+		("bool", []) => "boolean".into(),
+		("String" | "str" | "char", []) => "string".into(),
+		("Box" | "Arc" | "Rc" | "Cow", [.., ty]) => ts_type(ty),
+		("Option", [ty]) => format!("{} | undefined", ts_type(ty)),
+		("Vec" | "VecDeque" | "HashSet" | "BTreeSet", [ty]) => format!("{}[]", ts_type_grouped(ty)),
+		("HashMap" | "BTreeMap", [k, v]) => format!("Map<{}, {}>", ts_type(k), ts_type(v)),
+		("Result", [t, e]) => format!("{{ Ok: {} }} | {{ Err: {} }}", ts_type(t), ts_type(e)),
+		(_, []) => ident,
+		(_, args) => {
+			format!("{}<{}>", ident, args.iter().map(|ty| ts_type(ty)).collect::<Vec<_>>().join(", "))
+		}
+	}
+}
+
+/// TypeScript type of the value `wasm-bindgen` converts a Rust type to, which differs from `ts_type` in having
+/// `bigint`s for 64- and 128-bit integers and typed arrays for `Vec`s of numbers.
+pub fn ts_abi_type(ty: &syn::Type) -> String {
+	let path = match ty {
+		syn::Type::Reference(syn::TypeReference { elem, .. })
+		| syn::Type::Paren(syn::TypeParen { elem, .. })
+		| syn::Type::Group(syn::TypeGroup { elem, .. }) => return ts_abi_type(elem),
+		syn::Type::Slice(syn::TypeSlice { elem, .. }) => return ts_abi_array(elem),
+		syn::Type::Path(syn::TypePath { qself: None, path }) => path,
+		_ => return ts_type(ty),
+	};
+	let segment = path.segments.last().unwrap();
+
+	match (segment.ident.to_string().as_str(), type_args(segment).as_slice()) {
+		("i64" | "u64" | "i128" | "u128", []) => "bigint".into(),
+		("Box" | "Arc" | "Rc" | "Cow", [.., ty]) => ts_abi_type(ty),
+		("Option", [ty]) => format!("{} | undefined", ts_abi_type(ty)),
+		("Vec", [ty]) => ts_abi_array(ty),
+		_ => ts_type(ty),
+	}
+}
+
+/// TypeScript type of a `Vec` or slice of `elem` passed through `wasm-bindgen`.
+fn ts_abi_array(elem: &syn::Type) -> String {
+	let typed_array = match elem {
+		syn::Type::Path(syn::TypePath { qself: None, path }) => match path.get_ident() {
+			Some(ident) if ident == "u8" => Some("Uint8Array"),
+			Some(ident) if ident == "i8" => Some("Int8Array"),
+			Some(ident) if ident == "u16" => Some("Uint16Array"),
+			Some(ident) if ident == "i16" => Some("Int16Array"),
+			Some(ident) if ident == "u32" || ident == "usize" => Some("Uint32Array"),
+			Some(ident) if ident == "i32" || ident == "isize" => Some("Int32Array"),
+			Some(ident) if ident == "u64" => Some("BigUint64Array"),
+			Some(ident) if ident == "i64" => Some("BigInt64Array"),
+			Some(ident) if ident == "f32" => Some("Float32Array"),
+			Some(ident) if ident == "f64" => Some("Float64Array"),
+			_ => None,
+		},
+		_ => None,
+	};
+	match typed_array {
+		Some(typed_array) => typed_array.into(),
+		None => format!("{}[]", grouped(ts_abi_type(elem))),
+	}
+}
+
+fn type_args(segment: &syn::PathSegment) -> Vec<&syn::Type> {
+	match &segment.arguments {
+		syn::PathArguments::AngleBracketed(args) => args
+			.args
+			.iter()
+			.filter_map(|arg| match arg {
+				syn::GenericArgument::Type(ty) => Some(ty),
+				_ => None,
+			})
+			.collect(),
+		_ => vec![],
+	}
+}
+
+/// Like `ts_type`, but parenthesized if needed to be followed by `[]`.
+fn ts_type_grouped(ty: &syn::Type) -> String {
+	grouped(ts_type(ty))
+}
+
+fn grouped(ts: String) -> String {
+	if ts.contains(' ') && !ts.starts_with('[') && !ts.starts_with('{') {
+		format!("({})", ts)
+	} else {
+		ts
+	}
+}
+
+/// TypeScript declaration of an enum's adjacently tagged (`{ type, value }`) representation, as a discriminated union.
+pub fn ts_enum(item: &syn::ItemEnum) -> String {
+	let variants: Vec<_> = item
+		.variants
+		.iter()
+		.map(|variant| {
+			let tag = format!("type: \"{}\"", variant.ident);
+			match &variant.fields {
+				syn::Fields::Unit => format!("{{ {} }}", tag),
+				syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+					format!("{{ {}; value: {} }}", tag, ts_type(&fields.unnamed[0].ty))
+				}
+				syn::Fields::Unnamed(fields) => format!(
+					"{{ {}; value: [{}] }}",
+					tag,
+					fields.unnamed.iter().map(|f| ts_type(&f.ty)).collect::<Vec<_>>().join(", ")
+				),
+				syn::Fields::Named(fields) => format!("{{ {}; value: {} }}", tag, ts_fields(fields)),
+			}
+		})
+		.collect();
+	format!("export type {} =\n | {};", item.ident, variants.join("\n | "))
+}
+
+/// TypeScript declaration of a plain struct's `serde-wasm-bindgen` representation: an interface, where `Option` fields
+/// may be left out, or an alias for newtype, tuple and unit structs.
+pub fn ts_interface(item: &syn::ItemStruct) -> String {
+	let ident = &item.ident;
+	match &item.fields {
+		syn::Fields::Named(fields) => {
+			let fields: Vec<_> = fields
+				.named
+				.iter()
+				.map(|f| match crate::extract::extract_option(&f.ty) {
+					Some(ty) => format!("  {}?: {};\n", f.ident.to_token_stream(), ts_type(ty)),
+					None => format!("  {}: {};\n", f.ident.to_token_stream(), ts_type(&f.ty)),
+				})
+				.collect();
+			format!("export interface {} {{\n{}}}", ident, fields.concat())
+		}
+		syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+			format!("export type {} = {};", ident, ts_type(&fields.unnamed[0].ty))
+		}
+		syn::Fields::Unnamed(fields) => format!(
+			"export type {} = [{}];",
+			ident,
+			fields.unnamed.iter().map(|f| ts_type(&f.ty)).collect::<Vec<_>>().join(", ")
+		),
+		syn::Fields::Unit => format!("export type {} = null;", ident),
+	}
+}
+
+/// TypeScript object type of named fields, e.g. `{ name: string; age: number }`.
+pub fn ts_fields(fields: &syn::FieldsNamed) -> String {
+	let fields: Vec<_> = fields
+		.named
+		.iter()
+		.map(|f| format!("{}: {}", f.ident.to_token_stream(), ts_type(&f.ty)))
+		.collect();
+	format!("{{ {} }}", fields.join("; "))
+}
+
+/// Name of the React hook for a `#[backend(js)]` function, e.g. `useCountUp` for `count_up`.
+pub fn react_hook_name(fn_name: &str) -> String {
+	let mut name = "use".to_string();
+	for word in fn_name.split('_').filter(|word| !word.is_empty()) {
+		let mut chars = word.chars();
+		name.extend(chars.next().map(|c| c.to_ascii_uppercase()));
+		name.extend(chars);
+	}
+	name
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn ts(ty: &str) -> String {
+		ts_type(&syn::parse_str(ty).unwrap())
+	}
+
+	#[test]
+	fn test_ts_type() {
+		assert_eq!(ts("i64"), "number");
+		assert_eq!(ts("&str"), "string");
+		assert_eq!(ts("()"), "null");
+		assert_eq!(ts("(u8, String)"), "[number, string]");
+		assert_eq!(ts("Vec<Option<bool>>"), "(boolean | undefined)[]");
+		assert_eq!(ts("std::collections::HashMap<String, Person>"), "Map<string, Person>");
+		assert_eq!(ts("Box<Shape>"), "Shape");
+		assert_eq!(ts("Page<Person>"), "Page<Person>");
+	}
+
+	#[test]
+	fn test_ts_abi_type() {
+		let ts = |ty: &str| ts_abi_type(&syn::parse_str(ty).unwrap());
+		assert_eq!(ts("i64"), "bigint");
+		assert_eq!(ts("i32"), "number");
+		assert_eq!(ts("Option<u64>"), "bigint | undefined");
+		assert_eq!(ts("Vec<u8>"), "Uint8Array");
+		assert_eq!(ts("Vec<Option<String>>"), "(string | undefined)[]");
+		assert_eq!(ts("Person"), "Person");
+	}
+
+	#[test]
+	fn test_ts_interface() {
+		let item: syn::ItemStruct = syn::parse_str(
+			"struct Person { name: String, friends: Vec<Person>, pets: HashMap<String, Pet>, boss: Option<Box<Person>> }",
+		)
+		.unwrap();
+		assert_eq!(
+			ts_interface(&item),
+			"export interface Person {\n  name: string;\n  friends: Person[];\n  pets: Map<string, Pet>;\n  boss?: Person;\n}"
+		);
+		let item: syn::ItemStruct = syn::parse_str("struct Point(f64, f64);").unwrap();
+		assert_eq!(ts_interface(&item), "export type Point = [number, number];");
+	}
+
+	#[test]
+	fn test_react_hook_name() {
+		assert_eq!(react_hook_name("count_up"), "useCountUp");
+		assert_eq!(react_hook_name("add"), "useAdd");
+		assert_eq!(react_hook_name("_get_v2"), "useGetV2");
+	}
+
+	#[test]
+	fn test_ts_enum() {
+		let item: syn::ItemEnum =
+			syn::parse_str("enum Shape { Empty, Circle { radius: f64 }, Rect(f64, f64), Named(String) }")
+				.unwrap();
+		assert_eq!(
+			ts_enum(&item),
+			"export type Shape =\n | { type: \"Empty\" }\n | { type: \"Circle\"; value: { radius: number } }\n | { type: \"Rect\"; value: [number, number] }\n | { type: \"Named\"; value: string };"
+		);
+	}
+}
@@ -10,6 +10,34 @@ it("does stuff", async function () {
 	expect(await backend.one_hundred()).to.equal(100);
 	expect(await backend.two_hundred()).to.equal(200);
 	expect(await backend.two_hundred_increment()).to.equal(201);
+	expect(await backend.three_hundred()).to.equal(300);
+	expect(await backend.connection_local_or_none()).to.equal(true);
+	expect(await backend.greeting()).to.equal("hello");
+	expect(await backend.scale_shape({ type: "Circle", value: { radius: 2 } }, 3)).to.deep.equal({
+		type: "Circle",
+		value: { radius: 6 },
+	});
+	expect(await backend.scale_shape({ type: "Empty" }, 3)).to.deep.equal({ type: "Empty" });
+	const team = { name: "A", members: [{ name: "Ann", age: 30 }], scores: new Map([["q1", 2], ["q2", 3]]) };
+	expect(await backend.promote(team, "Ann")).to.deep.equal({
+		name: "A",
+		members: [],
+		lead: { name: "Ann", age: 30 },
+		scores: new Map([["q1", 2], ["q2", 3]]),
+	});
+	expect((await backend.promote(team, "Bob").catch((e) => e)).message).to.contain("no such member");
+	expect(await backend.total_scores([team])).to.deep.equal(new Map([["A", 5]]));
+	expect(await backend.add(2, 3)).to.equal(5);
+	expect(await backend.double(21)).to.equal(42);
+	expect((await backend.error_test().catch((e) => e)).message).to.contain("expected error");
+	const controller = new AbortController();
+	const aborted = backend.one_hundred({ signal: controller.signal });
+	controller.abort();
+	expect((await aborted.catch((e) => e)).name).to.equal("AbortError");
+	const counts = [];
+	for await (const n of backend.count_up(3)) counts.push(n);
+	expect(counts).to.deep.equal([0, 1, 2]);
+	for await (const n of backend.count_up(3)) if (n === 1) break;
 
 	// backend.set_socket_url("ws://localhost:8080/turbocharger_socket");
 	// let person = Object.assign(new backend.Person(), { name: "Bob" });
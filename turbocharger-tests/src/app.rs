@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
 #![cfg_attr(feature = "wasm", allow(dead_code))]
 
+use std::collections::HashMap;
 use turbocharger::prelude::*;
 
 #[backend(js)]
@@ -27,9 +28,184 @@ pub async fn two_hundred() -> i32 {
 	*two_hundred
 }
 
-#[backend(js)]
+#[backend(js, invalidates = "two_hundred")]
 pub async fn two_hundred_increment() -> i32 {
 	connection_local!(two_hundred: &mut i32);
 	*two_hundred += 1;
 	*two_hundred
 }
+
+/// Ids of the `complete_later` calls that have completed.
+#[server_only]
+pub static COMPLETED: Lazy<std::sync::Mutex<Vec<i64>>> = Lazy::new(Default::default);
+
+/// Completes after a moment, to test that the client can cancel calls, and that disconnecting doesn't.
+#[backend]
+pub async fn complete_later(id: i64) -> i64 {
+	tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+	COMPLETED.lock().unwrap().push(id);
+	id
+}
+
+/// Counts calls in the client's session, to test that sessions are resumed.
+#[backend]
+pub async fn session_visits() -> i64 {
+	session_local!(visits: &mut i64);
+	*visits += 1;
+	*visits
+}
+
+#[backend(js)]
+pub async fn error_test() -> Result<i32, tracked::StringError> {
+	Err("expected error")?
+}
+
+#[server_only]
+pub struct Greeting(pub String);
+
+#[backend(js)]
+pub async fn greeting() -> String {
+	state!(Greeting).0.clone()
+}
+
+#[backend(js)]
+pub async fn three_hundred() -> i32 {
+	connection_local!(three_hundred: &mut i32 = 300);
+	*three_hundred
+}
+
+#[backend(js)]
+pub async fn connection_local_or_none() -> bool {
+	connection_local!(maybe: Option<&mut i32>);
+	maybe.is_some()
+}
+
+/// A shape, to test `#[backend(js)]` enums.
+#[backend(js)]
+pub enum Shape {
+	Empty,
+	Circle { radius: f64 },
+	Rect(f64, f64),
+}
+
+#[backend(js)]
+pub async fn scale_shape(shape: Shape, factor: f64) -> Shape {
+	match shape {
+		Shape::Empty => Shape::Empty,
+		Shape::Circle { radius } => Shape::Circle { radius: radius * factor },
+		Shape::Rect(w, h) => Shape::Rect(w * factor, h * factor),
+	}
+}
+
+/// A team, to test `#[backend(js, plain)]` structs, which JS gets as plain objects.
+#[backend(js, plain)]
+#[derive(Debug, Default, PartialEq)]
+pub struct Team {
+	pub name: String,
+	pub members: Vec<Member>,
+	pub lead: Option<Member>,
+	pub scores: HashMap<String, i32>,
+}
+
+#[backend(js, plain)]
+#[derive(Debug, Default, PartialEq)]
+pub struct Member {
+	pub name: String,
+	pub age: u32,
+}
+
+#[backend(js, plain)]
+pub async fn promote(team: Team, name: String) -> Result<Team, tracked::StringError> {
+	let mut team = team;
+	let position =
+		team.members.iter().position(|member| member.name == name).ok_or("no such member")?;
+	team.lead = Some(team.members.remove(position));
+	Ok(team)
+}
+
+#[backend(js, plain)]
+pub async fn total_scores(teams: Vec<Team>) -> HashMap<String, i32> {
+	teams.into_iter().map(|team| (team.name, team.scores.values().sum())).collect()
+}
+
+/// A page of results, to test generic `#[backend]` structs and functions.
+#[backend]
+#[derive(Debug, PartialEq)]
+pub struct Page<T> {
+	pub items: Vec<T>,
+	pub total: u64,
+}
+
+#[backend(instantiate(T = i64, T = String))]
+pub async fn first_page<T: Default + Clone>(fill: T, len: u64) -> Page<T> {
+	Page { items: vec![fill; len as usize], total: len * 2 }
+}
+
+#[backend(instantiate((K = String, V = bool)))]
+pub async fn pair<K, V>(key: K, value: V) -> (K, V) {
+	(key, value)
+}
+
+#[backend]
+impl Greeting {
+	/// Greets `name` with the shared `Greeting`.
+	pub async fn greet(&self, name: String) -> String {
+		format!("{}, {}", self.0, name)
+	}
+}
+
+/// A service with one instance per connection, to test `#[backend(connection_local)]` on `impl` blocks.
+#[server_only]
+#[derive(Default)]
+pub struct Tally {
+	total: i64,
+}
+
+#[backend(connection_local)]
+impl Tally {
+	pub async fn add(&mut self, n: i64) -> i64 {
+		self.total += n;
+		self.total
+	}
+
+	pub fn countdown(&self) -> impl Stream<Item = i64> {
+		turbocharger::futures_util::stream::iter((0..self.total).rev())
+	}
+}
+
+/// Functions exposed by `#[backend]` on a module, to test `backend_mod`.
+#[backend(js)]
+pub mod arithmetic {
+	use super::*;
+
+	pub async fn add(a: i32, b: i32) -> i32 {
+		helper(a) + b
+	}
+
+	pub fn count_up(to: i32) -> impl Stream<Item = i32> {
+		turbocharger::futures_util::stream::iter(0..to)
+	}
+
+	#[backend(instantiate(T = i32))]
+	pub async fn identity<T>(value: T) -> T {
+		value
+	}
+
+	fn helper(a: i32) -> i32 {
+		a
+	}
+}
+
+/// Same function name as `arithmetic::add`, to test that modules get different dispatch names.
+#[backend]
+pub mod strings {
+	pub async fn add(a: String, b: String) -> String {
+		a + &b
+	}
+}
+
+/// A dispatch name that doesn't depend on the module path, to test `#[backend(name = "...")]`.
+#[backend(js, name = "tests.double")]
+pub async fn double(n: i32) -> i32 {
+	n * 2
+}
@@ -4,10 +4,241 @@ mod app;
 #[tokio::main(flavor = "multi_thread", worker_threads = 10)]
 async fn main() {
 	let addr = std::net::SocketAddr::from(([0, 0, 0, 0], 8888));
-	let app = axum::routing::Router::new()
-		.route("/turbocharger_socket", axum::routing::get(turbocharger::ws_handler));
 	axum::Server::bind(&addr)
-		.serve(app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+		.serve(router().into_make_service_with_connect_info::<std::net::SocketAddr>())
 		.await
 		.unwrap();
 }
+
+fn router() -> axum::Router {
+	let state = turbocharger::StateMap::new().with(app::Greeting("hello".into()));
+	axum::routing::Router::new()
+		.route("/turbocharger_socket", axum::routing::get(turbocharger::ws_handler))
+		.layer(axum::Extension(state))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::{net::SocketAddr, time::Duration};
+	use tokio_tungstenite::tungstenite::Message;
+	use turbocharger::{
+		futures_util::{SinkExt, StreamExt},
+		serde::{de::DeserializeOwned, Serialize},
+	};
+
+	type Socket =
+		tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+	/// Starts a server like `main`'s on a free port.
+	async fn start_server() -> SocketAddr {
+		let server = axum::Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+			.serve(router().into_make_service_with_connect_info::<SocketAddr>());
+		let addr = server.local_addr();
+		tokio::spawn(server);
+		addr
+	}
+
+	async fn connect(addr: SocketAddr) -> Socket {
+		let url = format!("ws://{}/turbocharger_socket?stream_end=1", addr);
+		tokio_tungstenite::connect_async(url).await.unwrap().0
+	}
+
+	/// A request, as clients serialize it.
+	fn request(dispatch_name: &str, txid: i64, params: impl Serialize) -> Message {
+		Message::Binary(turbocharger::bincode::serialize(&(1i64, dispatch_name, txid, params)).unwrap())
+	}
+
+	/// Makes a call and waits for its result.
+	async fn call<R: DeserializeOwned>(
+		socket: &mut Socket,
+		dispatch_name: &str,
+		txid: i64,
+		params: impl Serialize,
+	) -> R {
+		socket.send(request(dispatch_name, txid, params)).await.unwrap();
+		response(socket, txid).await
+	}
+
+	/// Waits for the result of the call `txid`.
+	async fn response<R: DeserializeOwned>(socket: &mut Socket, txid: i64) -> R {
+		loop {
+			if let Message::Binary(data) = socket.next().await.unwrap().unwrap() {
+				if data[..8] == txid.to_le_bytes() {
+					let (_, result): (i64, R) = turbocharger::bincode::deserialize(&data).unwrap();
+					return result;
+				}
+			}
+		}
+	}
+
+	/// Waits for the items of the stream `txid` until it ends.
+	async fn stream_items<R: DeserializeOwned>(socket: &mut Socket, txid: i64) -> Vec<R> {
+		let mut items = vec![];
+		loop {
+			if let Message::Binary(data) = socket.next().await.unwrap().unwrap() {
+				if data[..8] == txid.to_le_bytes() {
+					let (_, item): (i64, R) = turbocharger::bincode::deserialize(&data).unwrap();
+					items.push(item);
+				} else if data[..8] == 3i64.to_le_bytes() && data[8..16] == txid.to_le_bytes() {
+					return items;
+				}
+			}
+		}
+	}
+
+	#[tokio::test]
+	async fn test_disconnect_mid_call() {
+		const COMPLETE_LATER: &str = "turbocharger_tests::app::complete_later";
+		let mut socket = connect(start_server().await).await;
+		assert_eq!(call::<i64>(&mut socket, COMPLETE_LATER, 256, (1i64,)).await, 1);
+
+		socket.send(request(COMPLETE_LATER, 257, (2i64,))).await.unwrap();
+		// re-sending a request cancels the call
+		socket.send(request(COMPLETE_LATER, 257, (2i64,))).await.unwrap();
+		socket.send(request(COMPLETE_LATER, 258, (3i64,))).await.unwrap();
+		// disconnecting lets the call run to completion
+		socket.close(None).await.unwrap();
+		let completed = async {
+			while !app::COMPLETED.lock().unwrap().contains(&3) {
+				tokio::time::sleep(Duration::from_millis(10)).await;
+			}
+		};
+		tokio::time::timeout(Duration::from_secs(10), completed).await.unwrap();
+		// the cancelled call started before the last one, and would have completed first
+		assert_eq!(*app::COMPLETED.lock().unwrap(), [1, 3]);
+	}
+
+	#[tokio::test]
+	async fn test_api_names() {
+		// the names `backend_api.rs` gives functions, which the generated clients call them by
+		let mut socket = connect(start_server().await).await;
+		assert_eq!(call::<i32>(&mut socket, "app::run_test", 1, ()).await, 42);
+		assert_eq!(call::<i32>(&mut socket, "app::arithmetic::add", 2, (1, 2)).await, 3);
+		let sum: String = call(&mut socket, "app::strings::add", 3, ("1", "2")).await;
+		assert_eq!(sum, "12");
+	}
+
+	#[tokio::test]
+	async fn test_generic_instantiations() {
+		// each instantiation has a dispatch name of its own, with its type arguments
+		let mut socket = connect(start_server().await).await;
+		let page: app::Page<i64> =
+			call(&mut socket, "turbocharger_tests::app::first_page<i64>", 1, (7i64, 2u64)).await;
+		assert_eq!(page, app::Page { items: vec![7, 7], total: 4 });
+		let page: app::Page<String> =
+			call(&mut socket, "turbocharger_tests::app::first_page<String>", 2, ("x", 1u64)).await;
+		assert_eq!(page, app::Page { items: vec!["x".to_string()], total: 2 });
+		let pair: (String, bool) =
+			call(&mut socket, "turbocharger_tests::app::pair<String,bool>", 3, ("key", true)).await;
+		assert_eq!(pair, ("key".to_string(), true));
+		let value: i32 =
+			call(&mut socket, "turbocharger_tests::app::arithmetic::identity<i32>", 4, (5i32,)).await;
+		assert_eq!(value, 5);
+	}
+
+	#[tokio::test]
+	async fn test_services() {
+		const TALLY_ADD: &str = "turbocharger_tests::app::Tally::add";
+		let addr = start_server().await;
+		let mut socket = connect(addr).await;
+		let greeting: String =
+			call(&mut socket, "turbocharger_tests::app::Greeting::greet", 1, ("world",)).await;
+		assert_eq!(greeting, "hello, world");
+
+		// a connection's `Tally` keeps its state across calls
+		assert_eq!(call::<i64>(&mut socket, TALLY_ADD, 2, (2i64,)).await, 2);
+		assert_eq!(call::<i64>(&mut socket, TALLY_ADD, 3, (3i64,)).await, 5);
+		socket.send(request("turbocharger_tests::app::Tally::countdown", 4, ())).await.unwrap();
+		assert_eq!(stream_items::<i64>(&mut socket, 4).await, [4, 3, 2, 1, 0]);
+
+		// and other connections have their own
+		let mut other = connect(addr).await;
+		assert_eq!(call::<i64>(&mut other, TALLY_ADD, 2, (1i64,)).await, 1);
+		assert_eq!(call::<i64>(&mut socket, TALLY_ADD, 5, (1i64,)).await, 6);
+	}
+
+	/// Calls `session_visits` on a new connection, first presenting `token` to resume a session, like clients do.
+	/// Returns the session's token, which the server sends before any responses, and the result.
+	async fn visit(addr: SocketAddr, token: Option<&str>) -> (String, i64) {
+		let mut socket = connect(addr).await;
+		if let Some(token) = token {
+			let msg = [&2i64.to_le_bytes()[..], token.as_bytes()].concat();
+			socket.send(Message::Binary(msg)).await.unwrap();
+		}
+		socket.send(request("turbocharger_tests::app::session_visits", 256, ())).await.unwrap();
+		let msg = socket.next().await.unwrap().unwrap().into_data();
+		assert_eq!(msg[..8], 2i64.to_le_bytes());
+		let token = String::from_utf8(msg[8..].to_vec()).unwrap();
+		(token, response(&mut socket, 256).await)
+	}
+
+	#[tokio::test]
+	async fn test_sessions() {
+		let store = turbocharger::session::MemorySessionStore::new(Duration::from_secs(60));
+		turbocharger::session::set_session_store(store);
+		let addr = start_server().await;
+
+		let (token, visits) = visit(addr, None).await;
+		assert_eq!(visits, 1);
+		assert_eq!(visit(addr, Some(&token)).await, (token.clone(), 2));
+
+		// an unknown token starts a new session
+		let (new_token, visits) = visit(addr, Some("bad")).await;
+		assert_ne!(new_token, "bad");
+		assert_ne!(new_token, token);
+		assert_eq!(visits, 1);
+	}
+
+	#[derive(rust_embed::RustEmbed)]
+	#[folder = "src-frontend"]
+	struct Frontend;
+
+	/// Fetches `path` from `addr`, waiting for the server to start.
+	fn get(addr: SocketAddr, path: &str) -> String {
+		use std::io::{Read, Write};
+		let mut stream = loop {
+			match std::net::TcpStream::connect(addr) {
+				Ok(stream) => break stream,
+				Err(_) => std::thread::sleep(Duration::from_millis(10)),
+			}
+		};
+		write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", path).unwrap();
+		let mut response = String::new();
+		stream.read_to_string(&mut response).unwrap();
+		response
+	}
+
+	#[tokio::test]
+	async fn test_ssr() {
+		use base64::Engine;
+
+		// `serve_ssr` binds the address itself
+		let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+		tokio::spawn(async move {
+			turbocharger::serve_ssr::<Frontend, _, _>(
+				&addr,
+				|_uri| async { format!("<p>{}</p>", app::strings::add("a".into(), "b".into()).await) },
+				std::time::Duration::from_secs(5),
+			)
+			.await
+		});
+		let page = tokio::task::spawn_blocking(move || get(addr, "/")).await.unwrap();
+		assert!(page.contains(r#"<div id="main"><p>ab</p></div>"#), "{}", page);
+
+		// the call's result, keyed like the client's first call with the same arguments
+		let start = r#"<script id="turbocharger-prefetched" type="application/octet-stream">"#;
+		let script = &page[page.find(start).unwrap() + start.len()..];
+		let script = &script[..script.find("</script>").unwrap()];
+		let bytes = base64::engine::general_purpose::STANDARD.decode(script).unwrap();
+		let prefetched: Vec<(Vec<u8>, Vec<u8>)> = turbocharger::bincode::deserialize(&bytes).unwrap();
+		let key = ("turbocharger_tests::app::strings::add", (&"a", &"b"));
+		assert_eq!(
+			prefetched,
+			[(
+				turbocharger::bincode::serialize(&key).unwrap(),
+				turbocharger::bincode::serialize("ab").unwrap()
+			)]
+		);
+	}
+}
@@ -5,7 +5,7 @@ use axum::{
 	body::{boxed, Full},
 	extract::{
 		ws::{Message, WebSocket, WebSocketUpgrade},
-		ConnectInfo, TypedHeader,
+		ConnectInfo, Extension, Query, TypedHeader,
 	},
 	headers,
 	http::{header, header::HeaderMap, StatusCode, Uri},
@@ -13,19 +13,13 @@ use axum::{
 	routing::{get, Router},
 	Server,
 };
-use futures_util::{SinkExt, StreamExt, TryFutureExt};
+use futures_util::{FutureExt, SinkExt, StreamExt, TryFutureExt};
 use rust_embed::RustEmbed;
-use std::{
-	collections::HashMap,
-	marker::PhantomData,
-	net::SocketAddr,
-	sync::{Arc, Mutex},
-};
+use std::{collections::HashMap, marker::PhantomData, net::SocketAddr};
 
 /// Convenience function to run a full server with static files from `rust_embed` and the Turbocharger WebSocket.
 pub async fn serve<A: 'static + RustEmbed>(addr: &SocketAddr) {
-	let app =
-		Router::new().route("/turbocharger_socket", get(ws_handler)).fallback(rust_embed_handler::<A>);
+	let app = router::<A>();
 
 	Server::bind(addr).serve(app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
 }
@@ -33,12 +27,150 @@ pub async fn serve<A: 'static + RustEmbed>(addr: &SocketAddr) {
 /// Convenience function to run a full server with static files from `rust_embed` and the Turbocharger WebSocket.
 #[cfg(feature = "tls")]
 pub async fn serve_tls<A: 'static + RustEmbed>(addr: &SocketAddr) {
-	let app =
-		Router::new().route("/turbocharger_socket", get(ws_handler)).fallback(rust_embed_handler::<A>);
+	let app = router::<A>();
+
+	tls::serve(addr, app).await.unwrap();
+}
+
+/// Like [`serve`], but pages are rendered on the server by `render`, given the page's URI, into `index.html`. The
+/// results of the `#[backend]` calls made while rendering are embedded in the page, so the WASM frontend's first calls
+/// use them instead of making requests. Pages that take longer than `timeout` to render are served unrendered.
+#[cfg(feature = "ssr")]
+pub async fn serve_ssr<A, R, F>(addr: &SocketAddr, render: R, timeout: std::time::Duration)
+where
+	A: 'static + RustEmbed,
+	R: Fn(Uri) -> F + Clone + Send + Sync + 'static,
+	F: std::future::Future<Output = String> + 'static,
+{
+	let app = ssr_router::<A, _, _>(render, timeout);
+
+	Server::bind(addr).serve(app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
+}
+
+/// Like [`serve_tls`], but pages are rendered on the server, as with [`serve_ssr`].
+#[cfg(all(feature = "tls", feature = "ssr"))]
+pub async fn serve_tls_ssr<A, R, F>(addr: &SocketAddr, render: R, timeout: std::time::Duration)
+where
+	A: 'static + RustEmbed,
+	R: Fn(Uri) -> F + Clone + Send + Sync + 'static,
+	F: std::future::Future<Output = String> + 'static,
+{
+	let app = ssr_router::<A, _, _>(render, timeout);
 
 	tls::serve(addr, app).await.unwrap();
 }
 
+fn router<A: 'static + RustEmbed>() -> Router {
+	routes().fallback(rust_embed_handler::<A>)
+}
+
+#[cfg(feature = "ssr")]
+fn ssr_router<A, R, F>(render: R, timeout: std::time::Duration) -> Router
+where
+	A: 'static + RustEmbed,
+	R: Fn(Uri) -> F + Clone + Send + Sync + 'static,
+	F: std::future::Future<Output = String> + 'static,
+{
+	routes().fallback(
+		move |uri: Uri,
+		      headers: HeaderMap,
+		      user_agent: Option<TypedHeader<headers::UserAgent>>,
+		      ConnectInfo(addr): ConnectInfo<SocketAddr>,
+		      state: Option<Extension<crate::StateMap>>| {
+			ssr_handler::<A, _, _>(render.clone(), timeout, uri, headers, user_agent, addr, state)
+		},
+	)
+}
+
+/// The Turbocharger WebSocket, and metrics if enabled.
+fn routes() -> Router {
+	crate::dispatch::check();
+
+	let app = Router::new().route("/turbocharger_socket", get(ws_handler));
+
+	#[cfg(feature = "metrics")]
+	let app = match crate::metrics::route() {
+		Some(route) => app.route(&route, get(crate::metrics::metrics_handler)),
+		None => app,
+	};
+
+	app
+}
+
+/// Serves pages rendered by `render` within `timeout`, and static files from rust_embed.
+#[cfg(feature = "ssr")]
+async fn ssr_handler<A, R, F>(
+	render: R,
+	timeout: std::time::Duration,
+	uri: Uri,
+	headers: HeaderMap,
+	user_agent: Option<TypedHeader<headers::UserAgent>>,
+	addr: SocketAddr,
+	state: Option<Extension<crate::StateMap>>,
+) -> Response
+where
+	A: RustEmbed,
+	R: Fn(Uri) -> F + Send + 'static,
+	F: std::future::Future<Output = String> + 'static,
+{
+	// paths of files, e.g. the WASM, are served as they are; other paths are the frontend's routes
+	let path = uri.path().trim_start_matches('/');
+	if !(path.is_empty() || path == "index.html") && (path.contains('.') || A::get(path).is_some()) {
+		return rust_embed_handler::<A>(uri, headers).await.into_response();
+	}
+	let index_html = match A::get("index.html") {
+		Some(index_html) => String::from_utf8_lossy(&index_html.data).into_owned(),
+		None => return rust_embed_handler::<A>(uri, headers).await.into_response(),
+	};
+
+	let connection_info = super::ConnectionInfo {
+		remote_addr: Some(addr),
+		user_agent: user_agent.map(|TypedHeader(ua)| ua.as_str().into()),
+		state: match state {
+			Some(Extension(state)) => state,
+			None => crate::global_state(),
+		},
+		connection_local: Default::default(),
+		session: None,
+	};
+
+	let page = render_page(index_html, connection_info, move || render(uri), timeout).await;
+	([(header::CONTENT_TYPE, "text/html; charset=utf-8")], page).into_response()
+}
+
+/// `index_html` with the page rendered by `render`'s future and the results of the calls it made, or unchanged if
+/// rendering fails or takes longer than `timeout`.
+#[cfg(feature = "ssr")]
+async fn render_page<R, F>(
+	index_html: String,
+	connection_info: super::ConnectionInfo,
+	render: R,
+	timeout: std::time::Duration,
+) -> String
+where
+	R: FnOnce() -> F + Send + 'static,
+	F: std::future::Future<Output = String> + 'static,
+{
+	// the render future needn't be `Send`, so it runs on a thread of its own
+	let runtime = tokio::runtime::Handle::current();
+	let rendered = tokio::task::spawn_blocking(move || {
+		crate::ssr::capture(connection_info, || runtime.block_on(tokio::time::timeout(timeout, render())))
+	})
+	.await;
+
+	match rendered {
+		Ok((Ok(html), prefetched)) => crate::ssr::inject(&index_html, &html, &prefetched),
+		Ok((Err(_), _)) => {
+			log::warn!("server-side rendering timed out");
+			index_html
+		}
+		Err(e) => {
+			log::error!("server-side rendering failed: {}", e);
+			index_html
+		}
+	}
+}
+
 /// Axum handler for serving static files from rust_embed.
 pub async fn rust_embed_handler<A: RustEmbed>(uri: Uri, headers: HeaderMap) -> impl IntoResponse {
 	if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
@@ -106,6 +238,8 @@ pub async fn ws_handler(
 	ws: WebSocketUpgrade,
 	user_agent: Option<TypedHeader<headers::UserAgent>>,
 	ConnectInfo(addr): ConnectInfo<SocketAddr>,
+	state: Option<Extension<crate::StateMap>>,
+	query: Option<Query<HashMap<String, String>>>,
 ) -> impl IntoResponse {
 	#[cfg(debug_assertions)]
 	log::info!("websocket connecting from {}", addr);
@@ -113,24 +247,60 @@ pub async fn ws_handler(
 	let ua_str =
 		if let Some(TypedHeader(ua)) = user_agent { ua.as_str().into() } else { String::new() };
 
-	ws.on_upgrade(move |ws| handle_socket(ws, ua_str, addr))
+	let state = match state {
+		Some(Extension(state)) => state,
+		None => crate::global_state(),
+	};
+
+	// clients from before stream ends were signalled don't expect the message
+	let stream_end = query.as_ref().is_some_and(|Query(query)| query.contains_key("stream_end"));
+
+	ws.on_upgrade(move |ws| handle_socket(ws, ua_str, addr, state, stream_end))
 }
 
-async fn handle_socket(ws: WebSocket, ua: String, addr: SocketAddr) {
+async fn handle_socket(
+	ws: WebSocket,
+	ua: String,
+	addr: SocketAddr,
+	state: crate::StateMap,
+	stream_end: bool,
+) {
 	#[cfg(debug_assertions)]
 	log::info!("websocket connected");
 
-	let (mut ws_tx, mut ws_rx) = ws.split();
+	#[cfg(feature = "metrics")]
+	let _connection = crate::metrics::Connection::open();
+
+	let (mut ws_tx, ws_rx) = ws.split();
+
+	// a client resuming a session sends its token first; otherwise, the first message is a request
+	let mut ws_rx = ws_rx.peekable();
+	let token = match std::pin::Pin::new(&mut ws_rx).peek().await {
+		Some(Ok(msg)) => crate::session::token_message(&msg.clone().into_data()).map(String::from),
+		_ => None,
+	};
+	if token.is_some() {
+		ws_rx.next().await;
+	}
+	let session = crate::session::Session::resume_or_create(token.as_deref());
 	let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
 	let mut rx = tokio_stream::wrappers::UnboundedReceiverStream::new(rx);
-	let triggers: Arc<Mutex<HashMap<i64, stream_cancel::Trigger>>> = Default::default();
+	let mut calls = Calls::new();
 
 	let connection_info = super::ConnectionInfo {
 		remote_addr: Some(addr),
 		user_agent: Some(ua),
+		state,
 		connection_local: Default::default(),
+		session,
 	};
 
+	if let Some(session) = &connection_info.session {
+		let mut msg = crate::SESSION_TOKEN_TXID.to_le_bytes().to_vec();
+		msg.extend_from_slice(session.token().as_bytes());
+		tx.send(Message::Binary(msg)).ok();
+	}
+
 	tokio::task::spawn(async move {
 		while let Some(msg) = rx.next().await {
 			ws_tx
@@ -150,34 +320,139 @@ async fn handle_socket(ws: WebSocket, ua: String, addr: SocketAddr) {
 				break;
 			}
 		};
+		let data = msg.clone().into_data();
+		if data.is_empty() {
+			continue;
+		}
+		let target_func = match crate::dispatch::deserialize(&data) {
+			Ok(target_func) => target_func,
+			Err(e) => {
+				if !matches!(msg, Message::Ping(_)) {
+					log::error!("websocket deserialize error: {} {:?}", e, msg);
+				}
+				continue;
+			}
+		};
+		// calls start in the order of their requests, so a request re-sent before the socket closes cancels its call
+		let tripwire = match calls.start(target_func.txid(), target_func.is_stream()) {
+			Some(tripwire) => tripwire,
+			None => continue,
+		};
 		let tx_clone = tx.clone();
-		let triggers_clone = triggers.clone();
 		let connection_info_clone = connection_info.clone();
 		tokio::task::spawn(async move {
-			let data = msg.clone().into_data();
-			if !data.is_empty() {
-				let target_func: Box<dyn crate::RPC> = match bincode::deserialize(&data) {
-					Ok(target_func) => target_func,
-					Err(e) => {
-						if !matches!(msg, Message::Ping(_)) {
-							log::error!("websocket deserialize error: {} {:?}", e, msg);
-						}
-						return;
-					}
-				};
-				let (trigger, tripwire) = stream_cancel::Tripwire::new();
-				let trigger = triggers_clone.lock().unwrap().insert(target_func.txid(), trigger);
-				drop(triggers_clone);
-				if let Some(trigger) = trigger {
-					trigger.cancel();
-				} else {
-					let sender = Box::new(move |response| tx_clone.send(Message::Binary(response)).unwrap());
-					target_func.execute(sender, Some(tripwire), Some(connection_info_clone)).await;
-				}
+			let txid = target_func.txid();
+			let send_end = stream_end && target_func.is_stream();
+			let cancelled = tripwire.clone();
+			let end_tx = tx_clone.clone();
+			let sender = Box::new(move |response| {
+				tx_clone.send(Message::Binary(response)).ok();
+			});
+			crate::telemetry::execute(
+				target_func,
+				data.len(),
+				sender,
+				Some(tripwire),
+				Some(connection_info_clone),
+			)
+			.await;
+			if send_end && !cancelled.now_or_never().unwrap_or_default() {
+				let mut msg = crate::STREAM_END_TXID.to_le_bytes().to_vec();
+				msg.extend_from_slice(&txid.to_le_bytes());
+				end_tx.send(Message::Binary(msg)).ok();
 			}
 		});
 	}
 
+	calls.close();
+
 	#[cfg(debug_assertions)]
 	log::info!("websocket disconnected")
 }
+
+/// The calls of a connection, by txid, which the client cancels by re-sending their requests. When the connection
+/// closes, its streams are cancelled, while unary calls run to completion, so a mutation isn't stopped midway.
+struct Calls(HashMap<i64, (stream_cancel::Trigger, bool)>);
+
+impl Calls {
+	fn new() -> Self {
+		Self(HashMap::new())
+	}
+
+	/// Starts the call `txid`, returning the tripwire that trips if it's cancelled, or cancels it if it has started.
+	fn start(&mut self, txid: i64, is_stream: bool) -> Option<stream_cancel::Tripwire> {
+		let (trigger, tripwire) = stream_cancel::Tripwire::new();
+		match self.0.insert(txid, (trigger, is_stream)) {
+			Some((trigger, _)) => {
+				trigger.cancel();
+				None
+			}
+			None => Some(tripwire),
+		}
+	}
+
+	/// Cancels the streams, and lets the unary calls run to completion.
+	fn close(self) {
+		for (trigger, is_stream) in self.0.into_values() {
+			// dropping a trigger cancels its call; disabling it doesn't
+			if !is_stream {
+				trigger.disable();
+			}
+		}
+	}
+}
+
+#[cfg(all(test, any(feature = "metrics", feature = "ssr")))]
+mod tests {
+	use super::*;
+
+	#[cfg(feature = "metrics")]
+	#[tokio::test]
+	async fn test_metrics_route() {
+		use tower::ServiceExt;
+
+		let status = |uri: &str| {
+			let request = axum::http::Request::get(uri).body(axum::body::Body::empty()).unwrap();
+			async move { routes().oneshot(request).await.unwrap().status() }
+		};
+		assert_eq!(status("/metrics").await, StatusCode::NOT_FOUND);
+
+		crate::metrics::set_route(Some("/internal/metrics"));
+		assert_eq!(status("/internal/metrics").await, StatusCode::OK);
+		assert_eq!(status("/metrics").await, StatusCode::NOT_FOUND);
+
+		crate::metrics::set_route(None);
+		assert_eq!(status("/internal/metrics").await, StatusCode::NOT_FOUND);
+	}
+
+	#[cfg(feature = "ssr")]
+	#[tokio::test(flavor = "multi_thread")]
+	async fn test_render_page() {
+		let index_html = r#"<div id="main"></div>"#.to_string();
+		let connection_info = || crate::ConnectionInfo {
+			remote_addr: None,
+			user_agent: None,
+			state: Default::default(),
+			connection_local: Default::default(),
+			session: None,
+		};
+		let timeout = std::time::Duration::from_millis(100);
+
+		let render = || async {
+			// as a call made while rendering does
+			assert!(crate::ssr::connection_info().is_some());
+			crate::ssr::record(vec![1], vec![2]);
+			"<p>hi</p>".to_string()
+		};
+		let page = render_page(index_html.clone(), connection_info(), render, timeout).await;
+		let prefetched = vec![(vec![1], vec![2])];
+		assert_eq!(page, crate::ssr::inject(&index_html, "<p>hi</p>", &prefetched));
+
+		// pages that take too long are served unrendered
+		let render = || async {
+			crate::ssr::record(vec![1], vec![2]);
+			std::future::pending().await
+		};
+		assert_eq!(render_page(index_html.clone(), connection_info(), render, timeout).await, index_html);
+	}
+}
@@ -1,36 +1,454 @@
-use dioxus::prelude::*;
+use crate::hooks::{next_items, Query, StreamStatus};
+use dioxus::{core::TaskId, prelude::*};
+use futures_util::{FutureExt, Stream, StreamExt};
+use std::{
+	any::Any,
+	cell::{Cell, Ref, RefCell, RefMut},
+	convert::Infallible,
+	rc::Rc,
+	sync::Arc,
+};
 
-pub fn use_stream<C, T, S>(
+/// Runs the stream that `stream` returns, and again when `dependencies` change, folding its items into the state with
+/// `callback`. Starting the stream again resets the state; doing so, or unmounting the component, drops the stream,
+/// which cancels it on the server.
+pub fn use_stream<C, T, S, D>(
 	cx: &ScopeState,
-	stream: impl FnOnce() -> S + 'static,
+	dependencies: D,
+	stream: impl FnOnce(D::Out) -> S,
 	callback: impl Fn(&mut C, T) + 'static,
-) -> &UseRef<C>
+) -> &UseStream<C>
 where
 	C: Default + 'static,
-	S: futures_util::Stream<Item = T>,
+	T: 'static,
+	S: Stream<Item = T> + 'static,
+	D: UseFutureDep,
 {
-	let state = use_ref(cx, <C as Default>::default);
+	use_try_stream(cx, dependencies, |dependencies| stream(dependencies).map(Ok), callback)
+}
 
-	let state_cloned = state.clone();
-	let _ = use_future(cx, (), |_| async move {
-		let stream = stream();
-		futures_util::pin_mut!(stream);
+/// Like [`use_stream`], for streams of `Result`s: `callback` gets the `Ok` items, and [`UseStream::error`] is the
+/// latest error until the next `Ok` item.
+pub fn use_try_stream<C, T, E, S, D>(
+	cx: &ScopeState,
+	dependencies: D,
+	stream: impl FnOnce(D::Out) -> S,
+	callback: impl Fn(&mut C, T) + 'static,
+) -> &UseStream<C, E>
+where
+	C: Default + 'static,
+	T: 'static,
+	E: 'static,
+	S: Stream<Item = Result<T, E>> + 'static,
+	D: UseFutureDep,
+{
+	let state = cx.use_hook(|| {
+		let (needs_run, query) = hook_query(cx);
+		UseStream {
+			update: Rc::new(Update {
+				update: cx.schedule_update(),
+				per_frame: Cell::new(false),
+				frame_pending: Default::default(),
+			}),
+			needs_run,
+			query,
+			dependencies: Vec::new(),
+			task: Default::default(),
+			status: Rc::new(Cell::new(StreamStatus::Connecting)),
+			value: Default::default(),
+			error: Default::default(),
+		}
+	});
 
-		while let Some(value) = futures_util::StreamExt::next(&mut stream).await {
-			let mut container = state_cloned.write();
-			callback(&mut *container, value);
-			state_cloned.needs_update();
+	if dependencies.clone().apply(&mut state.dependencies) || state.needs_run.get() {
+		state.needs_run.set(false);
+		let stream = state.query.start(|| stream(dependencies.out()));
+		if let Some(task) = state.task.take() {
+			cx.remove_future(task);
+		}
+		*state.value.borrow_mut() = C::default();
+		*state.error.borrow_mut() = None;
+		state.status.set(StreamStatus::Connecting);
+
+		let (query, task, status, value, error, update) = (
+			state.query.clone(),
+			state.task.clone(),
+			state.status.clone(),
+			state.value.clone(),
+			state.error.clone(),
+			state.update.clone(),
+		);
+		state.task.set(Some(cx.push_future(query.tracked(async move {
+			let handle = |item| match item {
+				Ok(item) => {
+					callback(&mut value.borrow_mut(), item);
+					*error.borrow_mut() = None;
+				}
+				Err(e) => *error.borrow_mut() = Some(e),
+			};
+			let stream = stream.fuse();
+			futures_util::pin_mut!(stream);
+			while let Some(items) = next_items(&mut stream).await {
+				items.into_iter().for_each(&handle);
+				status.set(StreamStatus::Open);
+				update.schedule();
+			}
+			status.set(StreamStatus::Ended);
+			// finished tasks' ids are reused
+			task.set(None);
+			update.schedule();
+		}))));
+	}
+
+	state
+}
+
+/// State of a stream run by [`use_stream`] or [`use_try_stream`].
+pub struct UseStream<C, E = Infallible> {
+	update: Rc<Update>,
+	needs_run: Rc<Cell<bool>>,
+	query: Rc<Query>,
+	dependencies: Vec<Box<dyn Any>>,
+	task: Rc<Cell<Option<TaskId>>>,
+	status: Rc<Cell<StreamStatus>>,
+	value: Rc<RefCell<C>>,
+	error: Rc<RefCell<Option<E>>>,
+}
+
+impl<C, E> UseStream<C, E> {
+	/// The state that `callback` has folded the items into.
+	pub fn read(&self) -> Ref<'_, C> {
+		self.value.borrow()
+	}
+
+	/// The state, for changes that show on the next render.
+	pub fn write(&self) -> RefMut<'_, C> {
+		(self.update.update)();
+		self.value.borrow_mut()
+	}
+
+	/// Whether the stream has items yet, or has ended.
+	pub fn status(&self) -> StreamStatus {
+		self.status.get()
+	}
+
+	/// The latest error, until the next `Ok` item.
+	pub fn error(&self) -> Option<Ref<'_, E>> {
+		Ref::filter_map(self.error.borrow(), Option::as_ref).ok()
+	}
+
+	/// Renders at most once per animation frame in browsers, however often items arrive.
+	pub fn coalesce_per_frame(&self) -> &Self {
+		self.update.per_frame.set(true);
+		self
+	}
+}
+
+/// Schedules renders for a [`UseStream`].
+struct Update {
+	update: Arc<dyn Fn()>,
+	#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+	per_frame: Cell<bool>,
+	#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+	frame_pending: Rc<Cell<bool>>,
+}
+
+impl Update {
+	fn schedule(&self) {
+		#[cfg(target_arch = "wasm32")]
+		if self.per_frame.get() {
+			use wasm_bindgen::{closure::Closure, JsCast};
+			if !self.frame_pending.replace(true) {
+				let (update, frame_pending) = (self.update.clone(), self.frame_pending.clone());
+				let on_frame = Closure::once_into_js(move || {
+					frame_pending.set(false);
+					update();
+				});
+				web_sys::window().unwrap().request_animation_frame(on_frame.unchecked_ref()).unwrap();
+			}
+			return;
+		}
+		(self.update)();
+	}
+}
+
+/// Calls a backend function with `call`, and again when `dependencies` change or on `refetch()`, tracking whether the
+/// call is in flight and its value or error. Starting the call again, or unmounting the component, drops the call in
+/// flight, which cancels it on the server. The call is also made again when a backend function whose
+/// `#[backend(invalidates = "...")]` names a function it called completes.
+pub fn use_backend<T, E, F, D>(
+	cx: &ScopeState,
+	dependencies: D,
+	call: impl FnOnce(D::Out) -> F,
+) -> &UseBackend<T, E>
+where
+	T: 'static,
+	E: 'static,
+	F: std::future::Future<Output = Result<T, E>> + 'static,
+	D: UseFutureDep,
+{
+	let state = cx.use_hook(|| {
+		let (needs_run, query) = hook_query(cx);
+		UseBackend {
+			update: cx.schedule_update(),
+			needs_run,
+			query,
+			dependencies: Vec::new(),
+			task: Default::default(),
+			loading: Default::default(),
+			result: Default::default(),
 		}
 	});
 
+	if dependencies.clone().apply(&mut state.dependencies) || state.needs_run.get() {
+		state.needs_run.set(false);
+		let future = state.query.start(|| call(dependencies.out()));
+		if let Some(task) = state.task.take() {
+			cx.remove_future(task);
+		}
+
+		// a call that completes without waiting, e.g. with a result embedded by server-side rendering, shows in this
+		// render, so hydration finds the same content that the server rendered
+		let mut future = Box::pin(state.query.clone().tracked(future));
+		if let Some(value) = future.as_mut().now_or_never() {
+			*state.result.borrow_mut() = Some(value);
+			state.loading.set(false);
+			return state;
+		}
+		state.loading.set(true);
+
+		let (task, loading, result, update) =
+			(state.task.clone(), state.loading.clone(), state.result.clone(), state.update.clone());
+		let in_flight = CallInFlight::new();
+		state.task.set(Some(cx.push_future(async move {
+			let value = future.await;
+			*result.borrow_mut() = Some(value);
+			// finished tasks' ids are reused
+			task.set(None);
+			loading.set(false);
+			drop(in_flight);
+			update();
+		})));
+	}
+
 	state
 }
 
+/// State of a backend call made by [`use_backend`].
+pub struct UseBackend<T, E> {
+	update: Arc<dyn Fn()>,
+	needs_run: Rc<Cell<bool>>,
+	query: Rc<Query>,
+	dependencies: Vec<Box<dyn Any>>,
+	task: Rc<Cell<Option<TaskId>>>,
+	loading: Rc<Cell<bool>>,
+	result: Rc<RefCell<Option<Result<T, E>>>>,
+}
+
+impl<T, E> UseBackend<T, E> {
+	/// Whether a call is in flight; the result of the previous one is kept until it completes.
+	pub fn loading(&self) -> bool {
+		self.loading.get()
+	}
+
+	/// The value returned by the latest call, if it succeeded.
+	pub fn value(&self) -> Option<Ref<'_, T>> {
+		Ref::filter_map(self.result.borrow(), |result| result.as_ref()?.as_ref().ok()).ok()
+	}
+
+	/// The error returned by the latest call, if it failed.
+	pub fn error(&self) -> Option<Ref<'_, E>> {
+		Ref::filter_map(self.result.borrow(), |result| result.as_ref()?.as_ref().err()).ok()
+	}
+
+	/// Calls the backend function again.
+	pub fn refetch(&self) {
+		self.query.rerun();
+	}
+}
+
+/// Makes backend calls with `mutate` when [`UseMutation::mutate`] is called, e.g. from an event handler, one at a time
+/// in order, tracking whether any are in flight and the value or error of the latest one. Completed calls of functions
+/// with `#[backend(invalidates = "...")]` make the [`use_backend`] and [`use_stream`] hooks that called the named
+/// functions run again. The `mutate` of the first render is used.
+pub fn use_mutation<A, T, E, F>(
+	cx: &ScopeState,
+	mutate: impl Fn(A) -> F + 'static,
+) -> &UseMutation<A, T, E>
+where
+	A: 'static,
+	T: 'static,
+	E: 'static,
+	F: std::future::Future<Output = Result<T, E>> + 'static,
+{
+	cx.use_hook(|| {
+		let (args_tx, mut args_rx) = futures_channel::mpsc::unbounded();
+		let state = UseMutation {
+			update: cx.schedule_update(),
+			args_tx,
+			pending: Default::default(),
+			result: Default::default(),
+		};
+
+		let (pending, result, update) =
+			(state.pending.clone(), state.result.clone(), state.update.clone());
+		cx.push_future(async move {
+			while let Some(args) = args_rx.next().await {
+				let value = mutate(args).await;
+				*result.borrow_mut() = Some(value);
+				pending.set(pending.get() - 1);
+				update();
+			}
+		});
+
+		state
+	})
+}
+
+/// State of backend calls made by [`use_mutation`].
+pub struct UseMutation<A, T, E> {
+	update: Arc<dyn Fn()>,
+	args_tx: futures_channel::mpsc::UnboundedSender<A>,
+	pending: Rc<Cell<usize>>,
+	result: Rc<RefCell<Option<Result<T, E>>>>,
+}
+
+impl<A, T, E> UseMutation<A, T, E> {
+	/// Makes a call with `args`, after the calls in flight.
+	pub fn mutate(&self, args: A) {
+		self.pending.set(self.pending.get() + 1);
+		self.args_tx.unbounded_send(args).ok();
+		(self.update)();
+	}
+
+	/// Whether any calls are in flight.
+	pub fn loading(&self) -> bool {
+		self.pending.get() > 0
+	}
+
+	/// The value returned by the latest call, if it succeeded.
+	pub fn value(&self) -> Option<Ref<'_, T>> {
+		Ref::filter_map(self.result.borrow(), |result| result.as_ref()?.as_ref().ok()).ok()
+	}
+
+	/// The error returned by the latest call, if it failed.
+	pub fn error(&self) -> Option<Ref<'_, E>> {
+		Ref::filter_map(self.result.borrow(), |result| result.as_ref()?.as_ref().err()).ok()
+	}
+}
+
+thread_local! {
+	static CALLS_IN_FLIGHT: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts a [`use_backend`] call in flight until dropped, with the call's task.
+struct CallInFlight;
+
+impl CallInFlight {
+	fn new() -> Self {
+		CALLS_IN_FLIGHT.with(|calls| calls.set(calls.get() + 1));
+		CallInFlight
+	}
+}
+
+impl Drop for CallInFlight {
+	fn drop(&mut self) {
+		CALLS_IN_FLIGHT.with(|calls| calls.set(calls.get() - 1));
+	}
+}
+
+/// The number of [`use_backend`] calls in flight on this thread.
+#[cfg_attr(any(target_arch = "wasm32", not(feature = "ssr")), allow(dead_code))]
+pub(crate) fn calls_in_flight() -> usize {
+	CALLS_IN_FLIGHT.with(Cell::get)
+}
+
+/// A query that runs a hook again by setting `needs_run` and rendering the component.
+fn hook_query(cx: &ScopeState) -> (Rc<Cell<bool>>, Rc<Query>) {
+	let (needs_run, update) = (Rc::new(Cell::new(true)), cx.schedule_update());
+	let query = Query::new({
+		let needs_run = needs_run.clone();
+		move || {
+			needs_run.set(true);
+			update();
+		}
+	});
+	(needs_run, query)
+}
+
 #[allow(dead_code)]
-fn test_compile_use_stream(cx: Scope) {
+fn test_compile_hooks(cx: Scope) {
 	fn make_stream() -> impl futures_util::Stream<Item = Vec<u8>> {
 		futures_channel::mpsc::unbounded().1
 	}
-	let _latest = use_stream(cx, make_stream, |s, v| *s = Some(v));
-	let _vec = use_stream(cx, make_stream, |s: &mut Vec<_>, v| s.push(v));
+	let latest = use_stream(cx, (), |()| make_stream(), |s, v| *s = Some(v));
+	let _: (Option<Vec<u8>>, StreamStatus) = (latest.read().clone(), latest.status());
+	let vec =
+		use_stream(cx, (), |()| make_stream(), |s: &mut Vec<_>, v| s.push(v)).coalesce_per_frame();
+	vec.write().clear();
+
+	fn make_try_stream(id: i64) -> impl futures_util::Stream<Item = Result<i64, String>> {
+		futures_util::stream::iter([Ok(id), Err(id.to_string())])
+	}
+	let id = 1;
+	let count = use_try_stream(cx, (&id,), |(id,)| make_try_stream(id), |s, _| *s += 1);
+	let _: (usize, Option<String>) = (*count.read(), count.error().map(|e| e.clone()));
+
+	async fn get_name(id: i64) -> Result<String, String> {
+		Ok(id.to_string())
+	}
+	let name = use_backend(cx, (&id,), |(id,)| get_name(id));
+	let _: (bool, Option<String>, Option<String>) =
+		(name.loading(), name.value().map(|v| v.clone()), name.error().map(|e| e.clone()));
+	name.refetch();
+
+	async fn rename(id: i64, name: String) -> Result<(), String> {
+		let _ = (id, name);
+		Ok(())
+	}
+	let renaming = use_mutation(cx, |(id, name)| rename(id, name));
+	renaming.mutate((id, "Bob".to_string()));
+	let _: (bool, Option<()>, Option<String>) =
+		(renaming.loading(), renaming.value().map(|v| *v), renaming.error().map(|e| e.clone()));
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::task::Poll;
+
+	/// Completes on its second poll, like a call waiting for its response.
+	async fn response() -> Result<i32, Infallible> {
+		let mut polled = false;
+		futures_util::future::poll_fn(|cx| match std::mem::replace(&mut polled, true) {
+			true => Poll::Ready(Ok(1)),
+			false => {
+				cx.waker().wake_by_ref();
+				Poll::Pending
+			}
+		})
+		.await
+	}
+
+	fn app(cx: Scope) -> Element {
+		let call = use_backend(cx, (), |()| response());
+		cx.render(rsx!("{call.value().as_deref():?}"))
+	}
+
+	#[tokio::test]
+	async fn test_calls_in_flight() {
+		let mut vdom = VirtualDom::new(app);
+		let _ = vdom.rebuild();
+		assert_eq!(calls_in_flight(), 1);
+		vdom.wait_for_work().await;
+		let _ = vdom.render_immediate();
+		assert_eq!(calls_in_flight(), 0);
+
+		// unmounting drops the call
+		let mut vdom = VirtualDom::new(app);
+		let _ = vdom.rebuild();
+		assert_eq!(calls_in_flight(), 1);
+		drop(vdom);
+		assert_eq!(calls_in_flight(), 0);
+	}
 }
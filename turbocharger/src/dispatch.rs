@@ -0,0 +1,176 @@
+//! Registry of `#[backend]` functions, used to deserialize requests by their dispatch name.
+
+use crate::RPC;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+
+/// A `#[backend]` function, or one instantiation of a generic one, registered with `inventory`.
+#[doc(hidden)]
+pub struct _Dispatch {
+	/// `CARGO_PKG_NAME` of the crate that defines the function.
+	pub package: &'static str,
+	/// `module_path!()` where the function is defined.
+	pub module_path: &'static str,
+	/// Name within its module, e.g. `get_person`, `UserService::list` or `first_page<i64>`. This was the whole
+	/// dispatch name before it included the module path, and is still accepted when it's unambiguous.
+	pub name: &'static str,
+	/// Set by `#[backend(name = "...")]`, and used instead of the module path and name. It's in a static exported
+	/// under a symbol named after it, so that linking fails if two crates register the same name.
+	pub explicit_name: Option<&'static &'static str>,
+	/// Names from `#[backend(invalidates = "...")]`, each of which must match a function's dispatch name or its end.
+	pub invalidates: &'static [&'static str],
+	/// Deserializes the request that follows the dispatch name.
+	pub deserialize: fn(&[u8]) -> bincode::Result<Box<dyn RPC>>,
+}
+
+inventory::collect!(_Dispatch);
+
+impl _Dispatch {
+	fn dispatch_name(&self) -> &'static str {
+		self
+			.explicit_name
+			.copied()
+			.unwrap_or_else(|| crate::_dispatch_name(self.package, self.module_path, self.name))
+	}
+}
+
+struct Registry {
+	by_dispatch_name: HashMap<&'static str, &'static _Dispatch>,
+	/// By `_Dispatch::name`, and by that name prefixed by the end of the module path, e.g. `get_person` and
+	/// `api::get_person`, which is how `backend_api.rs` names it; `None` if several functions share it.
+	by_name: HashMap<&'static str, Option<&'static _Dispatch>>,
+}
+
+static REGISTRY: Lazy<Registry> =
+	Lazy::new(|| Registry::new(inventory::iter::<_Dispatch>).unwrap_or_else(|e| panic!("{}", e)));
+
+impl Registry {
+	/// Fails if two functions have the same dispatch name, or a function invalidates a name that no function has.
+	/// Explicit names can only collide with each other when linking, but one can be the same as another function's
+	/// module path and name, which is only known at runtime, like the names of all of a client's functions.
+	fn new(dispatches: impl IntoIterator<Item = &'static _Dispatch>) -> Result<Self, String> {
+		let dispatches: Vec<_> = dispatches.into_iter().collect();
+		let mut registry = Registry { by_dispatch_name: HashMap::new(), by_name: HashMap::new() };
+		for &dispatch in &dispatches {
+			if let Some(existing) = registry.by_dispatch_name.insert(dispatch.dispatch_name(), dispatch) {
+				return Err(format!(
+					"#[backend] functions `{}::{}` and `{}::{}` have the same dispatch name `{}`; give one of them a different #[backend(name = \"...\")]",
+					existing.module_path,
+					existing.name,
+					dispatch.module_path,
+					dispatch.name,
+					dispatch.dispatch_name()
+				));
+			}
+			for name in partial_names(dispatch) {
+				registry.by_name.entry(name).and_modify(|d| *d = None).or_insert(Some(dispatch));
+			}
+		}
+		for dispatch in dispatches {
+			for name in dispatch.invalidates {
+				if !registry
+					.by_dispatch_name
+					.keys()
+					.any(|dispatch_name| crate::name_matches(dispatch_name, name))
+				{
+					return Err(format!(
+						"#[backend] function `{}::{}` invalidates `{}`, which isn't the dispatch name of any function, or the end of one",
+						dispatch.module_path, dispatch.name, name
+					));
+				}
+			}
+		}
+		Ok(registry)
+	}
+}
+
+/// `name`, and, without an explicit name, `name` prefixed by each trailing part of the module path below the crate root.
+fn partial_names(dispatch: &'static _Dispatch) -> Vec<&'static str> {
+	let mut names = vec![dispatch.name];
+	if dispatch.explicit_name.is_none() {
+		let mut name = dispatch.name.to_string();
+		for segment in dispatch.module_path.split("::").skip(1).collect::<Vec<_>>().into_iter().rev() {
+			name = format!("{}::{}", segment, name);
+			names.push(Box::leak(name.clone().into_boxed_str()));
+		}
+	}
+	names
+}
+
+/// Checks the registered dispatch names for duplicates, which panics, so servers can fail at startup rather than on the first request.
+pub(crate) fn check() {
+	Lazy::force(&REGISTRY);
+}
+
+/// Deserializes a request, which starts with the `1` that marks requests, and the dispatch name.
+pub(crate) fn deserialize(data: &[u8]) -> bincode::Result<Box<dyn RPC>> {
+	let (_, name): (i64, &str) = bincode::deserialize(data)?;
+	let dispatch = match REGISTRY.by_dispatch_name.get(name) {
+		Some(dispatch) => dispatch,
+		None => match REGISTRY.by_name.get(name) {
+			Some(Some(dispatch)) => dispatch,
+			Some(None) => {
+				return Err(
+					bincode::ErrorKind::Custom(format!(
+						"dispatch name `{}` is ambiguous; use the full dispatch name, or give the function a #[backend(name = \"...\")]",
+						name
+					))
+					.into(),
+				)
+			}
+			None => {
+				return Err(bincode::ErrorKind::Custom(format!("unknown dispatch name `{}`", name)).into())
+			}
+		},
+	};
+	// The `i64` and the string's `u64` length prefix, then the string.
+	(dispatch.deserialize)(&data[16 + name.len()..])
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn dispatch(
+		package: &'static str,
+		module_path: &'static str,
+		name: &'static str,
+		explicit_name: Option<&'static &'static str>,
+		invalidates: &'static [&'static str],
+	) -> &'static _Dispatch {
+		Box::leak(Box::new(_Dispatch {
+			package,
+			module_path,
+			name,
+			explicit_name,
+			invalidates,
+			deserialize: |_| unimplemented!(),
+		}))
+	}
+
+	#[test]
+	fn test_duplicate_names() {
+		let get = dispatch("my-app", "server::api", "get", None, &[]);
+		let explicit_get = dispatch("auth", "auth", "login", Some(&"my_app::api::get"), &[]);
+		let other_get = dispatch("my-app", "server::people", "get", None, &[]);
+		assert!(Registry::new([get, other_get]).is_ok());
+		assert_eq!(
+			Registry::new([get, other_get, explicit_get]).err().unwrap(),
+			"#[backend] functions `server::api::get` and `auth::login` have the same dispatch name `my_app::api::get`; give one of them a different #[backend(name = \"...\")]"
+		);
+	}
+
+	#[test]
+	fn test_invalidated_names() {
+		let get = dispatch("my-app", "server::api", "get", None, &[]);
+		let list = dispatch("my-app", "server::api", "list", None, &[]);
+		let count = dispatch("my-app", "server::api", "count", Some(&"users.count"), &[]);
+		let add = dispatch("my-app", "server::api", "add", None, &["get", "api::list", "users.count"]);
+		assert!(Registry::new([get, list, count, add]).is_ok());
+		let typo = dispatch("my-app", "server::api", "remove", None, &["get", "gte"]);
+		assert_eq!(
+			Registry::new([get, typo]).err().unwrap(),
+			"#[backend] function `server::api::remove` invalidates `gte`, which isn't the dispatch name of any function, or the end of one"
+		);
+	}
+}
@@ -0,0 +1,167 @@
+//! The parts of the Dioxus, Leptos and Yew integrations that don't depend on the framework.
+
+use crate::name_matches;
+use futures_util::{stream::FusedStream, FutureExt, StreamExt};
+use std::{
+	cell::RefCell,
+	collections::HashSet,
+	future::Future,
+	rc::{Rc, Weak},
+};
+
+/// Whether a stream run by a `use_stream` hook has items yet, or has ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamStatus {
+	/// Waiting for the first item.
+	Connecting,
+	/// Items are arriving.
+	Open,
+	/// The stream has ended.
+	Ended,
+}
+
+/// A hook's backend calls: the dispatch names of the functions they called, found while they run, so the hook runs
+/// again when one of them is invalidated.
+pub(crate) struct Query {
+	rerun: Box<dyn Fn()>,
+	names: RefCell<HashSet<&'static str>>,
+}
+
+thread_local! {
+	/// The queries of mounted hooks; unmounting drops them.
+	static QUERIES: RefCell<Vec<Weak<Query>>> = Default::default();
+	/// The query of the hook whose call is running.
+	static CURRENT_QUERY: RefCell<Option<Rc<Query>>> = Default::default();
+}
+
+impl Query {
+	/// A query that calls `rerun` to run the hook again.
+	pub(crate) fn new(rerun: impl Fn() + 'static) -> Rc<Self> {
+		let query = Rc::new(Query { rerun: Box::new(rerun), names: Default::default() });
+		QUERIES.with(|queries| {
+			let mut queries = queries.borrow_mut();
+			queries.retain(|query| query.strong_count() > 0);
+			queries.push(Rc::downgrade(&query));
+		});
+		query
+	}
+
+	pub(crate) fn rerun(&self) {
+		(self.rerun)()
+	}
+
+	/// Runs `f` as this query's call, forgetting the functions that earlier calls called.
+	pub(crate) fn start<R>(self: &Rc<Self>, f: impl FnOnce() -> R) -> R {
+		self.names.borrow_mut().clear();
+		self.track(f)
+	}
+
+	/// Runs `f` as this query's call.
+	fn track<R>(self: &Rc<Self>, f: impl FnOnce() -> R) -> R {
+		let previous = CURRENT_QUERY.with(|current| current.replace(Some(self.clone())));
+		let result = f();
+		CURRENT_QUERY.with(|current| *current.borrow_mut() = previous);
+		result
+	}
+
+	/// Polls `future` as this query's call.
+	pub(crate) fn tracked<F: Future>(self: Rc<Self>, future: F) -> impl Future<Output = F::Output> {
+		let mut future = Box::pin(future);
+		futures_util::future::poll_fn(move |cx| self.track(|| future.as_mut().poll(cx)))
+	}
+}
+
+pub(crate) fn track_query(dispatch_name: &'static str) {
+	CURRENT_QUERY.with(|current| {
+		if let Some(query) = &*current.borrow() {
+			query.names.borrow_mut().insert(dispatch_name);
+		}
+	});
+}
+
+pub(crate) fn invalidate(names: &[&str]) {
+	if names.is_empty() {
+		return;
+	}
+	let queries: Vec<_> =
+		QUERIES.with(|queries| queries.borrow().iter().filter_map(Weak::upgrade).collect());
+	for query in queries {
+		if query
+			.names
+			.borrow()
+			.iter()
+			.any(|dispatch_name| names.iter().any(|name| name_matches(dispatch_name, name)))
+		{
+			query.rerun();
+		}
+	}
+}
+
+/// Waits for the next item of `stream`, then takes the items that have already arrived after it, e.g. in a burst, so
+/// they share one update; `None` once the stream has ended. A stream's end often arrives with its last items, so the
+/// stream is fused, to be polled again after it has ended.
+pub(crate) async fn next_items<S: FusedStream + Unpin>(stream: &mut S) -> Option<Vec<S::Item>> {
+	let mut items = vec![stream.next().await?];
+	while let Some(Some(item)) = stream.next().now_or_never() {
+		items.push(item);
+	}
+	Some(items)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_name_matches() {
+		assert!(name_matches("my_app::api::get_person", "get_person"));
+		assert!(name_matches("my_app::api::get_person", "api::get_person"));
+		assert!(name_matches("users.list", "users.list"));
+		assert!(!name_matches("my_app::api::get_person", "person"));
+		assert!(!name_matches("my_app::api::get_person", "get_people"));
+	}
+
+	#[test]
+	fn test_invalidate() {
+		let reruns = Rc::new(std::cell::Cell::new(0));
+		let query = Query::new({
+			let reruns = reruns.clone();
+			move || reruns.set(reruns.get() + 1)
+		});
+		// as the client of a `#[backend]` function records its call
+		let call = |dispatch_name| move || crate::_track_query(dispatch_name);
+
+		query.start(call("my_app::api::get_person"));
+		crate::_invalidate(&["api::get_people", "person"]);
+		assert_eq!(reruns.get(), 0);
+		crate::_invalidate(&["api::get_person"]);
+		assert_eq!(reruns.get(), 1);
+
+		// calls made while the query's future is polled are tracked too
+		let future = query.clone().tracked(async { call("my_app::api::list_people")() });
+		assert_eq!(future.now_or_never(), Some(()));
+		crate::_invalidate(&["my_app::api::list_people"]);
+		assert_eq!(reruns.get(), 2);
+
+		// running the query again forgets the functions earlier calls called
+		query.start(|| ());
+		crate::_invalidate(&["get_person", "list_people"]);
+		assert_eq!(reruns.get(), 2);
+
+		// unmounting a hook drops its query
+		query.start(call("my_app::api::get_person"));
+		drop(query);
+		crate::_invalidate(&["get_person"]);
+		assert_eq!(reruns.get(), 2);
+	}
+
+	#[test]
+	fn test_next_items() {
+		// the last items and the end arrive together; an unfused `Unfold` panics when polled after it has ended
+		let stream = futures_util::stream::unfold(0, |n| async move { (n < 3).then_some((n, n + 1)) });
+		let mut stream = Box::pin(stream).fuse();
+		assert_eq!(next_items(&mut stream).now_or_never(), Some(Some(vec![0, 1, 2])));
+		assert_eq!(next_items(&mut stream).now_or_never(), Some(None));
+		assert_eq!(next_items(&mut stream).now_or_never(), Some(None));
+	}
+}
@@ -0,0 +1,76 @@
+//! JS async iterators over streams, which make stream stores usable in `for await` loops.
+
+use crate::{_Transaction, send_ws, G};
+use futures_util::{lock::Mutex, StreamExt};
+use js_sys::{Object, Promise, Reflect};
+use std::{cell::Cell, rc::Rc};
+use wasm_bindgen::prelude::*;
+
+/// Sends the streaming request that `req` serializes for a new txid, and returns a JS async iterator over its
+/// responses, which `item` converts to the values to yield or the errors to throw. The iteration ends when the stream
+/// does; throwing an error, or ending the iteration early with `break`, cancels the stream.
+pub fn _stream_iterator(
+	req: impl FnOnce(i64) -> Vec<u8>,
+	item: impl Fn(&[u8]) -> Result<JsValue, JsValue> + 'static,
+) -> JsValue {
+	let _Transaction { txid, resp_rx } = _Transaction::new();
+	let req = req(txid);
+	send_ws(req.clone());
+
+	let resp_rx = Rc::new(Mutex::new(resp_rx));
+	let item = Rc::new(item);
+	let done = Rc::new(Cell::new(false));
+	// re-sending the request cancels it
+	let cancel = {
+		let done = done.clone();
+		Rc::new(move || {
+			if !done.replace(true) {
+				G.lock().unwrap().senders.remove(&txid);
+				send_ws(req.clone());
+			}
+		})
+	};
+
+	let next = {
+		let cancel = cancel.clone();
+		Closure::wrap(Box::new(move || {
+			let (resp_rx, item, done, cancel) =
+				(resp_rx.clone(), item.clone(), done.clone(), cancel.clone());
+			wasm_bindgen_futures::future_to_promise(async move {
+				let mut resp_rx = resp_rx.lock().await;
+				if done.get() {
+					return Ok(iterator_result(JsValue::UNDEFINED, true));
+				}
+				match resp_rx.next().await {
+					Some(response) => item(&response).map(|value| iterator_result(value, false)).map_err(|e| {
+						cancel();
+						e
+					}),
+					None => {
+						done.set(true);
+						Ok(iterator_result(JsValue::UNDEFINED, true))
+					}
+				}
+			})
+		}) as Box<dyn Fn() -> Promise>)
+		.into_js_value()
+	};
+
+	let return_ = Closure::wrap(Box::new(move || {
+		cancel();
+		Promise::resolve(&iterator_result(JsValue::UNDEFINED, true))
+	}) as Box<dyn Fn() -> Promise>)
+	.into_js_value();
+
+	let iterator = Object::new();
+	Reflect::set(&iterator, &"next".into(), &next).unwrap_throw();
+	Reflect::set(&iterator, &"return".into(), &return_).unwrap_throw();
+	iterator.into()
+}
+
+fn iterator_result(value: JsValue, done: bool) -> JsValue {
+	let result = Object::new();
+	Reflect::set(&result, &"value".into(), &value).unwrap_throw();
+	Reflect::set(&result, &"done".into(), &done.into()).unwrap_throw();
+	result.into()
+}
@@ -0,0 +1,176 @@
+//! Leptos resources and signals over `#[backend]` calls and streams.
+
+use crate::hooks::{next_items, Query, StreamStatus};
+use ::leptos::{prelude::*, task::spawn_local};
+use futures_util::{
+	future::{abortable, AbortHandle},
+	FutureExt, Stream, StreamExt,
+};
+use std::{
+	convert::Infallible,
+	future::Future,
+	rc::Rc,
+	sync::{Arc, Mutex},
+};
+
+/// A resource that makes a backend call with `fetcher`, and again when the signals it reads change, on `refetch()`,
+/// or when a backend function whose `#[backend(invalidates = "...")]` names a function it called completes. Starting
+/// the call again, or disposing the resource's owner, e.g. by unmounting the component, drops the call in flight,
+/// which cancels it on the server.
+pub fn backend_resource<T, F>(fetcher: impl Fn() -> F + 'static) -> LocalResource<T>
+where
+	T: 'static,
+	F: Future<Output = T> + 'static,
+{
+	let (trigger, query) = query();
+	let in_flight = Arc::new(Mutex::new(None::<AbortHandle>));
+	on_cleanup({
+		let in_flight = in_flight.clone();
+		move || abort(&in_flight)
+	});
+
+	LocalResource::new(move || {
+		trigger.track();
+		abort(&in_flight);
+		let (future, handle) = abortable(query.clone().tracked(query.start(&fetcher)));
+		*in_flight.lock().unwrap() = Some(handle);
+		async move {
+			match future.await {
+				Ok(value) => value,
+				// a newer call has replaced this one
+				Err(_) => std::future::pending().await,
+			}
+		}
+	})
+}
+
+/// Runs the stream that `fetcher` returns, and again from fresh state when the signals it reads change, folding its
+/// items into the state with `callback`. Starting the stream again, or disposing the owner, drops the stream, which
+/// cancels it on the server.
+pub fn backend_stream<C, T, S>(
+	fetcher: impl Fn() -> S + 'static,
+	callback: impl Fn(&mut C, T) + 'static,
+) -> BackendStream<C>
+where
+	C: Default + 'static,
+	T: 'static,
+	S: Stream<Item = T> + 'static,
+{
+	backend_try_stream(move || fetcher().map(Ok), callback)
+}
+
+/// Like [`backend_stream`], for streams of `Result`s: `callback` gets the `Ok` items, and [`BackendStream::error`] is
+/// the latest error until the next `Ok` item.
+pub fn backend_try_stream<C, T, E, S>(
+	fetcher: impl Fn() -> S + 'static,
+	callback: impl Fn(&mut C, T) + 'static,
+) -> BackendStream<C, E>
+where
+	C: Default + 'static,
+	T: 'static,
+	E: 'static,
+	S: Stream<Item = Result<T, E>> + 'static,
+{
+	let value = RwSignal::new_local(C::default());
+	let status = RwSignal::new(StreamStatus::Connecting);
+	let error = RwSignal::new_local(None);
+	let (trigger, query) = query();
+	let callback = Rc::new(callback);
+
+	Effect::new(move |_| {
+		trigger.track();
+		let stream = query.start(&fetcher);
+		if status.get_untracked() != StreamStatus::Connecting {
+			value.set(C::default());
+			error.set(None);
+			status.set(StreamStatus::Connecting);
+		}
+
+		let callback = callback.clone();
+		let (task, handle) = abortable(query.clone().tracked(async move {
+			let stream = stream.fuse();
+			futures_util::pin_mut!(stream);
+			while let Some(items) = next_items(&mut stream).await {
+				let mut latest_error = None;
+				value.update(|value| {
+					for item in items {
+						match item {
+							Ok(item) => {
+								callback(value, item);
+								latest_error = Some(None);
+							}
+							Err(e) => latest_error = Some(Some(e)),
+						}
+					}
+				});
+				if let Some(latest_error) = latest_error {
+					error.set(latest_error);
+				}
+				status.set(StreamStatus::Open);
+			}
+			status.set(StreamStatus::Ended);
+		}));
+		spawn_local(task.map(drop));
+		on_cleanup(move || handle.abort());
+	});
+
+	BackendStream { value: value.read_only(), status: status.read_only(), error: error.read_only() }
+}
+
+/// Signals of a stream run by [`backend_stream`] or [`backend_try_stream`].
+pub struct BackendStream<C: 'static, E: 'static = Infallible> {
+	/// The state that `callback` has folded the items into.
+	pub value: ReadSignal<C, LocalStorage>,
+	/// Whether the stream has items yet, or has ended.
+	pub status: ReadSignal<StreamStatus>,
+	/// The latest error, until the next `Ok` item.
+	pub error: ReadSignal<Option<E>, LocalStorage>,
+}
+
+impl<C, E> Clone for BackendStream<C, E> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+
+impl<C, E> Copy for BackendStream<C, E> {}
+
+/// A query that runs a resource or effect again by notifying the trigger it tracks.
+fn query() -> (ArcTrigger, Rc<Query>) {
+	let trigger = ArcTrigger::new();
+	let query = Query::new({
+		let trigger = trigger.clone();
+		move || trigger.notify()
+	});
+	(trigger, query)
+}
+
+fn abort(in_flight: &Mutex<Option<AbortHandle>>) {
+	if let Some(handle) = in_flight.lock().unwrap().take() {
+		handle.abort();
+	}
+}
+
+#[allow(dead_code)]
+fn test_compile_hooks() {
+	fn make_stream(id: i64) -> impl Stream<Item = Vec<u8>> {
+		let _ = id;
+		futures_channel::mpsc::unbounded().1
+	}
+	fn make_try_stream(id: i64) -> impl Stream<Item = Result<i64, String>> {
+		futures_util::stream::iter([Ok(id), Err(id.to_string())])
+	}
+	async fn get_name(id: i64) -> Result<String, String> {
+		Ok(id.to_string())
+	}
+
+	let id = RwSignal::new(1);
+	let name = backend_resource(move || get_name(id.get()));
+	let _: Option<Result<String, String>> = name.get().map(|name| name.take());
+	name.refetch();
+
+	let latest = backend_stream(move || make_stream(id.get()), |s, v| *s = Some(v));
+	let _: (Option<Vec<u8>>, StreamStatus) = (latest.value.get(), latest.status.get());
+	let count = backend_try_stream(move || make_try_stream(id.get()), |s: &mut usize, _| *s += 1);
+	let _: (usize, Option<String>) = (count.value.get(), count.error.get());
+}
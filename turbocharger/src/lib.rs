@@ -12,6 +12,53 @@ pub use turbocharger_impl::{backend, server_only, wasm_only};
 #[cfg(feature = "dioxus")]
 mod dioxus;
 
+#[cfg(any(feature = "dioxus", feature = "leptos", feature = "yew"))]
+mod hooks;
+
+#[cfg(feature = "leptos")]
+pub mod leptos;
+
+#[cfg(feature = "ssr")]
+pub mod ssr;
+
+#[cfg(feature = "yew")]
+pub mod yew;
+
+#[cfg(any(feature = "dioxus", feature = "leptos", feature = "yew"))]
+pub use hooks::StreamStatus;
+
+#[cfg(target_arch = "wasm32")]
+mod iterator;
+
+#[cfg(target_arch = "wasm32")]
+#[doc(hidden)]
+pub use iterator::_stream_iterator;
+
+#[cfg(all(target_arch = "wasm32", feature = "react"))]
+mod react;
+
+#[cfg(all(target_arch = "wasm32", feature = "react"))]
+#[doc(hidden)]
+pub use react::{_use_query, _use_stream};
+
+#[cfg(not(target_arch = "wasm32"))]
+#[doc(hidden)]
+pub mod telemetry;
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub mod session;
+
+#[cfg(not(target_arch = "wasm32"))]
+mod dispatch;
+
+#[cfg(not(target_arch = "wasm32"))]
+#[doc(hidden)]
+pub use dispatch::_Dispatch;
+
 pub mod prelude {
 	#[cfg(all(feature = "axum", not(target_arch = "wasm32")))]
 	pub use axum;
@@ -21,7 +68,14 @@ pub mod prelude {
 	};
 	#[cfg(all(feature = "dioxus", any(feature = "wasm", target_arch = "wasm32")))]
 	pub use {
-		crate::dioxus::use_stream, ::dioxus, ::dioxus::events::*, ::dioxus::prelude::*, ::dioxus_web,
+		crate::dioxus::{
+			use_backend, use_mutation, use_stream, use_try_stream, UseBackend, UseMutation, UseStream,
+		},
+		crate::StreamStatus,
+		::dioxus,
+		::dioxus::events::*,
+		::dioxus::prelude::*,
+		::dioxus_web,
 	};
 	pub use {
 		::tracked::{self, tracked},
@@ -40,8 +94,7 @@ pub mod prelude {
 	#[cfg(not(target_arch = "wasm32"))]
 	pub use {
 		async_stream::{stream, try_stream},
-		turbocharger_impl::{connection_local, remote_addr, user_agent},
-		typetag,
+		turbocharger_impl::{connection_local, remote_addr, session_local, state, user_agent},
 	};
 }
 
@@ -52,31 +105,209 @@ const Subscriber: &'static str = r#"
 import { Subscriber } from "svelte/store";
 "#;
 
+#[wasm_only]
+#[cfg(not(feature = "svelte"))]
+#[wasm_bindgen(typescript_custom_section)]
+const Subscriber: &'static str = r#"
+export type Subscriber<T> = (value: T) => void;
+"#;
+
+#[wasm_only]
+#[wasm_bindgen(typescript_custom_section)]
+const CALL_OPTIONS: &'static str = r#"
+export interface CallOptions {
+  /** Aborting cancels the call on the server and rejects it with the signal's reason, an `AbortError` by default. */
+  signal?: AbortSignal;
+}
+"#;
+
+#[wasm_only]
+#[wasm_bindgen]
+extern "C" {
+	/// The options JS can pass after the arguments of a `#[backend(js)]` function.
+	#[doc(hidden)]
+	#[wasm_bindgen(typescript_type = "CallOptions")]
+	pub type _CallOptions;
+
+	#[wasm_bindgen(method, getter)]
+	fn signal(this: &_CallOptions) -> Option<web_sys::AbortSignal>;
+}
+
 #[doc(hidden)]
 pub use {bincode, futures_channel, futures_util, serde};
 
 #[server_only]
 #[doc(hidden)]
-pub use {async_stream, async_trait::async_trait, stream_cancel, typetag};
+pub use {async_stream, async_trait::async_trait, inventory, stream_cancel};
 
 #[cfg(target_arch = "wasm32")]
 #[doc(hidden)]
-pub use js_sys;
+pub use {js_sys, serde_wasm_bindgen};
 
 #[server_only]
 #[derive(Clone)]
 pub struct ConnectionInfo {
 	pub remote_addr: Option<std::net::SocketAddr>,
 	pub user_agent: Option<String>,
-	#[allow(clippy::type_complexity)]
-	pub connection_local: std::sync::Arc<
-		tokio::sync::Mutex<HashMap<(&'static str, std::any::TypeId), Box<dyn std::any::Any + Send>>>,
-	>,
+	pub state: StateMap,
+	pub connection_local: ConnectionLocals,
+	pub session: Option<session::Session>,
+}
+
+#[server_only]
+type ConnectionLocalSlot =
+	std::sync::Arc<tokio::sync::Mutex<Option<Box<dyn std::any::Any + Send>>>>;
+
+/// Per-connection values used by `connection_local!()`. Each key is locked separately, so concurrent calls on one connection only wait for each other when they use the same key.
+#[server_only]
+#[derive(Clone, Default)]
+pub struct ConnectionLocals(
+	std::sync::Arc<Mutex<HashMap<(&'static str, std::any::TypeId), ConnectionLocalSlot>>>,
+);
+
+#[server_only]
+impl ConnectionLocals {
+	/// Locks the value for `key`, initializing it with `init` if this connection doesn't have one yet.
+	pub async fn get_or_insert_with<T: Send + 'static>(
+		&self,
+		key: &'static str,
+		init: impl FnOnce() -> T,
+	) -> ConnectionLocal<T> {
+		let mut local = self._lock::<T>(key).await;
+		if local._is_empty() {
+			local._set(init());
+		}
+		local
+	}
+
+	#[doc(hidden)]
+	pub async fn _lock<T: Send + 'static>(&self, key: &'static str) -> ConnectionLocal<T> {
+		let slot = self.0.lock().unwrap().entry((key, std::any::TypeId::of::<T>())).or_default().clone();
+		ConnectionLocal(slot.lock_owned().await, std::marker::PhantomData)
+	}
+}
+
+/// A locked handle to a connection-local value of type `T`; the lock is released when this is dropped.
+#[server_only]
+pub struct ConnectionLocal<T>(
+	tokio::sync::OwnedMutexGuard<Option<Box<dyn std::any::Any + Send>>>,
+	std::marker::PhantomData<T>,
+);
+
+#[server_only]
+impl<T: Send + 'static> ConnectionLocal<T> {
+	#[doc(hidden)]
+	pub fn _is_empty(&self) -> bool {
+		self.0.is_none()
+	}
+
+	#[doc(hidden)]
+	pub fn _set(&mut self, value: T) {
+		*self.0 = Some(Box::new(value));
+	}
+}
+
+#[server_only]
+impl<T: 'static> std::ops::Deref for ConnectionLocal<T> {
+	type Target = T;
+	fn deref(&self) -> &T {
+		self.0.as_ref().and_then(|v| v.downcast_ref()).unwrap()
+	}
+}
+
+#[server_only]
+impl<T: 'static> std::ops::DerefMut for ConnectionLocal<T> {
+	fn deref_mut(&mut self) -> &mut T {
+		self.0.as_mut().and_then(|v| v.downcast_mut()).unwrap()
+	}
+}
+
+/// Typed shared state (DB pools, config, caches) made available to `#[backend]` functions via `state!(T)`.
+///
+/// Provide it to `ws_handler` as an `axum::Extension`, e.g. `router.layer(Extension(StateMap::new().with(app_state)))`,
+/// or process-wide with [`register_state`], which is also used for direct server-side calls and by `serve`.
+#[server_only]
+#[derive(Clone, Default)]
+pub struct StateMap(
+	std::sync::Arc<HashMap<std::any::TypeId, std::sync::Arc<dyn std::any::Any + Send + Sync>>>,
+);
+
+#[server_only]
+impl StateMap {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds `value`, replacing any previous value of the same type.
+	pub fn with<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+		self.insert(value);
+		self
+	}
+
+	/// Adds `value`, replacing any previous value of the same type.
+	pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+		std::sync::Arc::make_mut(&mut self.0)
+			.insert(std::any::TypeId::of::<T>(), std::sync::Arc::new(value));
+	}
+
+	pub fn get<T: Send + Sync + 'static>(&self) -> Option<State<T>> {
+		let value = self.0.get(&std::any::TypeId::of::<T>())?.clone();
+		Some(State(value.downcast().ok()?))
+	}
+}
+
+#[server_only]
+static STATE: Lazy<Mutex<StateMap>> = Lazy::new(Mutex::default);
+
+/// Registers `value` as process-wide state for `state!(T)`, replacing any previous value of the same type.
+#[server_only]
+pub fn register_state<T: Send + Sync + 'static>(value: T) {
+	STATE.lock().unwrap().insert(value);
+}
+
+#[server_only]
+pub(crate) fn global_state() -> StateMap {
+	STATE.lock().unwrap().clone()
+}
+
+/// A handle to shared state of type `T`, as returned by `state!(T)`.
+#[server_only]
+pub struct State<T>(std::sync::Arc<T>);
+
+#[server_only]
+impl<T> Clone for State<T> {
+	fn clone(&self) -> Self {
+		Self(self.0.clone())
+	}
+}
+
+#[server_only]
+impl<T> std::ops::Deref for State<T> {
+	type Target = T;
+	fn deref(&self) -> &T {
+		&self.0
+	}
+}
+
+#[server_only]
+impl<T: Send + Sync + 'static> State<T> {
+	#[doc(hidden)]
+	pub fn _resolve(connection_info: Option<&ConnectionInfo>) -> Self {
+		match connection_info {
+			Some(info) => info.state.get(),
+			None => global_state().get(),
+		}
+		.unwrap_or_else(|| {
+			panic!(
+				"no state of type `{}` registered; use turbocharger::register_state or a StateMap extension",
+				std::any::type_name::<T>()
+			)
+		})
+	}
 }
 
 #[server_only]
 #[doc(hidden)]
-#[typetag::serde]
 #[async_trait]
 pub trait RPC: Send + Sync {
 	async fn execute(
@@ -86,6 +317,8 @@ pub trait RPC: Send + Sync {
 		_turbocharger_connection_info: Option<ConnectionInfo>,
 	);
 	fn txid(&self) -> i64;
+	fn name(&self) -> &'static str;
+	fn is_stream(&self) -> bool;
 }
 
 struct Globals {
@@ -93,13 +326,21 @@ struct Globals {
 	channel_tx: Option<futures_channel::mpsc::UnboundedSender<Vec<u8>>>,
 	#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
 	socket_url: Option<String>,
+	#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+	session_token: Option<String>,
 	next_txid: i64,
 	senders: HashMap<i64, futures_channel::mpsc::UnboundedSender<Vec<u8>>>,
 }
 
 impl Default for Globals {
 	fn default() -> Self {
-		Self { socket_url: None, channel_tx: None, next_txid: 256, senders: Default::default() }
+		Self {
+			socket_url: None,
+			channel_tx: None,
+			session_token: None,
+			next_txid: 256,
+			senders: Default::default(),
+		}
 	}
 }
 
@@ -159,10 +400,106 @@ mod axum_server;
 #[cfg(feature = "axum")]
 pub use axum_server::{serve, ws_handler};
 
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(feature = "ssr")]
+pub use axum_server::serve_ssr;
+
+#[cfg(not(target_arch = "wasm32"))]
+#[cfg(all(feature = "tls", feature = "ssr"))]
+pub use axum_server::serve_tls_ssr;
+
 #[cfg(not(target_arch = "wasm32"))]
 #[cfg(all(feature = "tls", feature = "axum"))]
 pub use axum_server::serve_tls;
 
+/// Whether `name`, e.g. from `#[backend(invalidates = "...")]`, is `dispatch_name` or its end, e.g. `api::get_person`
+/// of `my_app::api::get_person`.
+#[cfg(any(not(target_arch = "wasm32"), feature = "dioxus", feature = "leptos", feature = "yew"))]
+fn name_matches(dispatch_name: &str, name: &str) -> bool {
+	dispatch_name.strip_suffix(name).is_some_and(|prefix| prefix.is_empty() || prefix.ends_with("::"))
+}
+
+/// Dispatch name of a `#[backend]` function without `#[backend(name = "...")]`: its package, module path and name,
+/// e.g. `my_app::api::get_person`. The module path leaves out the crate root, because a server binary and a WASM
+/// binary built from the same modules are different crates.
+#[doc(hidden)]
+pub fn _dispatch_name(
+	package: &'static str,
+	module_path: &'static str,
+	name: &'static str,
+) -> &'static str {
+	#[allow(clippy::type_complexity)]
+	static NAMES: Lazy<Mutex<HashMap<(&str, &str, &str), &str>>> = Lazy::new(Mutex::default);
+	NAMES.lock().unwrap().entry((package, module_path, name)).or_insert_with(|| {
+		let mut dispatch_name = package.replace('-', "_");
+		for segment in module_path.split("::").skip(1) {
+			dispatch_name = dispatch_name + "::" + segment;
+		}
+		Box::leak((dispatch_name + "::" + name).into_boxed_str())
+	})
+}
+
+/// Records that the frontend hook making a call, if any, shows the results of the backend function `dispatch_name`.
+#[doc(hidden)]
+#[cfg_attr(
+	not(any(feature = "dioxus", feature = "leptos", feature = "yew")),
+	allow(unused_variables)
+)]
+pub fn _track_query(dispatch_name: &'static str) {
+	#[cfg(any(feature = "dioxus", feature = "leptos", feature = "yew"))]
+	hooks::track_query(dispatch_name);
+}
+
+/// Runs the frontend hooks showing results of the backend functions `names`, after a call that changed them.
+#[doc(hidden)]
+#[cfg_attr(
+	not(any(feature = "dioxus", feature = "leptos", feature = "yew")),
+	allow(unused_variables)
+)]
+pub fn _invalidate(names: &[&str]) {
+	#[cfg(any(feature = "dioxus", feature = "leptos", feature = "yew"))]
+	hooks::invalidate(names);
+}
+
+/// The connection of the page being rendered on this thread, if any, for calls made while rendering to run in-process.
+#[server_only]
+#[doc(hidden)]
+pub fn _ssr_connection_info() -> Option<ConnectionInfo> {
+	#[cfg(feature = "ssr")]
+	return ssr::connection_info();
+	#[cfg(not(feature = "ssr"))]
+	None
+}
+
+/// Records `result` of the call `key`, made while rendering a page, to embed it in the page.
+#[server_only]
+#[doc(hidden)]
+#[cfg_attr(not(feature = "ssr"), allow(unused_variables))]
+pub fn _ssr_record<T: serde::Serialize>(key: Vec<u8>, result: &T) {
+	#[cfg(feature = "ssr")]
+	ssr::record(key, bincode::serialize(result).unwrap());
+}
+
+/// The serialized result of the call `key` embedded in the page by server-side rendering, if it hasn't been used yet.
+#[cfg(target_arch = "wasm32")]
+#[doc(hidden)]
+#[cfg_attr(not(feature = "ssr"), allow(unused_variables))]
+pub fn _prefetched(key: &impl serde::Serialize) -> Option<Vec<u8>> {
+	#[cfg(feature = "ssr")]
+	return ssr::take(key);
+	#[cfg(not(feature = "ssr"))]
+	None
+}
+
+/// Txid of the messages with a session token: the server sends it to the client when the session starts, and a client
+/// resuming the session sends it first, rather than in the WebSocket URL, which may be logged.
+#[cfg(any(feature = "wasm", target_arch = "wasm32", feature = "axum"))]
+const SESSION_TOKEN_TXID: i64 = 2;
+
+/// Txid of the message that tells the client a stream has ended, followed by the stream's txid; real txids start at 256.
+#[cfg_attr(not(any(feature = "wasm", target_arch = "wasm32", feature = "axum")), allow(dead_code))]
+const STREAM_END_TXID: i64 = 3;
+
 #[doc(hidden)]
 pub struct _Transaction {
 	pub txid: i64,
@@ -184,11 +521,7 @@ impl _Transaction {
 
 	#[cfg(target_arch = "wasm32")]
 	pub fn send_ws(&self, req: Vec<u8>) {
-		wasm_bindgen_futures::spawn_local(async move {
-			ensure_ws_connected().await;
-			let mut channel = G.lock().unwrap().channel_tx.clone().unwrap();
-			channel.send(req).await.unwrap();
-		});
+		send_ws(req);
 	}
 
 	#[server_only]
@@ -201,6 +534,77 @@ impl _Transaction {
 		self.resp_rx.next().await.unwrap()
 	}
 
+	/// Sends `req` and waits for the response. Dropping the future before then cancels the request.
+	#[cfg(target_arch = "wasm32")]
+	pub async fn call(mut self, req: Vec<u8>) -> Vec<u8> {
+		self.send_ws(req.clone());
+		let mut cancel = CancelOnDrop { txid: self.txid, req: Some(req) };
+		let response = self.resp_rx.next().await.unwrap();
+		cancel.req = None;
+		response
+	}
+
+	/// Like `call`, but the signal in `options` aborting first also cancels the request, and returns the abort reason.
+	#[cfg(target_arch = "wasm32")]
+	pub async fn call_with_options(
+		mut self,
+		req: Vec<u8>,
+		options: Option<_CallOptions>,
+	) -> Result<Vec<u8>, JsValue> {
+		use futures_util::future::{select, Either};
+
+		let signal = match options.and_then(|options| options.signal()) {
+			Some(signal) => signal,
+			None => return Ok(self.call(req).await),
+		};
+		if signal.aborted() {
+			G.lock().unwrap().senders.remove(&self.txid);
+			return Err(abort_reason(&signal));
+		}
+		self.send_ws(req.clone());
+		let mut cancel = CancelOnDrop { txid: self.txid, req: Some(req) };
+
+		let (abort_tx, abort_rx) = futures_channel::oneshot::channel();
+		let on_abort = Closure::once(move || {
+			abort_tx.send(()).ok();
+		});
+		signal.add_event_listener_with_callback("abort", on_abort.as_ref().unchecked_ref())?;
+		let response = select(self.resp_rx.next(), abort_rx).await;
+		signal.remove_event_listener_with_callback("abort", on_abort.as_ref().unchecked_ref())?;
+
+		match response {
+			Either::Left((response, _)) => {
+				cancel.req = None;
+				Ok(response.unwrap())
+			}
+			Either::Right(_) => Err(abort_reason(&signal)),
+		}
+	}
+
+	/// Sends the streaming request `req` and returns its responses, which end when the stream does. Dropping the
+	/// returned stream before then cancels the request. It's fused, so it can be polled after it has ended.
+	#[cfg(target_arch = "wasm32")]
+	pub fn stream(
+		self,
+		req: Vec<u8>,
+	) -> impl futures_util::stream::FusedStream<Item = Vec<u8>> + Unpin {
+		self.send_ws(req.clone());
+		let cancel = CancelOnDrop { txid: self.txid, req: Some(req) };
+		StreamExt::fuse(Box::pin(futures_util::stream::unfold(
+			(self.resp_rx, cancel),
+			|(mut resp_rx, mut cancel)| async move {
+				match resp_rx.next().await {
+					Some(response) => Some((response, (resp_rx, cancel))),
+					None => {
+						// the stream has ended, so there's nothing to cancel
+						cancel.req.take();
+						None
+					}
+				}
+			},
+		)))
+	}
+
 	#[cfg(target_arch = "wasm32")]
 	pub fn set_sender(mut self, sender: Box<dyn Fn(Vec<u8>)>) {
 		wasm_bindgen_futures::spawn_local(async move {
@@ -217,6 +621,46 @@ impl Default for _Transaction {
 	}
 }
 
+/// Cancels the request `req` with txid `txid`, unless it's been taken, when dropped.
+#[cfg(target_arch = "wasm32")]
+struct CancelOnDrop {
+	txid: i64,
+	req: Option<Vec<u8>>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Drop for CancelOnDrop {
+	fn drop(&mut self) {
+		if let Some(req) = self.req.take() {
+			// re-sending the request cancels it
+			G.lock().unwrap().senders.remove(&self.txid);
+			send_ws(req);
+		}
+	}
+}
+
+/// The reason `signal` was aborted with, or an `AbortError` in browsers that don't have `AbortSignal.reason`.
+#[cfg(target_arch = "wasm32")]
+fn abort_reason(signal: &web_sys::AbortSignal) -> JsValue {
+	match signal.reason() {
+		reason if reason.is_undefined() => web_sys::DomException::new_with_message_and_name(
+			"signal is aborted without reason",
+			"AbortError",
+		)
+		.map_or(reason, JsValue::from),
+		reason => reason,
+	}
+}
+
+#[cfg(target_arch = "wasm32")]
+fn send_ws(req: Vec<u8>) {
+	wasm_bindgen_futures::spawn_local(async move {
+		ensure_ws_connected().await;
+		let mut channel = G.lock().unwrap().channel_tx.clone().unwrap();
+		channel.send(req).await.unwrap();
+	});
+}
+
 /// _Experimental._ Spawns a new Turbocharger UDP server. Future resolves when the server is ready to respond to requests.
 #[server_only]
 #[tracked::tracked]
@@ -225,6 +669,7 @@ pub async fn spawn_udp_server(port: u16) -> Result<(), tracked::StringError> {
 	let socket = std::sync::Arc::new(tokio::net::UdpSocket::bind(format!("0.0.0.0:{}", port)).await?);
 	log::debug!("Listening on: {}", socket.local_addr()?);
 	*UDP_SOCKET.lock().unwrap() = Some(socket.clone());
+	dispatch::check();
 
 	tokio::spawn(async move {
 		loop {
@@ -238,10 +683,13 @@ pub async fn spawn_udp_server(port: u16) -> Result<(), tracked::StringError> {
 			let msg = buf[0..size].to_vec();
 			match first_word {
 				1 => {
-					// typetagged request
+					// request
 					let send_socket = socket.clone();
 					tokio::task::spawn(async move {
-						let target_func: Box<dyn RPC> = bincode::deserialize(&msg).unwrap();
+						let target_func = match dispatch::deserialize(&msg) {
+							Ok(target_func) => target_func,
+							Err(e) => return log::error!("udp deserialize error: {} from {}", e, peer),
+						};
 						let sender = Box::new(move |response: Vec<u8>| {
 							let send_socket_cloned = send_socket.clone();
 							tokio::task::spawn(async move {
@@ -251,9 +699,11 @@ pub async fn spawn_udp_server(port: u16) -> Result<(), tracked::StringError> {
 						let connection_info = ConnectionInfo {
 							remote_addr: Some(peer),
 							user_agent: Some("udp".into()),
+							state: global_state(),
 							connection_local: Default::default(),
+							session: None,
 						};
-						target_func.execute(sender, None, Some(connection_info)).await;
+						telemetry::execute(target_func, size, sender, None, Some(connection_info)).await;
 					});
 				}
 				txid => {
@@ -275,6 +725,20 @@ pub fn set_socket_url(url: String) {
 	g.socket_url = Some(url);
 }
 
+/// Returns the session token assigned by the server, if sessions are enabled; persist it to resume the session after a page reload.
+#[wasm_only]
+#[wasm_bindgen]
+pub fn session_token() -> Option<String> {
+	G.lock().unwrap().session_token.clone()
+}
+
+/// Sets the session token presented to the server when the WebSocket (re)connects.
+#[wasm_only]
+#[wasm_bindgen]
+pub fn set_session_token(token: Option<String>) {
+	G.lock().unwrap().session_token = token;
+}
+
 #[wasm_only]
 #[allow(dead_code)]
 async fn ensure_ws_connected() {
@@ -291,7 +755,9 @@ async fn ensure_ws_connected() {
 				format!("{}//{}/turbocharger_socket", protocol, location.host().unwrap())
 			});
 		}
-		let socket_url = g.socket_url.clone().unwrap();
+		let mut socket_url = g.socket_url.clone().unwrap();
+		socket_url.push(if socket_url.contains('?') { '&' } else { '?' });
+		socket_url.push_str("stream_end=1");
 
 		if g.channel_tx.is_some() {
 			return;
@@ -315,14 +781,37 @@ async fn ensure_ws_connected() {
 		while let Some(msg) = ws_rx.next().await {
 			if let ws_stream_wasm::WsMessage::Binary(msg) = msg {
 				let txid = i64::from_le_bytes(msg[0..8].try_into().unwrap());
-				let mut sender = G.lock().unwrap().senders.get(&txid).unwrap().clone();
-				sender.send(msg).await.unwrap();
+				if txid == SESSION_TOKEN_TXID {
+					// session token
+					G.lock().unwrap().session_token = String::from_utf8(msg[8..].to_vec()).ok();
+					continue;
+				}
+				if txid == STREAM_END_TXID {
+					// closing the stream's channel ends its receiver
+					let txid = i64::from_le_bytes(msg[8..16].try_into().unwrap());
+					G.lock().unwrap().senders.remove(&txid);
+					continue;
+				}
+				// responses to cancelled streams may still arrive
+				let sender = G.lock().unwrap().senders.get(&txid).cloned();
+				if let Some(mut sender) = sender {
+					sender.send(msg).await.ok();
+				}
 			}
 		}
 		tc_console_log!("ws_rx ENDED");
+		// reconnect, resuming the session, on the next request
+		G.lock().unwrap().channel_tx = None;
 	});
 
 	wasm_bindgen_futures::spawn_local(async move {
+		// resume the session, before any requests
+		let session_token = G.lock().unwrap().session_token.clone();
+		if let Some(token) = session_token {
+			let mut msg = SESSION_TOKEN_TXID.to_le_bytes().to_vec();
+			msg.extend_from_slice(token.as_bytes());
+			ws_tx.send(ws_stream_wasm::WsMessage::Binary(msg)).await.unwrap();
+		}
 		while let Some(msg) = channel_rx.next().await {
 			ws_tx.send(ws_stream_wasm::WsMessage::Binary(msg)).await.unwrap();
 		}
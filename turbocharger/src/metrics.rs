@@ -0,0 +1,179 @@
+//! Prometheus metrics for backend calls and connections, enabled by the `metrics` feature.
+
+use once_cell::sync::Lazy;
+use prometheus::{
+	Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+	TextEncoder,
+};
+use std::sync::Mutex;
+
+struct Metrics {
+	registry: Registry,
+	calls: IntCounterVec,
+	errors: IntCounterVec,
+	panics: IntCounterVec,
+	cancellations: IntCounterVec,
+	duration: HistogramVec,
+	in_flight: IntGaugeVec,
+	active_streams: IntGaugeVec,
+	connections: IntGauge,
+}
+
+static METRICS: Lazy<Metrics> = Lazy::new(|| {
+	let registry = Registry::new_custom(Some("turbocharger".into()), None).unwrap();
+	let counter = |name: &str, help: &str| {
+		let counter = IntCounterVec::new(Opts::new(name, help), &["function"]).unwrap();
+		registry.register(Box::new(counter.clone())).unwrap();
+		counter
+	};
+	let gauge = |name: &str, help: &str| {
+		let gauge = IntGaugeVec::new(Opts::new(name, help), &["function"]).unwrap();
+		registry.register(Box::new(gauge.clone())).unwrap();
+		gauge
+	};
+
+	let calls = counter("rpc_calls_total", "Backend function calls started.");
+	let errors =
+		counter("rpc_errors_total", "Backend function calls that returned or streamed an `Err`.");
+	let panics = counter("rpc_panics_total", "Backend function calls that panicked.");
+	let cancellations = counter("rpc_cancellations_total", "Calls cancelled by the client.");
+	let in_flight = gauge("rpc_in_flight", "Backend function calls currently executing.");
+	let active_streams = gauge("rpc_active_streams", "Streaming subscriptions currently open.");
+
+	let duration = HistogramVec::new(
+		HistogramOpts::new("rpc_duration_seconds", "Backend function call latency."),
+		&["function"],
+	)
+	.unwrap();
+	registry.register(Box::new(duration.clone())).unwrap();
+
+	let connections = IntGauge::new("connections", "Open WebSocket connections.").unwrap();
+	registry.register(Box::new(connections.clone())).unwrap();
+
+	Metrics {
+		registry,
+		calls,
+		errors,
+		panics,
+		cancellations,
+		duration,
+		in_flight,
+		active_streams,
+		connections,
+	}
+});
+
+static ROUTE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Sets the route on which `serve` and `serve_tls` expose metrics, e.g. `Some("/metrics")`, or `None` to not expose
+/// them, which is the default, since they may be public.
+pub fn set_route(route: Option<&str>) {
+	*ROUTE.lock().unwrap() = route.map(Into::into);
+}
+
+#[cfg_attr(not(feature = "axum"), allow(dead_code))]
+pub(crate) fn route() -> Option<String> {
+	ROUTE.lock().unwrap().clone()
+}
+
+/// Returns Turbocharger's metrics, followed by anything registered in the default `prometheus` registry, in the Prometheus text format.
+pub fn render() -> String {
+	let mut families = METRICS.registry.gather();
+	families.extend(prometheus::gather());
+	let mut buffer = Vec::new();
+	TextEncoder::new().encode(&families, &mut buffer).unwrap();
+	String::from_utf8(buffer).unwrap()
+}
+
+/// Axum handler serving [`render`], for mounting on your own `Router`.
+#[cfg(feature = "axum")]
+pub async fn metrics_handler() -> impl axum::response::IntoResponse {
+	([(axum::http::header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], render())
+}
+
+pub(crate) fn record_error(function: &str) {
+	METRICS.errors.with_label_values(&[function]).inc();
+}
+
+/// Tracks one backend call from start to finish; gauges are released on drop, even if the task is aborted.
+pub(crate) struct InFlight {
+	function: &'static str,
+	is_stream: bool,
+	start: std::time::Instant,
+}
+
+impl InFlight {
+	pub(crate) fn start(function: &'static str, is_stream: bool) -> Self {
+		METRICS.calls.with_label_values(&[function]).inc();
+		METRICS.in_flight.with_label_values(&[function]).inc();
+		if is_stream {
+			METRICS.active_streams.with_label_values(&[function]).inc();
+		}
+		Self { function, is_stream, start: std::time::Instant::now() }
+	}
+
+	pub(crate) fn finish(self, ended: &str) {
+		METRICS.duration.with_label_values(&[self.function]).observe(self.start.elapsed().as_secs_f64());
+		match ended {
+			"cancelled" => METRICS.cancellations.with_label_values(&[self.function]).inc(),
+			"panicked" => METRICS.panics.with_label_values(&[self.function]).inc(),
+			_ => (),
+		}
+	}
+}
+
+impl Drop for InFlight {
+	fn drop(&mut self) {
+		METRICS.in_flight.with_label_values(&[self.function]).dec();
+		if self.is_stream {
+			METRICS.active_streams.with_label_values(&[self.function]).dec();
+		}
+	}
+}
+
+/// Counts one open WebSocket connection for as long as it is held.
+#[cfg_attr(not(feature = "axum"), allow(dead_code))]
+pub(crate) struct Connection;
+
+impl Connection {
+	#[cfg_attr(not(feature = "axum"), allow(dead_code))]
+	pub(crate) fn open() -> Self {
+		METRICS.connections.inc();
+		Self
+	}
+}
+
+impl Drop for Connection {
+	fn drop(&mut self) {
+		METRICS.connections.dec();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_render() {
+		let call = InFlight::start("test_render_stream", true);
+		let line = |name: &str| format!("turbocharger_{}{{function=\"test_render_stream\"}}", name);
+		let rendered = render();
+		for (name, value) in [("rpc_calls_total", 1), ("rpc_in_flight", 1), ("rpc_active_streams", 1)] {
+			assert!(rendered.contains(&format!("{} {}\n", line(name), value)), "{}", rendered);
+		}
+
+		call.finish("cancelled");
+		record_error("test_render_stream");
+		let rendered = render();
+		for (name, value) in [
+			("rpc_in_flight", 0),
+			("rpc_active_streams", 0),
+			("rpc_cancellations_total", 1),
+			("rpc_errors_total", 1),
+			("rpc_duration_seconds_count", 1),
+		] {
+			assert!(rendered.contains(&format!("{} {}\n", line(name), value)), "{}", rendered);
+		}
+		assert!(!rendered.contains(&line("rpc_panics_total")));
+	}
+}
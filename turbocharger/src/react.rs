@@ -0,0 +1,142 @@
+//! React hooks for `#[backend(js)]` functions, generated with the `react` feature.
+
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(typescript_custom_section)]
+const HOOK_STATES: &'static str = r#"
+export interface QueryState<T> {
+  loading: boolean;
+  data: T | undefined;
+  error: unknown;
+  /** Calls the function again, keeping `data` until the response arrives. */
+  refetch(): void;
+}
+
+export interface StreamState<T> {
+  /** The latest item, or `undefined` before the first one arrives. */
+  value: T | undefined;
+  /** The latest error, for streams of `Result`s. */
+  error: unknown;
+}
+"#;
+
+#[wasm_bindgen(inline_js = r#"
+import { useEffect, useRef, useState } from "react";
+
+// Keeps the latest of the Rust objects passed on each render, freeing the ones it replaces, and the last on unmount.
+// The unmount is deferred, since StrictMode unmounts and mounts again at once.
+function useLatest(object) {
+	const latest = useRef(null);
+	if (latest.current !== object) {
+		latest.current?.free();
+		latest.current = object;
+	}
+	useEffect(() => {
+		clearTimeout(latest.unmount);
+		return () => {
+			latest.unmount = setTimeout(() => {
+				latest.current?.free();
+				latest.current = null;
+			});
+		};
+	}, []);
+	return latest;
+}
+
+export function useQuery(key, call) {
+	const latest = useLatest(call);
+	const [nonce, setNonce] = useState(0);
+	const [state, setState] = useState({ loading: true, data: undefined, error: undefined });
+	useEffect(() => {
+		const controller = new AbortController();
+		setState((state) => (state.loading ? state : { ...state, loading: true }));
+		latest.current.call({ signal: controller.signal }).then(
+			(data) => controller.signal.aborted || setState({ loading: false, data, error: undefined }),
+			(error) => controller.signal.aborted || setState({ loading: false, data: undefined, error }),
+		);
+		return () => controller.abort();
+	}, [key, nonce]);
+	return { ...state, refetch: () => setNonce((nonce) => nonce + 1) };
+}
+
+export function useStream(key, open) {
+	const latest = useLatest(open);
+	const [state, setState] = useState({ value: undefined, error: undefined });
+	useEffect(() => {
+		let active = true;
+		const unsubscribe = latest.current.open().subscribe((value) => {
+			if (value instanceof Promise) {
+				value.then(
+					(value) => active && setState({ value, error: undefined }),
+					(error) => active && setState((state) => ({ ...state, error })),
+				);
+			} else {
+				setState({ value, error: undefined });
+			}
+		});
+		return () => {
+			active = false;
+			unsubscribe();
+		};
+	}, [key]);
+	return state;
+}
+"#)]
+extern "C" {
+	#[wasm_bindgen(js_name = useQuery)]
+	fn use_query(key: &str, call: QueryCall) -> JsValue;
+
+	#[wasm_bindgen(js_name = useStream)]
+	fn use_stream(key: &str, open: StreamOpen) -> JsValue;
+}
+
+type Call = dyn Fn(&[u8], Option<crate::_CallOptions>) -> js_sys::Promise;
+type Open = dyn Fn(&[u8]) -> JsValue;
+
+/// A render's call with its parameters, owned by the hook, which frees it when it's replaced or unmounted.
+#[wasm_bindgen(js_name = TurbochargerQueryCall, skip_typescript)]
+pub struct QueryCall {
+	params: Vec<u8>,
+	call: Box<Call>,
+}
+
+#[wasm_bindgen(js_class = TurbochargerQueryCall)]
+impl QueryCall {
+	pub fn call(&self, options: JsValue) -> js_sys::Promise {
+		(self.call)(&self.params, Some(options.unchecked_into()))
+	}
+}
+
+/// A render's stream with its parameters, owned by the hook like [`QueryCall`].
+#[wasm_bindgen(js_name = TurbochargerStreamOpen, skip_typescript)]
+pub struct StreamOpen {
+	params: Vec<u8>,
+	open: Box<Open>,
+}
+
+#[wasm_bindgen(js_class = TurbochargerStreamOpen)]
+impl StreamOpen {
+	pub fn open(&self) -> JsValue {
+		(self.open)(&self.params)
+	}
+}
+
+/// The state of the call that `call` makes with `params` and the given `CallOptions`, made again when `params` change.
+/// Unmounting, or changing `params`, aborts the call.
+pub fn _use_query(
+	params: Vec<u8>,
+	call: impl Fn(&[u8], Option<crate::_CallOptions>) -> js_sys::Promise + 'static,
+) -> JsValue {
+	use_query(&key(&params), QueryCall { params, call: Box::new(call) })
+}
+
+/// The latest item of the stream store that `open` returns for `params`, subscribed to again when `params` change.
+/// Unmounting, or changing `params`, unsubscribes.
+pub fn _use_stream(params: Vec<u8>, open: impl Fn(&[u8]) -> JsValue + 'static) -> JsValue {
+	use_stream(&key(&params), StreamOpen { params, open: Box::new(open) })
+}
+
+/// React compares hook dependencies by identity, so the serialized parameters are compared as a string.
+fn key(params: &[u8]) -> String {
+	params.iter().map(|&b| char::from(b)).collect()
+}
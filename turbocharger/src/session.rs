@@ -0,0 +1,356 @@
+//! Opt-in sessions that survive WebSocket reconnects, used by `session_local!()`.
+//!
+//! Enable them with [`set_session_store`]. The client is sent a session token when it connects, and presents it again
+//! in its first message when it reconnects, so values stored with `session_local!()` are restored until the session
+//! expires. Connections presenting the same token, e.g. from several tabs, share the session's values.
+
+use crate::{ConnectionLocal, ConnectionLocals};
+use once_cell::sync::Lazy;
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+/// Storage for session values, serialized with `bincode`. Implementations are responsible for expiring sessions.
+pub trait SessionStore: Send + Sync {
+	/// Returns the values saved in session `token`, refreshing its expiry, or `None` if it has none or has expired.
+	fn load(&self, token: &str) -> Option<HashMap<String, Vec<u8>>>;
+	/// Saves `value` under `key` in session `token`, refreshing its expiry.
+	fn save(&self, token: &str, key: &str, value: Vec<u8>);
+}
+
+static STORE: Lazy<Mutex<Option<Arc<dyn SessionStore>>>> = Lazy::new(Mutex::default);
+
+/// Enables sessions for new WebSocket connections, stored in `store`.
+pub fn set_session_store(store: impl SessionStore + 'static) {
+	*STORE.lock().unwrap() = Some(Arc::new(store));
+}
+
+/// Keeps sessions in memory; they are lost when the server restarts.
+pub struct MemorySessionStore {
+	ttl: Duration,
+	#[allow(clippy::type_complexity)]
+	sessions: Mutex<HashMap<String, (Instant, HashMap<String, Vec<u8>>)>>,
+}
+
+impl MemorySessionStore {
+	/// Sessions expire `ttl` after they were last used.
+	pub fn new(ttl: Duration) -> Self {
+		Self { ttl, sessions: Default::default() }
+	}
+}
+
+impl SessionStore for MemorySessionStore {
+	fn load(&self, token: &str) -> Option<HashMap<String, Vec<u8>>> {
+		let mut sessions = self.sessions.lock().unwrap();
+		sessions.retain(|_, (last_seen, _)| last_seen.elapsed() < self.ttl);
+		let (last_seen, values) = sessions.get_mut(token)?;
+		*last_seen = Instant::now();
+		Some(values.clone())
+	}
+
+	fn save(&self, token: &str, key: &str, value: Vec<u8>) {
+		let mut sessions = self.sessions.lock().unwrap();
+		let (last_seen, values) =
+			sessions.entry(token.into()).or_insert_with(|| (Instant::now(), HashMap::new()));
+		*last_seen = Instant::now();
+		values.insert(key.into(), value);
+	}
+}
+
+#[cfg(feature = "turbosql")]
+pub use self::turbosql_store::TurbosqlSessionStore;
+
+#[cfg(feature = "turbosql")]
+mod turbosql_store {
+	use super::*;
+	use turbosql::{execute, now_ms, select, Blob, Turbosql};
+
+	#[allow(non_camel_case_types)]
+	#[derive(Turbosql, Default)]
+	struct _turbocharger_session {
+		rowid: Option<i64>,
+		token: Option<String>,
+		name: Option<String>,
+		value: Option<Blob>,
+		last_seen_ms: Option<i64>,
+	}
+
+	/// Keeps sessions in the application's Turbosql database, so they survive server restarts.
+	pub struct TurbosqlSessionStore {
+		ttl: Duration,
+	}
+
+	impl TurbosqlSessionStore {
+		/// Sessions expire `ttl` after they were last used.
+		pub fn new(ttl: Duration) -> Self {
+			Self { ttl }
+		}
+	}
+
+	impl SessionStore for TurbosqlSessionStore {
+		fn load(&self, token: &str) -> Option<HashMap<String, Vec<u8>>> {
+			let expired = now_ms() - self.ttl.as_millis() as i64;
+			if let Err(e) = execute!("DELETE FROM _turbocharger_session WHERE last_seen_ms < ?", expired) {
+				log::warn!("session expiry failed: {}", e);
+			}
+			let rows = select!(Vec<_turbocharger_session> "WHERE token = ?", token)
+				.map_err(|e| log::warn!("session load failed: {}", e))
+				.ok()?;
+			if rows.is_empty() {
+				return None;
+			}
+			execute!("UPDATE _turbocharger_session SET last_seen_ms = ? WHERE token = ?", now_ms(), token)
+				.ok();
+			Some(rows.into_iter().filter_map(|row| Some((row.name?, row.value?))).collect())
+		}
+
+		fn save(&self, token: &str, key: &str, value: Vec<u8>) {
+			let result =
+				execute!("DELETE FROM _turbocharger_session WHERE token = ? AND name = ?", token, key)
+					.and_then(|_| {
+						_turbocharger_session {
+							rowid: None,
+							token: Some(token.into()),
+							name: Some(key.into()),
+							value: Some(value),
+							last_seen_ms: Some(now_ms()),
+						}
+						.insert()
+					});
+			if let Err(e) = result {
+				log::warn!("session save failed: {}", e);
+			}
+		}
+	}
+}
+
+/// A client's session, shared by its connections, e.g. in several tabs, while any of them is open.
+#[derive(Clone)]
+pub struct Session(Arc<SessionInner>);
+
+struct SessionInner {
+	token: String,
+	store: Arc<dyn SessionStore>,
+	/// The serialized value of each key, as last loaded from or saved to the store.
+	values: Mutex<HashMap<String, Vec<u8>>>,
+	/// Held while saving, so saves of the same key can't overtake each other.
+	saving: Mutex<()>,
+	locals: ConnectionLocals,
+}
+
+/// Sessions with open connections, by token.
+static LIVE: Lazy<Mutex<HashMap<String, std::sync::Weak<SessionInner>>>> =
+	Lazy::new(Mutex::default);
+
+impl Drop for SessionInner {
+	fn drop(&mut self) {
+		let mut live = LIVE.lock().unwrap();
+		// the session may have been resumed from the store again since its last connection closed
+		if live.get(&self.token).is_some_and(|session| session.strong_count() == 0) {
+			live.remove(&self.token);
+		}
+	}
+}
+
+impl Session {
+	/// Joins the session named by `token` if it has open connections, or else resumes it from the store if it is still
+	/// live there, or else starts a new one. Returns `None` if sessions are not enabled.
+	#[cfg_attr(not(feature = "axum"), allow(dead_code))]
+	pub(crate) fn resume_or_create(token: Option<&str>) -> Option<Self> {
+		let store = STORE.lock().unwrap().clone()?;
+		if let Some(session) = token.and_then(|token| LIVE.lock().unwrap().get(token)?.upgrade()) {
+			return Some(Self(session));
+		}
+		let resumed = token.and_then(|token| Some((token.to_string(), store.load(token)?)));
+		let (token, values) = resumed.unwrap_or_else(|| (new_token(), HashMap::new()));
+		let session = Arc::new(SessionInner {
+			token,
+			store,
+			values: Mutex::new(values),
+			saving: Mutex::new(()),
+			locals: Default::default(),
+		});
+		LIVE.lock().unwrap().insert(session.token.clone(), Arc::downgrade(&session));
+		Some(Self(session))
+	}
+
+	pub fn token(&self) -> &str {
+		&self.0.token
+	}
+
+	#[doc(hidden)]
+	pub async fn _lock<T>(&self, key: &'static str) -> SessionLocal<T>
+	where
+		T: serde::Serialize + serde::de::DeserializeOwned + Send + 'static,
+	{
+		SessionLocal {
+			local: self.0.locals._lock(key).await,
+			key: format!("{}: {}", key, std::any::type_name::<T>()),
+			session: self.clone(),
+		}
+	}
+
+	/// Saves the serialized `value` of `key` if it changed, on a blocking thread, since stores may do I/O.
+	fn save(&self, key: &str, value: Vec<u8>) {
+		{
+			let mut values = self.0.values.lock().unwrap();
+			if values.get(key) == Some(&value) {
+				return;
+			}
+			values.insert(key.to_string(), value);
+		}
+		let (session, key) = (self.0.clone(), key.to_string());
+		let save = move || {
+			let _saving = session.saving.lock().unwrap();
+			// a later change may have been made since, in which case this saves that
+			let value = session.values.lock().unwrap().get(&key).cloned();
+			if let Some(value) = value {
+				session.store.save(&session.token, &key, value);
+			}
+		};
+		match tokio::runtime::Handle::try_current() {
+			Ok(runtime) => drop(runtime.spawn_blocking(save)),
+			Err(_) => save(),
+		}
+	}
+}
+
+fn new_token() -> String {
+	let mut bytes = [0u8; 24];
+	getrandom::getrandom(&mut bytes).unwrap();
+	bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The token in a message a client resuming a session sends first: `SESSION_TOKEN_TXID`, then the token.
+#[cfg(feature = "axum")]
+pub(crate) fn token_message(data: &[u8]) -> Option<&str> {
+	let txid = i64::from_le_bytes(data.get(..8)?.try_into().unwrap());
+	match txid == crate::SESSION_TOKEN_TXID {
+		true => std::str::from_utf8(&data[8..]).ok(),
+		false => None,
+	}
+}
+
+/// A locked handle to a session value of type `T`, which is saved to the store when this is dropped, if it changed.
+pub struct SessionLocal<T: serde::Serialize + Send + 'static> {
+	local: ConnectionLocal<T>,
+	key: String,
+	session: Session,
+}
+
+impl<T> SessionLocal<T>
+where
+	T: serde::Serialize + serde::de::DeserializeOwned + Send + 'static,
+{
+	#[doc(hidden)]
+	pub fn _is_empty(&self) -> bool {
+		self.local._is_empty()
+	}
+
+	#[doc(hidden)]
+	pub fn _set(&mut self, value: T) {
+		self.local._set(value);
+	}
+
+	/// The value saved by a previous connection in this session, if any.
+	#[doc(hidden)]
+	pub fn _stored(&self) -> Option<T> {
+		bincode::deserialize(self.session.0.values.lock().unwrap().get(&self.key)?).ok()
+	}
+}
+
+impl<T: serde::Serialize + Send + 'static> std::ops::Deref for SessionLocal<T> {
+	type Target = T;
+	fn deref(&self) -> &T {
+		&self.local
+	}
+}
+
+impl<T: serde::Serialize + Send + 'static> std::ops::DerefMut for SessionLocal<T> {
+	fn deref_mut(&mut self) -> &mut T {
+		&mut self.local
+	}
+}
+
+impl<T: serde::Serialize + Send + 'static> Drop for SessionLocal<T> {
+	fn drop(&mut self) {
+		if !self.local._is_empty() {
+			match bincode::serialize(&*self.local) {
+				Ok(value) => self.session.save(&self.key, value),
+				Err(e) => log::warn!("session value serialization failed: {}", e),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures_util::FutureExt;
+	use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+	static SAVES: AtomicUsize = AtomicUsize::new(0);
+
+	struct CountingStore(MemorySessionStore);
+
+	impl SessionStore for CountingStore {
+		fn load(&self, token: &str) -> Option<HashMap<String, Vec<u8>>> {
+			self.0.load(token)
+		}
+
+		fn save(&self, token: &str, key: &str, value: Vec<u8>) {
+			SAVES.fetch_add(1, SeqCst);
+			self.0.save(token, key, value)
+		}
+	}
+
+	/// Locks `visits` like `session_local!(visits: &mut i64)`; saves run inline, since there's no runtime.
+	fn visits(session: &Session) -> SessionLocal<i64> {
+		let mut local = session._lock::<i64>("visits").now_or_never().unwrap();
+		if local._is_empty() {
+			let stored = local._stored();
+			local._set(stored.unwrap_or_default());
+		}
+		local
+	}
+
+	#[test]
+	fn test_sessions() {
+		set_session_store(CountingStore(MemorySessionStore::new(Duration::from_millis(300))));
+
+		let a = Session::resume_or_create(None).unwrap();
+		*visits(&a) += 1;
+		assert_eq!(SAVES.load(SeqCst), 1);
+		// reading without a change doesn't save
+		assert_eq!(*visits(&a), 1);
+		assert_eq!(SAVES.load(SeqCst), 1);
+
+		// connections with the same token share the session
+		let b = Session::resume_or_create(Some(a.token())).unwrap();
+		assert!(Arc::ptr_eq(&a.0, &b.0));
+		*visits(&b) += 1;
+		assert_eq!(*visits(&a), 2);
+
+		// resuming after all connections closed loads the values from the store
+		let token = a.token().to_string();
+		drop((a, b));
+		let c = Session::resume_or_create(Some(&token)).unwrap();
+		assert_eq!(c.token(), token);
+		assert_eq!(*visits(&c), 2);
+		assert_eq!(SAVES.load(SeqCst), 2);
+
+		// an unknown token starts a new session
+		let d = Session::resume_or_create(Some("bad")).unwrap();
+		assert_ne!(d.token(), "bad");
+		assert_eq!(*visits(&d), 0);
+
+		// as does the token of an expired session
+		drop(c);
+		std::thread::sleep(Duration::from_millis(400));
+		let e = Session::resume_or_create(Some(&token)).unwrap();
+		assert_ne!(e.token(), token);
+		assert_eq!(*visits(&e), 0);
+	}
+}
@@ -0,0 +1,303 @@
+//! Server-side rendering: while the server renders a page, `#[backend]` functions called directly run in-process and
+//! their results are embedded in the page, where the WASM frontend's first calls with the same arguments find them
+//! instead of making requests.
+
+use base64::Engine;
+#[cfg(target_arch = "wasm32")]
+use once_cell::sync::Lazy;
+#[cfg(any(target_arch = "wasm32", test))]
+use std::collections::HashMap;
+#[cfg(target_arch = "wasm32")]
+use std::sync::Mutex;
+
+/// Id of the `<script>` element holding the results, base64-encoded.
+const PREFETCHED_ID: &str = "turbocharger-prefetched";
+
+/// Results of the calls made while rendering a page, by the dispatch name and arguments of each call.
+pub(crate) type Prefetched = Vec<(Vec<u8>, Vec<u8>)>;
+
+#[cfg(not(target_arch = "wasm32"))]
+struct Capture {
+	connection_info: crate::ConnectionInfo,
+	prefetched: Prefetched,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+thread_local! {
+	/// The page being rendered on this thread, if any.
+	static CAPTURE: std::cell::RefCell<Option<Capture>> = Default::default();
+}
+
+/// Runs `f`, which renders a page for the connection `connection_info`, returning its result and the results of the
+/// `#[backend]` calls it made.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn capture<R>(
+	connection_info: crate::ConnectionInfo,
+	f: impl FnOnce() -> R,
+) -> (R, Prefetched) {
+	struct Reset;
+	impl Drop for Reset {
+		fn drop(&mut self) {
+			CAPTURE.with(|capture| capture.borrow_mut().take());
+		}
+	}
+
+	CAPTURE.with(|capture| {
+		*capture.borrow_mut() = Some(Capture { connection_info, prefetched: Vec::new() })
+	});
+	// blocking threads are reused, so a panic in `f` must not leave the capture in place
+	let _reset = Reset;
+	let result = f();
+	let prefetched = CAPTURE.with(|capture| capture.borrow_mut().take().unwrap().prefetched);
+	(result, prefetched)
+}
+
+/// The connection of the page being rendered on this thread, if any.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn connection_info() -> Option<crate::ConnectionInfo> {
+	CAPTURE.with(|capture| Some(capture.borrow().as_ref()?.connection_info.clone()))
+}
+
+/// Records the serialized `result` of the call `key` for the page being rendered on this thread.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn record(key: Vec<u8>, result: Vec<u8>) {
+	CAPTURE.with(|capture| {
+		if let Some(capture) = &mut *capture.borrow_mut() {
+			capture.prefetched.push((key, result));
+		}
+	});
+}
+
+/// `index_html` with `html` rendered into its `id="main"` element, where Dioxus mounts by default, and `prefetched`
+/// in a `<script>` element at the end of its body.
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn inject(index_html: &str, html: &str, prefetched: &Prefetched) -> String {
+	let mut page = index_html.to_string();
+	if let Some(main) = main_content_start(&page) {
+		page.insert_str(main, html);
+	}
+	let script = format!(
+		r#"<script id="{}" type="application/octet-stream">{}</script>"#,
+		PREFETCHED_ID,
+		base64::engine::general_purpose::STANDARD.encode(bincode::serialize(prefetched).unwrap())
+	);
+	match page.rfind("</body>") {
+		Some(body_end) => page.insert_str(body_end, &script),
+		None => page.push_str(&script),
+	}
+	page
+}
+
+/// The index just after the start tag of `index_html`'s element with the id `main`, if any. Comments and the contents
+/// of `<script>` and `<style>` elements are skipped, and attribute values may be quoted either way or not at all.
+#[cfg(not(target_arch = "wasm32"))]
+fn main_content_start(index_html: &str) -> Option<usize> {
+	let bytes = index_html.as_bytes();
+	let skip_whitespace = |mut i: usize| {
+		while bytes.get(i).is_some_and(u8::is_ascii_whitespace) {
+			i += 1;
+		}
+		i
+	};
+
+	let mut i = 0;
+	while let Some(open) = index_html[i..].find('<') {
+		i += open;
+		if index_html[i..].starts_with("<!--") {
+			i += index_html[i..].find("-->")? + "-->".len();
+			continue;
+		}
+		let name = &index_html[i + 1..];
+		let name = &name
+			[..name.find(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>').unwrap_or(name.len())];
+		if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+			// end tags, doctypes and a `<` in text
+			i += 1;
+			continue;
+		}
+
+		let mut j = i + 1 + name.len();
+		let mut is_main = false;
+		let end = loop {
+			while bytes.get(j).is_some_and(|&b| b.is_ascii_whitespace() || b == b'/') {
+				j += 1;
+			}
+			if *bytes.get(j)? == b'>' {
+				break j + 1;
+			}
+			let attribute_start = j;
+			while bytes
+				.get(j)
+				.is_some_and(|&b| !(b.is_ascii_whitespace() || matches!(b, b'/' | b'>' | b'=')))
+			{
+				j += 1;
+			}
+			let attribute = &index_html[attribute_start..j];
+			j = skip_whitespace(j);
+			let mut value = None;
+			if bytes.get(j) == Some(&b'=') {
+				j = skip_whitespace(j + 1);
+				match *bytes.get(j)? {
+					quote @ (b'"' | b'\'') => {
+						let len = index_html[j + 1..].find(quote as char)?;
+						value = Some(&index_html[j + 1..j + 1 + len]);
+						j += len + 2;
+					}
+					_ => {
+						let value_start = j;
+						while bytes.get(j).is_some_and(|&b| !(b.is_ascii_whitespace() || b == b'>')) {
+							j += 1;
+						}
+						value = Some(&index_html[value_start..j]);
+					}
+				}
+			}
+			is_main |= attribute.eq_ignore_ascii_case("id") && value == Some("main");
+		};
+		if is_main {
+			return Some(end);
+		}
+
+		i = end;
+		if name.eq_ignore_ascii_case("script") || name.eq_ignore_ascii_case("style") {
+			// the contents are text up to the end tag
+			let end_tag = format!("</{}", name.to_ascii_lowercase());
+			i += index_html[i..].to_ascii_lowercase().find(&end_tag)?;
+		}
+	}
+	None
+}
+
+/// Results embedded in the page that haven't been used yet.
+#[cfg(target_arch = "wasm32")]
+static PREFETCHED: Lazy<Mutex<HashMap<Vec<u8>, Vec<u8>>>> =
+	Lazy::new(|| Mutex::new(read_prefetched().unwrap_or_default()));
+
+#[cfg(target_arch = "wasm32")]
+fn read_prefetched() -> Option<HashMap<Vec<u8>, Vec<u8>>> {
+	let element = web_sys::window()?.document()?.get_element_by_id(PREFETCHED_ID)?;
+	decode(&element.text_content()?)
+}
+
+/// The results in the text of the `<script>` element written by [`inject`], by key.
+#[cfg(any(target_arch = "wasm32", test))]
+fn decode(text: &str) -> Option<HashMap<Vec<u8>, Vec<u8>>> {
+	let bytes = base64::engine::general_purpose::STANDARD.decode(text).ok()?;
+	let prefetched: Prefetched = bincode::deserialize(&bytes).ok()?;
+	Some(prefetched.into_iter().collect())
+}
+
+/// Takes the serialized result of the call `key` embedded in the page, if any; each result is used once, so later
+/// calls, e.g. after an invalidation, make requests.
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn take(key: &impl serde::Serialize) -> Option<Vec<u8>> {
+	take_from(&mut PREFETCHED.lock().unwrap(), key)
+}
+
+#[cfg(any(target_arch = "wasm32", test))]
+fn take_from(
+	prefetched: &mut HashMap<Vec<u8>, Vec<u8>>,
+	key: &impl serde::Serialize,
+) -> Option<Vec<u8>> {
+	if prefetched.is_empty() {
+		return None;
+	}
+	prefetched.remove(&bincode::serialize(key).unwrap())
+}
+
+/// Builds `vdom`, then renders it again as the `use_backend` calls it makes complete, until none are in flight, so
+/// `dioxus_ssr::pre_render(&vdom)` renders their results.
+#[cfg(all(feature = "dioxus", not(target_arch = "wasm32")))]
+pub async fn rebuild(vdom: &mut dioxus::prelude::VirtualDom) {
+	let _ = vdom.rebuild();
+	while crate::dioxus::calls_in_flight() > 0 {
+		vdom.wait_for_work().await;
+		let _ = vdom.render_immediate();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_inject() {
+		let index_html =
+			r#"<html><body><div id="main"></div><script src="app.js"></script></body></html>"#;
+		let page = inject(index_html, "<p>hi</p>", &vec![(vec![1], vec![2, 3])]);
+		assert!(
+			page.starts_with(r#"<html><body><div id="main"><p>hi</p></div><script src="app.js"></script>"#)
+		);
+		assert!(page.ends_with(r#"<script id="turbocharger-prefetched" type="application/octet-stream">AQAAAAAAAAABAAAAAAAAAAECAAAAAAAAAAID</script></body></html>"#));
+
+		let page = inject("<div>", "<p>hi</p>", &Vec::new());
+		assert_eq!(
+			page,
+			format!(
+				r#"<div><script id="{}" type="application/octet-stream">AAAAAAAAAAA=</script>"#,
+				PREFETCHED_ID
+			)
+		);
+	}
+
+	#[test]
+	fn test_main_content_start() {
+		fn start(index_html: &str) -> Option<(&str, &str)> {
+			main_content_start(index_html).map(|i| index_html.split_at(i))
+		}
+		assert_eq!(
+			start(r#"<!DOCTYPE html><body><div id="main"></div></body>"#),
+			Some((r#"<!DOCTYPE html><body><div id="main">"#, "</div></body>"))
+		);
+		assert_eq!(
+			start("<div class=app ID='main'>x</div>"),
+			Some(("<div class=app ID='main'>", "x</div>"))
+		);
+		assert_eq!(
+			start("<div id = main data-x=\"a>b\">x</div>"),
+			Some(("<div id = main data-x=\"a>b\">", "x</div>"))
+		);
+		assert_eq!(start("<main id=\"main\"/>"), Some(("<main id=\"main\"/>", "")));
+
+		// other attributes, and values containing the attribute
+		assert_eq!(start(r#"<div data-id="main"></div>"#), None);
+		assert_eq!(start(r#"<div title='id="main"'></div>"#), None);
+		assert_eq!(start(r#"<div id="mainer"></div>"#), None);
+		// comments and scripts
+		assert_eq!(
+			start(r#"<!-- <div id="main"> --><p id="main">"#),
+			Some((r#"<!-- <div id="main"> --><p id="main">"#, ""))
+		);
+		let index_html = r#"<script>let s = '<div id="main">';</SCRIPT><p id='main'>"#;
+		assert_eq!(start(index_html), Some((index_html, "")));
+		assert_eq!(start(r#"<style>/* <div id="main"> */</style>"#), None);
+		assert_eq!(start(r#"<div id="main"#), None);
+
+		let page = inject(r#"<div data-id="main"></div><div id='main'></div>"#, "<p>hi</p>", &Vec::new());
+		assert!(
+			page.starts_with(r#"<div data-id="main"></div><div id='main'><p>hi</p></div>"#),
+			"{}",
+			page
+		);
+	}
+
+	#[test]
+	fn test_take() {
+		// how `#[backend]` functions key their calls: the dispatch name, then references to the arguments
+		let key = |a: &str, b: &str| bincode::serialize(&("my_app::add", (&a, &b))).unwrap();
+		let prefetched = vec![(key("a", "b"), bincode::serialize("ab").unwrap())];
+		let page = inject(r#"<div id="main"></div>"#, "", &prefetched);
+		let start = page.find("octet-stream\">").unwrap() + "octet-stream\">".len();
+		let text = &page[start..page.rfind("</script>").unwrap()];
+
+		let mut prefetched = decode(text).unwrap();
+		let a = String::from("a");
+		assert_eq!(take_from(&mut prefetched, &("my_app::add", (&"b", &"a"))), None);
+		assert_eq!(take_from(&mut prefetched, &("my_app::other", (&a, &"b"))), None);
+		let result = take_from(&mut prefetched, &("my_app::add", (&a, &"b"))).unwrap();
+		assert_eq!(bincode::deserialize::<String>(&result).unwrap(), "ab");
+		// each result is used once
+		assert_eq!(take_from(&mut prefetched, &("my_app::add", (&a, &"b"))), None);
+		assert_eq!(decode("not base64"), None);
+	}
+}
@@ -0,0 +1,285 @@
+//! Per-call instrumentation around `RPC::execute`, shared by the WebSocket and UDP servers.
+
+use crate::{ConnectionInfo, RPC};
+
+/// Runs `target_func`, wrapped in a `tracing` span and/or recorded in metrics when those features are enabled.
+pub(crate) async fn execute(
+	target_func: Box<dyn RPC>,
+	request_len: usize,
+	sender: Box<dyn Fn(Vec<u8>) + Send>,
+	tripwire: Option<stream_cancel::Tripwire>,
+	connection_info: Option<ConnectionInfo>,
+) {
+	#[cfg(not(any(feature = "tracing", feature = "metrics")))]
+	{
+		let _ = request_len;
+		target_func.execute(sender, tripwire, connection_info).await;
+	}
+
+	#[cfg(any(feature = "tracing", feature = "metrics"))]
+	{
+		use futures_util::FutureExt as _;
+		use std::sync::{atomic::AtomicUsize, atomic::Ordering::Relaxed, Arc};
+
+		#[cfg(feature = "tracing")]
+		let span = {
+			let span = tracing::info_span!(
+				"rpc",
+				function = target_func.name(),
+				txid = target_func.txid(),
+				remote_addr = tracing::field::Empty,
+				param_bytes = request_len,
+				result_bytes = tracing::field::Empty,
+				items_sent = tracing::field::Empty,
+				duration_ms = tracing::field::Empty,
+				ended = tracing::field::Empty,
+				error = tracing::field::Empty,
+			);
+			if let Some(addr) = connection_info.as_ref().and_then(|i| i.remote_addr) {
+				span.record("remote_addr", tracing::field::display(addr));
+			}
+			span
+		};
+		#[cfg(not(feature = "tracing"))]
+		let _ = request_len;
+
+		#[cfg(feature = "metrics")]
+		let in_flight = crate::metrics::InFlight::start(target_func.name(), target_func.is_stream());
+
+		let result_bytes = Arc::new(AtomicUsize::new(0));
+		let items_sent = Arc::new(AtomicUsize::new(0));
+		let sender = {
+			let (result_bytes, items_sent) = (result_bytes.clone(), items_sent.clone());
+			Box::new(move |response: Vec<u8>| {
+				result_bytes.fetch_add(response.len(), Relaxed);
+				items_sent.fetch_add(1, Relaxed);
+				sender(response)
+			})
+		};
+
+		let cancelled = tripwire.clone();
+		let start = std::time::Instant::now();
+		let fut = target_func.execute(sender, tripwire, connection_info);
+		#[cfg(feature = "tracing")]
+		let fut = tracing::Instrument::instrument(fut, span.clone());
+		let panic = std::panic::AssertUnwindSafe(fut).catch_unwind().await.err();
+
+		let ended = match (&panic, cancelled.and_then(|t| t.now_or_never())) {
+			(Some(_), _) => "panicked",
+			(None, Some(true)) => "cancelled",
+			_ => "completed",
+		};
+
+		#[cfg(feature = "tracing")]
+		{
+			span.record("result_bytes", result_bytes.load(Relaxed));
+			span.record("items_sent", items_sent.load(Relaxed));
+			span.record("duration_ms", start.elapsed().as_secs_f64() * 1000.0);
+			span.record("ended", ended);
+			span.in_scope(|| tracing::debug!("rpc {}", ended));
+		}
+		#[cfg(not(feature = "tracing"))]
+		let _ = (start, result_bytes, items_sent);
+
+		#[cfg(feature = "metrics")]
+		in_flight.finish(ended);
+
+		if let Some(panic) = panic {
+			std::panic::resume_unwind(panic);
+		}
+	}
+}
+
+/// Called by `#[backend]` glue whenever a function returns (or a stream yields) an `Err`.
+pub fn rpc_error(function: &'static str, message: Option<String>) {
+	#[cfg(feature = "metrics")]
+	crate::metrics::record_error(function);
+	#[cfg(feature = "tracing")]
+	{
+		let message = message.as_deref().unwrap_or("<error does not implement Display>");
+		tracing::Span::current().record("error", message);
+		tracing::warn!(function, "rpc error: {}", message);
+	}
+	#[cfg(not(feature = "tracing"))]
+	let _ = (function, message);
+}
+
+/// Describes an error with its `Display` impl if it has one, via autoref specialization:
+/// `(&Describe(e)).describe()` resolves to [`DescribeDisplay`] when possible, else [`DescribeFallback`].
+pub struct Describe<'a, T>(pub &'a T);
+
+pub trait DescribeDisplay {
+	fn describe(&self) -> Option<String>;
+}
+
+impl<T: std::fmt::Display> DescribeDisplay for Describe<'_, T> {
+	fn describe(&self) -> Option<String> {
+		Some(self.0.to_string())
+	}
+}
+
+pub trait DescribeFallback {
+	fn describe(&self) -> Option<String>;
+}
+
+impl<T> DescribeFallback for &Describe<'_, T> {
+	fn describe(&self) -> Option<String> {
+		None
+	}
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+	use super::*;
+	use futures_util::FutureExt;
+	use std::{
+		collections::HashMap,
+		sync::{Arc, Mutex},
+	};
+	use tracing::{field, span};
+
+	/// The fields and metadata of each span, in the order the spans were created.
+	type Spans = Arc<Mutex<Vec<(HashMap<&'static str, String>, &'static tracing::Metadata<'static>)>>>;
+
+	/// A subscriber that records the fields of spans, and which spans are entered.
+	#[derive(Clone, Default)]
+	struct Recorder(Spans, Arc<Mutex<Vec<span::Id>>>);
+
+	struct Fields<'a>(&'a mut HashMap<&'static str, String>);
+
+	impl field::Visit for Fields<'_> {
+		fn record_str(&mut self, field: &field::Field, value: &str) {
+			self.0.insert(field.name(), value.to_string());
+		}
+
+		fn record_debug(&mut self, field: &field::Field, value: &dyn std::fmt::Debug) {
+			self.0.insert(field.name(), format!("{:?}", value));
+		}
+	}
+
+	impl tracing::Subscriber for Recorder {
+		fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+			true
+		}
+
+		fn new_span(&self, span: &span::Attributes<'_>) -> span::Id {
+			let mut spans = self.0.lock().unwrap();
+			let mut fields = HashMap::new();
+			span.record(&mut Fields(&mut fields));
+			spans.push((fields, span.metadata()));
+			span::Id::from_u64(spans.len() as u64)
+		}
+
+		fn record(&self, span: &span::Id, values: &span::Record<'_>) {
+			values.record(&mut Fields(&mut self.0.lock().unwrap()[span.into_u64() as usize - 1].0));
+		}
+
+		fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+		fn event(&self, _: &tracing::Event<'_>) {}
+
+		fn enter(&self, span: &span::Id) {
+			self.1.lock().unwrap().push(span.clone());
+		}
+
+		fn exit(&self, _: &span::Id) {
+			self.1.lock().unwrap().pop();
+		}
+
+		fn current_span(&self) -> tracing_core::span::Current {
+			let entered = self.1.lock().unwrap();
+			match entered.last() {
+				Some(span) => {
+					let metadata = self.0.lock().unwrap()[span.into_u64() as usize - 1].1;
+					tracing_core::span::Current::new(span.clone(), metadata)
+				}
+				None => tracing_core::span::Current::none(),
+			}
+		}
+	}
+
+	/// A call of `test_fn` that runs `body` with its sender.
+	struct TestCall(fn(&dyn Fn(Vec<u8>)));
+
+	#[async_trait::async_trait]
+	impl RPC for TestCall {
+		async fn execute(
+			&self,
+			sender: Box<dyn Fn(Vec<u8>) + Send>,
+			_tripwire: Option<stream_cancel::Tripwire>,
+			_connection_info: Option<ConnectionInfo>,
+		) {
+			(self.0)(&*sender)
+		}
+
+		fn txid(&self) -> i64 {
+			256
+		}
+
+		fn name(&self) -> &'static str {
+			"test_fn"
+		}
+
+		fn is_stream(&self) -> bool {
+			false
+		}
+	}
+
+	/// The fields of the span of a call running `body`, with a 10-byte request.
+	fn span_fields(
+		body: fn(&dyn Fn(Vec<u8>)),
+		tripwire: Option<stream_cancel::Tripwire>,
+	) -> HashMap<&'static str, String> {
+		let recorder = Recorder::default();
+		let connection_info = ConnectionInfo {
+			remote_addr: Some(([127, 0, 0, 1], 1234).into()),
+			user_agent: None,
+			state: Default::default(),
+			connection_local: Default::default(),
+			session: None,
+		};
+		tracing::subscriber::with_default(recorder.clone(), || {
+			let call =
+				execute(Box::new(TestCall(body)), 10, Box::new(|_| ()), tripwire, Some(connection_info));
+			let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| call.now_or_never()));
+			assert!(matches!(result, Ok(Some(())) | Err(_)));
+		});
+		let spans = recorder.0.lock().unwrap();
+		let (fields, _) = spans.iter().find(|(_, metadata)| metadata.name() == "rpc").unwrap();
+		fields.clone()
+	}
+
+	#[test]
+	fn test_span() {
+		let fields = span_fields(
+			|sender| {
+				sender(vec![0; 3]);
+				sender(vec![0; 4]);
+			},
+			None,
+		);
+		for (field, value) in [
+			("function", "test_fn"),
+			("txid", "256"),
+			("remote_addr", "127.0.0.1:1234"),
+			("param_bytes", "10"),
+			("result_bytes", "7"),
+			("items_sent", "2"),
+			("ended", "completed"),
+		] {
+			assert_eq!(fields.get(field).map(String::as_str), Some(value), "{}", field);
+		}
+		assert!(fields.get("duration_ms").unwrap().parse::<f64>().is_ok());
+		assert_eq!(fields.get("error"), None);
+
+		let fields = span_fields(|_| rpc_error("test_fn", Some("failed".into())), None);
+		assert_eq!(fields.get("error").map(String::as_str), Some("failed"));
+
+		let fields = span_fields(|_| panic!("test panic"), None);
+		assert_eq!(fields.get("ended").map(String::as_str), Some("panicked"));
+
+		let (trigger, tripwire) = stream_cancel::Tripwire::new();
+		trigger.cancel();
+		let fields = span_fields(|_| (), Some(tripwire));
+		assert_eq!(fields.get("ended").map(String::as_str), Some("cancelled"));
+	}
+}
@@ -0,0 +1,341 @@
+//! Yew hooks over `#[backend]` calls and streams.
+
+use crate::hooks::{next_items, Query, StreamStatus};
+use ::yew::{platform::spawn_local, prelude::*};
+use futures_util::{future::abortable, FutureExt, Stream, StreamExt};
+use std::{
+	cell::{Cell, Ref, RefCell},
+	convert::Infallible,
+	future::Future,
+	rc::Rc,
+};
+
+/// Calls a backend function with `call`, and again when `dependencies` change, on `refetch()`, or when a backend
+/// function whose `#[backend(invalidates = "...")]` names a function it called completes, tracking whether the call is
+/// in flight and its value or error. Starting the call again, or unmounting the component, drops the call in flight,
+/// which cancels it on the server.
+#[hook]
+pub fn use_backend<T, E, D, F, C>(dependencies: D, call: C) -> UseBackendHandle<T, E>
+where
+	T: 'static,
+	E: 'static,
+	D: PartialEq + Clone + 'static,
+	F: Future<Output = Result<T, E>> + 'static,
+	C: FnOnce(D) -> F + 'static,
+{
+	let (run, query) = use_query();
+	let update = use_force_update();
+	let handle = (*use_memo((), |_| UseBackendHandle {
+		query: query.clone(),
+		loading: Rc::new(Cell::new(true)),
+		result: Default::default(),
+	}))
+	.clone();
+
+	use_effect_with((dependencies, run), {
+		let handle = handle.clone();
+		move |(dependencies, _)| {
+			let future = query.start(|| call(dependencies.clone()));
+			if !handle.loading.replace(true) {
+				update.force_update();
+			}
+			let (future, abort) = abortable(query.tracked(future));
+			spawn_local(async move {
+				if let Ok(value) = future.await {
+					*handle.result.borrow_mut() = Some(value);
+					handle.loading.set(false);
+					update.force_update();
+				}
+			});
+			move || abort.abort()
+		}
+	});
+
+	handle
+}
+
+/// State of a backend call made by [`use_backend`].
+pub struct UseBackendHandle<T, E> {
+	query: Rc<Query>,
+	loading: Rc<Cell<bool>>,
+	result: Rc<RefCell<Option<Result<T, E>>>>,
+}
+
+impl<T, E> Clone for UseBackendHandle<T, E> {
+	fn clone(&self) -> Self {
+		Self { query: self.query.clone(), loading: self.loading.clone(), result: self.result.clone() }
+	}
+}
+
+impl<T, E> UseBackendHandle<T, E> {
+	/// Whether a call is in flight; the result of the previous one is kept until it completes.
+	pub fn loading(&self) -> bool {
+		self.loading.get()
+	}
+
+	/// The value returned by the latest call, if it succeeded.
+	pub fn value(&self) -> Option<Ref<'_, T>> {
+		Ref::filter_map(self.result.borrow(), |result| result.as_ref()?.as_ref().ok()).ok()
+	}
+
+	/// The error returned by the latest call, if it failed.
+	pub fn error(&self) -> Option<Ref<'_, E>> {
+		Ref::filter_map(self.result.borrow(), |result| result.as_ref()?.as_ref().err()).ok()
+	}
+
+	/// Calls the backend function again.
+	pub fn refetch(&self) {
+		self.query.rerun();
+	}
+}
+
+/// Runs the stream that `stream` returns, and again from fresh state when `dependencies` change, folding its items
+/// into the state with `callback`. Starting the stream again, or unmounting the component, drops the stream, which
+/// cancels it on the server.
+#[hook]
+pub fn use_stream<C, T, D, S, F, G>(dependencies: D, stream: F, callback: G) -> UseStreamHandle<C>
+where
+	C: Default + 'static,
+	T: 'static,
+	D: PartialEq + Clone + 'static,
+	S: Stream<Item = T> + 'static,
+	F: FnOnce(D) -> S + 'static,
+	G: Fn(&mut C, T) + 'static,
+{
+	use_try_stream(dependencies, move |dependencies| stream(dependencies).map(Ok), callback)
+}
+
+/// Like [`use_stream`], for streams of `Result`s: `callback` gets the `Ok` items, and [`UseStreamHandle::error`] is
+/// the latest error until the next `Ok` item.
+#[hook]
+pub fn use_try_stream<C, T, E, D, S, F, G>(
+	dependencies: D,
+	stream: F,
+	callback: G,
+) -> UseStreamHandle<C, E>
+where
+	C: Default + 'static,
+	T: 'static,
+	E: 'static,
+	D: PartialEq + Clone + 'static,
+	S: Stream<Item = Result<T, E>> + 'static,
+	F: FnOnce(D) -> S + 'static,
+	G: Fn(&mut C, T) + 'static,
+{
+	let (run, query) = use_query();
+	let update = use_force_update();
+	let handle = (*use_memo((), |_| UseStreamHandle {
+		status: Rc::new(Cell::new(StreamStatus::Connecting)),
+		value: Default::default(),
+		error: Default::default(),
+	}))
+	.clone();
+
+	use_effect_with((dependencies, run), {
+		let UseStreamHandle { status, value, error } = handle.clone();
+		move |(dependencies, _)| {
+			let stream = query.start(|| stream(dependencies.clone()));
+			if status.replace(StreamStatus::Connecting) != StreamStatus::Connecting {
+				*value.borrow_mut() = C::default();
+				*error.borrow_mut() = None;
+				update.force_update();
+			}
+
+			let (task, abort) = abortable(query.tracked(async move {
+				let handle = |item| match item {
+					Ok(item) => {
+						callback(&mut value.borrow_mut(), item);
+						*error.borrow_mut() = None;
+					}
+					Err(e) => *error.borrow_mut() = Some(e),
+				};
+				let stream = stream.fuse();
+				futures_util::pin_mut!(stream);
+				while let Some(items) = next_items(&mut stream).await {
+					items.into_iter().for_each(&handle);
+					status.set(StreamStatus::Open);
+					update.force_update();
+				}
+				status.set(StreamStatus::Ended);
+				update.force_update();
+			}));
+			spawn_local(task.map(drop));
+			move || abort.abort()
+		}
+	});
+
+	handle
+}
+
+/// State of a stream run by [`use_stream`] or [`use_try_stream`].
+pub struct UseStreamHandle<C, E = Infallible> {
+	status: Rc<Cell<StreamStatus>>,
+	value: Rc<RefCell<C>>,
+	error: Rc<RefCell<Option<E>>>,
+}
+
+impl<C, E> Clone for UseStreamHandle<C, E> {
+	fn clone(&self) -> Self {
+		Self { status: self.status.clone(), value: self.value.clone(), error: self.error.clone() }
+	}
+}
+
+impl<C, E> UseStreamHandle<C, E> {
+	/// The state that `callback` has folded the items into.
+	pub fn read(&self) -> Ref<'_, C> {
+		self.value.borrow()
+	}
+
+	/// Whether the stream has items yet, or has ended.
+	pub fn status(&self) -> StreamStatus {
+		self.status.get()
+	}
+
+	/// The latest error, until the next `Ok` item.
+	pub fn error(&self) -> Option<Ref<'_, E>> {
+		Ref::filter_map(self.error.borrow(), Option::as_ref).ok()
+	}
+}
+
+/// Makes backend calls with `mutate` when [`UseMutationHandle::mutate`] is called, e.g. from a callback, one at a
+/// time in order, tracking whether any are in flight and the value or error of the latest one. Completed calls of
+/// functions with `#[backend(invalidates = "...")]` make the [`use_backend`] and [`use_stream`] hooks that called the
+/// named functions run again. The `mutate` of the first render is used.
+#[hook]
+pub fn use_mutation<A, T, E, F, M>(mutate: M) -> UseMutationHandle<A, T, E>
+where
+	A: 'static,
+	T: 'static,
+	E: 'static,
+	F: Future<Output = Result<T, E>> + 'static,
+	M: Fn(A) -> F + 'static,
+{
+	let update = use_force_update();
+	let state = use_memo((), |_| {
+		let (args_tx, args_rx) = futures_channel::mpsc::unbounded();
+		let handle = UseMutationHandle {
+			update: update.clone(),
+			args_tx,
+			pending: Default::default(),
+			result: Default::default(),
+		};
+		(handle, RefCell::new(Some(args_rx)))
+	});
+
+	use_effect_with((), {
+		let state = state.clone();
+		move |_| {
+			let (UseMutationHandle { pending, result, .. }, args_rx) = &*state;
+			let (pending, result, mut args_rx) =
+				(pending.clone(), result.clone(), args_rx.borrow_mut().take().unwrap());
+			let (task, abort) = abortable(async move {
+				while let Some(args) = args_rx.next().await {
+					let value = mutate(args).await;
+					*result.borrow_mut() = Some(value);
+					pending.set(pending.get() - 1);
+					update.force_update();
+				}
+			});
+			spawn_local(task.map(drop));
+			move || abort.abort()
+		}
+	});
+
+	state.0.clone()
+}
+
+/// State of backend calls made by [`use_mutation`].
+pub struct UseMutationHandle<A, T, E> {
+	update: UseForceUpdateHandle,
+	args_tx: futures_channel::mpsc::UnboundedSender<A>,
+	pending: Rc<Cell<usize>>,
+	result: Rc<RefCell<Option<Result<T, E>>>>,
+}
+
+impl<A, T, E> Clone for UseMutationHandle<A, T, E> {
+	fn clone(&self) -> Self {
+		Self {
+			update: self.update.clone(),
+			args_tx: self.args_tx.clone(),
+			pending: self.pending.clone(),
+			result: self.result.clone(),
+		}
+	}
+}
+
+impl<A, T, E> UseMutationHandle<A, T, E> {
+	/// Makes a call with `args`, after the calls in flight.
+	pub fn mutate(&self, args: A) {
+		self.pending.set(self.pending.get() + 1);
+		self.args_tx.unbounded_send(args).ok();
+		self.update.force_update();
+	}
+
+	/// Whether any calls are in flight.
+	pub fn loading(&self) -> bool {
+		self.pending.get() > 0
+	}
+
+	/// The value returned by the latest call, if it succeeded.
+	pub fn value(&self) -> Option<Ref<'_, T>> {
+		Ref::filter_map(self.result.borrow(), |result| result.as_ref()?.as_ref().ok()).ok()
+	}
+
+	/// The error returned by the latest call, if it failed.
+	pub fn error(&self) -> Option<Ref<'_, E>> {
+		Ref::filter_map(self.result.borrow(), |result| result.as_ref()?.as_ref().err()).ok()
+	}
+}
+
+/// The hook's number of runs, which its effect depends on, and a query that runs it again by adding one and rendering.
+#[hook]
+fn use_query() -> (usize, Rc<Query>) {
+	let update = use_force_update();
+	let runs = (*use_memo((), |_| Rc::new(Cell::new(0)))).clone();
+	let query = (*use_memo((), |_| {
+		let runs = runs.clone();
+		Query::new(move || {
+			runs.set(runs.get() + 1);
+			update.force_update();
+		})
+	}))
+	.clone();
+	(runs.get(), query)
+}
+
+#[allow(dead_code)]
+#[function_component]
+fn TestCompileHooks() -> Html {
+	fn make_stream(id: i64) -> impl Stream<Item = Vec<u8>> {
+		let _ = id;
+		futures_channel::mpsc::unbounded().1
+	}
+	fn make_try_stream(id: i64) -> impl Stream<Item = Result<i64, String>> {
+		futures_util::stream::iter([Ok(id), Err(id.to_string())])
+	}
+	async fn get_name(id: i64) -> Result<String, String> {
+		Ok(id.to_string())
+	}
+	async fn rename(id: i64, name: String) -> Result<(), String> {
+		let _ = (id, name);
+		Ok(())
+	}
+
+	let id = 1;
+	let name = use_backend(id, get_name);
+	let _: (bool, Option<String>, Option<String>) =
+		(name.loading(), name.value().map(|v| v.clone()), name.error().map(|e| e.clone()));
+	name.refetch();
+
+	let latest = use_stream(id, make_stream, |s, v| *s = Some(v));
+	let _: (Option<Vec<u8>>, StreamStatus) = (latest.read().clone(), latest.status());
+	let count = use_try_stream(id, make_try_stream, |s: &mut usize, _| *s += 1);
+	let _: (usize, Option<String>) = (*count.read(), count.error().map(|e| e.clone()));
+
+	let renaming = use_mutation(|(id, name)| rename(id, name));
+	renaming.mutate((id, "Bob".to_string()));
+	let _: (bool, Option<()>, Option<String>) =
+		(renaming.loading(), renaming.value().map(|v| *v), renaming.error().map(|e| e.clone()));
+
+	html! {}
+}